@@ -3,78 +3,385 @@
 // (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
 //
 // MIT/APACHE licensed -- check LICENSE files in top dir
+extern crate blake3;
 extern crate chrono;
 extern crate clap;
+extern crate ctrlc;
 extern crate crates_index;
 extern crate flate2;
 extern crate futures;
+#[cfg(unix)]
+extern crate libc;
+extern crate num_cpus;
+extern crate regex;
 extern crate reqwest;
+extern crate rustc_demangle;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
+extern crate similar;
 extern crate tar;
+extern crate tiny_http;
 extern crate tokio_core;
+extern crate toml;
+extern crate wait_timeout;
 #[macro_use]
 extern crate lazy_static;
 extern crate glob;
 extern crate ini;
+extern crate rand;
 extern crate rayon;
+extern crate semver;
+
+/// `println!` that `--quiet` silences, for per-crate progress messages.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !::summary::quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// `println!` for the other messages of a phase, which go to stderr with
+/// `--json-lines` so that stdout only carries JSON.
+macro_rules! message {
+    ($($arg:tt)*) => {
+        if ::summary::json_lines() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+mod analyze;
+mod api;
+mod categories;
+mod checkpoint;
+mod checksum;
+mod children;
+mod closure;
+mod config;
+mod crate_sizes;
+mod dataset;
+mod diagnostics;
+mod disk;
+mod downloads;
+mod features;
+mod fetch;
+mod graph;
+mod graph_state;
+mod hook;
+mod index;
+mod locations;
+mod lock;
+mod manifest;
+mod merge;
+mod metrics;
+#[cfg(test)]
+mod mock;
+mod msrv;
+mod nice;
+mod owners;
+mod paths;
+mod policy;
+mod provenance;
+mod report;
+mod resolve;
+mod rusage;
+mod run_record;
+mod selection;
+mod selftest;
+mod sink;
+mod source;
+mod source_size;
+mod sources;
+mod stdio;
+mod stop;
+mod summary;
+mod swhid;
+mod targets;
+mod throttle;
+mod topo;
+mod unbuildable;
+mod unused_deps;
 
 use chrono::Utc;
-use clap::{App, SubCommand};
-use crates_index::Index;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use crates_index::{Crate, Index, Version};
 use flate2::read::GzDecoder;
-use futures::{stream, Future, Stream};
-use glob::glob;
-use ini::Ini;
+use futures::{future, stream, Future, Stream};
+use glob::{glob, Pattern};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
 use rayon::prelude::*;
-use reqwest::r#async::{Client, Decoder};
-use tar::Archive;
+use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use tar::{Archive, EntryType};
+use tokio_core::reactor::Timeout;
+
+use categories::Categories;
+use checksum::Algo;
+use config::Config;
+use features::{FeatureBuild, FeatureSet};
+use fetch::Fetched;
+use graph::{BinaryGraph, CallGraph, CallGraphDiff};
+use graph_state::GraphState;
+use hook::{Hook, Sandbox};
+use index::DepKinds;
+use metrics::{InFlight, METRICS};
+use policy::Policy;
+use provenance::{ArchiveFile, Provenance};
+use report::{Entry, Envelope, Status};
+use resolve::ResolvedGraph;
+use selection::{CrateList, Expand};
+use source::SOURCE;
+use targets::Targets;
 
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
-use std::path::Path;
+use std::io::{BufReader, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Component, Path};
 use std::path::PathBuf;
-use std::process::Command;
-
-static CRATES_ROOT: &str = "https://crates-io.s3-us-west-1.amazonaws.com/crates";
+use std::path::MAIN_SEPARATOR;
+use std::process::{Command, Output};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
 
 lazy_static! {
-    static ref CONFIG: Ini = {
+    static ref CONFIG: Config = {
         let dir = env!("CARGO_MANIFEST_DIR");
-        let conf = Ini::load_from_file(format!("{0}/{1}", dir, "conf.ini")).unwrap();
-        conf
+        Config::load(Path::new(dir)).unwrap_or_else(|error| panic!("Error reading the configuration: {}", error))
     };
-    static ref PRAZI_DIR: String = {
-        CONFIG
-            .section(Some("storage"))
-            .unwrap()
-            .get("path")
-            .unwrap()
-            .to_string()
+    /// `[storage] path`; the tests keep their crates and reports in a temp
+    /// folder of their own.
+    static ref PRAZI_DIR: String = if cfg!(test) {
+        std::env::temp_dir().join(format!("prazi-test-{}", std::process::id())).display().to_string()
+    } else {
+        config_value("storage", "path").expect("no [storage] path configured")
     };
+    /// Scratch space on a fast disk for unpacking and builds, from `[storage]
+    /// tmp_path`; an empty value selects the system temp dir.
+    static ref TMP_DIR: Option<PathBuf> = config_value("storage", "tmp_path").map(|path| {
+        if path.is_empty() {
+            return std::env::temp_dir().join("prazi");
+        }
+        let path = PathBuf::from(path);
+        match fs::create_dir_all(&path) {
+            Ok(()) => path,
+            Err(error) => {
+                let fallback = std::env::temp_dir().join("prazi");
+                eprintln!("Error creating [storage] tmp_path {:?}, using {:?}: {}", path, fallback, error);
+                fallback
+            }
+        }
+    });
+    /// Namespace of the outputs of this run, `[storage] run_label` or `--label`.
+    static ref RUN_LABEL: RwLock<Option<String>> =
+        RwLock::new(config_value("storage", "run_label").filter(|label| !label.is_empty()));
+    /// Entries a crate archive may have, `[download] max_files_per_crate`.
+    static ref MAX_FILES_PER_CRATE: u64 = config_value("download", "max_files_per_crate")
+        .map_or(100_000, |v| v.parse().expect("invalid [download] max_files_per_crate"));
+    /// Bytes a crate archive may extract to, `[download] max_uncompressed_bytes`.
+    static ref MAX_UNCOMPRESSED_BYTES: u64 = config_value("download", "max_uncompressed_bytes")
+        .map_or(1 << 30, |v| source_size::parse_size(&v).expect("invalid [download] max_uncompressed_bytes"));
+    /// Peak memory of a build above which it is flagged, `[build] max_rss`.
+    static ref MAX_RSS: Option<u64> = config_value("build", "max_rss")
+        .map(|v| source_size::parse_size(&v).expect("invalid [build] max_rss"));
+}
+
+/// Override `[storage] run_label` with `--label`.
+fn set_run_label(label: &str) {
+    let valid = !label.is_empty()
+        && label != "."
+        && label != ".."
+        && !label.contains('/')
+        && !label.contains(MAIN_SEPARATOR);
+    assert!(valid, "--label must be a plain folder name, got {:?}", label);
+    *RUN_LABEL.write().unwrap() = Some(label.to_string());
+}
+
+/// Folder of the outputs of this run below `base`: `base` itself or, with a
+/// run label, `base/runs/{label}`, so runs with different labels do not
+/// overwrite each other's builds, graphs and reports.
+fn run_dir(base: &Path) -> PathBuf {
+    match *RUN_LABEL.read().unwrap() {
+        Some(ref label) => base.join("runs").join(label),
+        None => base.to_path_buf(),
+    }
+}
+
+/// An empty folder for the test `name`, below the `PRAZI_DIR` of the tests.
+#[cfg(test)]
+fn test_dir(name: &str) -> PathBuf {
+    let dir = Path::new(&**PRAZI_DIR).join("tests").join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    fs::create_dir_all(&dir).unwrap();
+    dir
 }
 
-/// Get directory for crates.io index.
+/// Get directory for crates.io index, or for the index of the `--registry`.
 fn config_index_dir() -> String {
-    CONFIG
-        .section(Some("crates"))
-        .unwrap()
-        .get("index_path")
-        .unwrap()
-        .to_string()
+    if let Some(index_dir) = source::local_index_dir() {
+        return index_dir;
+    }
+    let index_path = config_value("crates", "index_path").expect("no [crates] index_path configured");
+    match source::registry() {
+        Some(name) => source::registry_index_dir(&name, &index_path),
+        None => index_path,
+    }
+}
+
+/// Look up an optional configuration value.
+fn config_value(section: &str, key: &str) -> Option<String> {
+    CONFIG.value(section, key).map(|v| v.to_string())
+}
+
+/// The cargo profile crates are built with, `debug` unless configured otherwise.
+fn config_profile() -> Profile {
+    config_value("compiler", "profile")
+        .map(|v| v.parse().expect("invalid [compiler] profile"))
+        .unwrap_or(Profile::Debug)
+}
+
+/// Shared `CARGO_HOME` for all cargo invocations, if configured.
+fn config_cargo_home() -> Option<PathBuf> {
+    config_value("build", "cargo_home").map(PathBuf::from)
+}
+
+/// Environment variables from the `[build.env]` section.
+fn config_build_env() -> BTreeMap<String, String> {
+    CONFIG.section("build.env").cloned().unwrap_or_default()
+}
+
+/// All values of the configuration by section, `""` for the keys outside of
+/// any.
+fn config_all() -> BTreeMap<String, BTreeMap<String, String>> {
+    CONFIG.sections().clone()
 }
 
 /// Do we need all crate versions or only the latest ones?
 fn config_latest_only() -> bool {
-    let value = CONFIG
-        .section(Some("crates"))
-        .unwrap()
-        .get("latest_only")
-        .unwrap();
+    let value = config_value("crates", "latest_only").expect("no [crates] latest_only configured");
     value == "true"
 }
 
-fn path_exists(path: &str) -> bool {
-    Path::new(path).exists()
+fn path_exists<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().exists()
+}
+
+/// Root folder of all extracted registry crates, of crates.io unless a
+/// `--registry` is selected.
+fn registry_dir() -> PathBuf {
+    let crates = Path::new(&**PRAZI_DIR).join("crates");
+    match source::registry() {
+        Some(name) => crates.join(format!("reg-{}", name)),
+        None => crates.join("reg"),
+    }
+}
+
+/// Path to the LLVM `opt` binary, with the platform specific executable suffix.
+fn opt_binary(llvm_path: &str) -> PathBuf {
+    Path::new(llvm_path)
+        .join("bin")
+        .join(format!("opt{}", std::env::consts::EXE_SUFFIX))
+}
+
+/// Path to the LLVM `llvm-link` binary, next to `opt`.
+fn llvm_link_binary(llvm_path: &str) -> PathBuf {
+    Path::new(llvm_path)
+        .join("bin")
+        .join(format!("llvm-link{}", std::env::consts::EXE_SUFFIX))
+}
+
+/// Path to the LLVM `llvm-dis` binary, next to `opt`.
+fn llvm_dis_binary(llvm_path: &str) -> PathBuf {
+    Path::new(llvm_path)
+        .join("bin")
+        .join(format!("llvm-dis{}", std::env::consts::EXE_SUFFIX))
+}
+
+/// The textual IR of `bitcode`.
+fn llvm_dis(llvm_path: &str, bitcode: &Path) -> PraziResult<String> {
+    let output = children::output(
+        "llvm-dis",
+        Command::new(llvm_dis_binary(llvm_path))
+            .arg(bitcode)
+            .arg("-o")
+            .arg("-"),
+    )?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Link `bitcodes`, e.g. the codegen units of a crate, into the single
+/// module `out`.
+fn llvm_link(llvm_path: &str, bitcodes: &[PathBuf], out: &Path) -> PraziResult<()> {
+    fs::create_dir_all(out.parent().unwrap())?;
+    let output = children::output(
+        "llvm-link",
+        Command::new(llvm_link_binary(llvm_path))
+            .args(bitcodes)
+            .arg("-o")
+            .arg(out),
+    )?;
+    if !output.status.success() {
+        if out.exists() {
+            fs::remove_file(out)?;
+        }
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+    }
+    Ok(())
+}
+
+/// Build a glob pattern matching `file_pattern` inside `dir`.
+///
+/// The directory part is escaped so that special characters in it are
+/// matched literally, and the native separator is used for joining.
+fn glob_pattern(dir: &Path, file_pattern: &str) -> String {
+    let escaped = Pattern::escape(&dir.to_string_lossy());
+    format!("{}{}{}", escaped, MAIN_SEPARATOR, file_pattern)
+}
+
+/// The cargo profile used for building crates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Debug,
+    Release,
+}
+
+impl Profile {
+    /// Name of the profile's folder under `target/`.
+    pub fn target_dir(self) -> &'static str {
+        match self {
+            Profile::Debug => "debug",
+            Profile::Release => "release",
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Profile, String> {
+        match s {
+            "debug" => Ok(Profile::Debug),
+            "release" => Ok(Profile::Release),
+            _ => Err(format!("unknown profile: {}", s)),
+        }
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Clone)]
@@ -85,290 +392,5071 @@ pub struct PraziCrate {
 
 impl PraziCrate {
     pub fn url_src(&self) -> String {
-        format!(
-            "{0}/{1}/{1}-{2}.crate",
-            CRATES_ROOT, self.name, self.version
-        )
+        SOURCE.crate_url(&self.name, &self.version)
     }
 
-    pub fn dir(&self) -> String {
-        format!(
-            "{0}/crates/reg/{1}/{2}",
-            &**PRAZI_DIR, self.name, self.version
-        )
+    pub fn dir(&self) -> PathBuf {
+        paths::crate_dir(&registry_dir(), &self.name, &self.version)
+    }
+
+    pub fn dir_src(&self) -> PathBuf {
+        paths::crate_dir_src(&registry_dir(), &self.name)
+    }
+
+    /// Scratch directory the crate tarball is extracted into before it is
+    /// moved to `dir`, in `[storage] tmp_path` when configured.
+    pub fn dir_tmp(&self) -> PathBuf {
+        match *TMP_DIR {
+            Some(ref tmp) => tmp.join("unpack").join(self.archive_root()),
+            None => self.dir_src().join(paths::unpack_dir_name(&self.version)),
+        }
+    }
+
+    /// Target directory of builds in `[storage] tmp_path` with `[storage]
+    /// tmp_builds=true`, moved to `dir()/target` after the build.
+    pub fn build_tmp(&self) -> Option<PathBuf> {
+        match *TMP_DIR {
+            Some(ref tmp) if config_value("storage", "tmp_builds").map_or(false, |v| v == "true") => {
+                Some(run_dir(&tmp.join("target").join(self.archive_root())))
+            }
+            _ => None,
+        }
+    }
+
+    /// Scratch copy of the sources `--out-of-tree-build` builds in, in
+    /// `[storage] tmp_path` when configured.
+    pub fn build_copy(&self) -> PathBuf {
+        match *TMP_DIR {
+            Some(ref tmp) => run_dir(&tmp.join("build").join(self.archive_root())),
+            None => run_dir(&self.dir_src().join(paths::build_dir_name(&self.version))),
+        }
     }
 
-    pub fn dir_src(&self) -> String {
-        format!("{0}/crates/reg/{1}", &**PRAZI_DIR, self.name)
+    /// The top-level directory of the crate tarball, `{name}-{version}`.
+    pub fn archive_root(&self) -> String {
+        paths::archive_root(&self.name, &self.version)
     }
 
     /// The file that marks that the crate was successfully downloaded and extracted.
-    pub fn success_file(&self) -> String {
-        format!(
-            "{0}/crates/reg/{1}/{2}_success",
-            &**PRAZI_DIR, self.name, self.version
-        )
+    pub fn success_file(&self) -> PathBuf {
+        self.dir_src().join(paths::success_file_name(&self.version))
+    }
+
+    /// The call graph of the crate at the location `create_prazi_graph.sh`
+    /// reads it from, a copy of its only graph in `graphs_dir` when the crate
+    /// has a single bitcode file.
+    pub fn callgraph_file(&self) -> PathBuf {
+        self.run_dir().join("callgraph.dot")
+    }
+
+    /// Folder of the build outputs of the crate, `runs/{label}` with a run
+    /// label; the sources are shared by all runs.
+    pub fn run_dir(&self) -> PathBuf {
+        run_dir(&self.dir())
+    }
+
+    /// Folder holding the call graphs of the crate.
+    pub fn graphs_dir(&self) -> PathBuf {
+        self.run_dir().join("graphs")
+    }
+
+    /// The call graph built from `bitcode` of a `features` build, in
+    /// `graphs/{features}/` unless these are the default features.
+    pub fn feature_graph_file(&self, bitcode: &Path, features: FeatureSet) -> PathBuf {
+        let stem = bitcode.file_stem().unwrap().to_string_lossy();
+        self.feature_graphs_dir(features)
+            .join(format!("{}.callgraph.dot", stem))
+    }
+
+    /// Folder of the graphs of a `features` build.
+    fn feature_graphs_dir(&self, features: FeatureSet) -> PathBuf {
+        match features {
+            FeatureSet::Default => self.graphs_dir(),
+            _ => self.graphs_dir().join(features.name()),
+        }
+    }
+
+    /// The module `llvm-link` links the bitcode files of a `features` build
+    /// into, `{name}-{version}.linked.bc` next to its graphs while the graph
+    /// is built.
+    pub fn linked_bitcode_file(&self, features: FeatureSet) -> PathBuf {
+        self.feature_graphs_dir(features)
+            .join(format!("{}.linked.bc", self.archive_root()))
+    }
+
+    /// The call graphs of `bitcodes` of a `features` build: the single graph
+    /// of their linked module when they are several and `link` is set, else
+    /// one per bitcode file.
+    pub fn feature_graph_files(&self, bitcodes: &[PathBuf], features: FeatureSet, link: bool) -> Vec<PathBuf> {
+        if link && bitcodes.len() > 1 {
+            vec![self.feature_graph_file(&self.linked_bitcode_file(features), features)]
+        } else {
+            bitcodes.iter().map(|bc| self.feature_graph_file(bc, features)).collect()
+        }
+    }
+
+    /// The edges of `graph` that `--resolve-indirect` added, one tab
+    /// separated `caller callee` line per edge.
+    pub fn indirect_edges_file(&self, graph: &Path) -> PathBuf {
+        graph.with_extension("indirect.tsv")
+    }
+
+    /// The source locations of the functions of `graph`, with
+    /// `--with-debuginfo`.
+    pub fn locations_file(&self, graph: &Path) -> PathBuf {
+        graph.with_extension("locations.json")
+    }
+
+    /// Hashes of the bitcode files the graphs were built from, see
+    /// `GraphState`.
+    pub fn graph_state_file(&self) -> PathBuf {
+        self.graphs_dir().join("state.json")
+    }
+
+    /// Cargo target directory of a `features` build, `target/features/{features}`
+    /// unless these are the default features.
+    pub fn target_dir(&self, features: FeatureSet) -> PathBuf {
+        let target = self.run_dir().join("target");
+        match features {
+            FeatureSet::Default => target,
+            _ => target.join("features").join(features.name()),
+        }
+    }
+
+    /// Folder the MIR files of the crate are collected into.
+    pub fn mir_dir(&self) -> PathBuf {
+        self.run_dir().join("mir")
+    }
+
+    /// The dependency graph `resolve` recorded.
+    pub fn resolved_file(&self) -> PathBuf {
+        self.run_dir().join("resolved.json")
+    }
+
+    /// Written after a successful build, holding the `rustc --version` it
+    /// was built with.
+    pub fn build_marker(&self) -> PathBuf {
+        self.run_dir().join("prazi-build.ok")
+    }
+
+    /// The whole output of the latest build with `--build-log`.
+    pub fn build_log(&self) -> PathBuf {
+        self.run_dir().join("build.log")
+    }
+
+    /// The output of `opt` of ad-hoc runs, only read by `scan-logs`.
+    pub fn callgraph_log(&self) -> PathBuf {
+        self.run_dir().join("callgraph.log")
+    }
+
+    /// The `.crate` file while it is being downloaded.
+    pub fn partial_file(&self) -> PathBuf {
+        self.dir_src().join(paths::partial_file_name(&self.version))
+    }
+
+    /// The downloaded `.crate` file, kept with `--keep-archive`.
+    pub fn archive_file(&self) -> PathBuf {
+        self.dir_src().join(paths::archive_file_name(&self.version))
+    }
+
+    /// Where the crate was downloaded from and how, see `Provenance`.
+    pub fn provenance_file(&self) -> PathBuf {
+        self.dir_src().join(paths::provenance_file_name(&self.version))
+    }
+
+    fn bitcode_pattern(&self, profile: Profile, features: FeatureSet) -> String {
+        self.deps_pattern(profile, features, "*.bc")
+    }
+
+    /// `file_pattern` in the `deps` folder of a `features` build.
+    fn deps_pattern(&self, profile: Profile, features: FeatureSet, file_pattern: &str) -> String {
+        let deps_dir = self
+            .target_dir(features)
+            .join(profile.target_dir())
+            .join("deps");
+        glob_pattern(&deps_dir, file_pattern)
     }
 
-    pub fn has_bitcode(&self) -> bool {
-        let res = glob(format!("{}/target/debug/deps/*.bc", self.dir()).as_str())
+    pub fn has_bitcode(&self, profile: Profile) -> bool {
+        !self.bitcode_paths(profile).is_empty()
+    }
+
+    /// All bitcode files of the crate, sorted.
+    pub fn bitcode_paths(&self, profile: Profile) -> Vec<PathBuf> {
+        self.feature_bitcode_paths(profile, FeatureSet::Default)
+    }
+
+    /// All bitcode files of a `features` build of the crate, sorted.
+    pub fn feature_bitcode_paths(&self, profile: Profile, features: FeatureSet) -> Vec<PathBuf> {
+        let mut res = glob(self.bitcode_pattern(profile, features).as_str())
             .expect("Failed to read glob pattern")
-            .map(|v| v.is_ok())
+            .filter_map(|v| v.ok())
+            .filter(|path| targets::selected().has_bitcode(path))
             .collect::<Vec<_>>();
-        res.len() == 1
+        res.sort();
+        res
     }
 
-    pub fn bitcode_path(&self) -> PathBuf {
-        let res = glob(format!("{}/target/debug/deps/*.bc", self.dir()).as_str())
+    /// All MIR files of a build with `--emit mir`, sorted.
+    pub fn mir_paths(&self, profile: Profile) -> Vec<PathBuf> {
+        let pattern = self.deps_pattern(profile, FeatureSet::Default, "*.mir");
+        let mut res = glob(pattern.as_str())
             .expect("Failed to read glob pattern")
-            .filter(|v| v.is_ok())
-            .map(|v| v.unwrap())
+            .filter_map(|v| v.ok())
             .collect::<Vec<_>>();
-        res[0].to_path_buf()
+        res.sort();
+        res
+    }
+
+    /// Have the call graphs of all bitcode files been built, see
+    /// `feature_graph_files`?
+    pub fn has_callgraphs(&self, profile: Profile, link: bool) -> bool {
+        let bitcodes = self.bitcode_paths(profile);
+        !bitcodes.is_empty()
+            && self
+                .feature_graph_files(&bitcodes, FeatureSet::Default, link)
+                .iter()
+                .all(|graph| graph::stored(graph).exists())
     }
 }
 
-pub(crate) struct Registry {
-    pub list: Vec<PraziCrate>,
+/// The line counts of all crates of `analyze-sources`.
+#[derive(Debug, Default, Serialize)]
+struct SourcesSummary {
+    crates: usize,
+    #[serde(flatten)]
+    stats: sources::SourceStats,
 }
 
-type PraziResult<T> = std::result::Result<T, Box<std::error::Error>>;
+/// The failed entry of `phase` of `krate` when its `log` reports an error,
+/// along with the log.
+fn scanned_failure(krate: &PraziCrate, phase: &str, log: &Path) -> Option<(Entry, String)> {
+    let text = fs::read_to_string(log).ok()?;
+    let failed = text.lines().any(|line| {
+        let line = line.trim_start();
+        line.starts_with("error") || line.contains("error:")
+    });
+    if !failed {
+        return None;
+    }
+    let entry = Entry::new(krate, phase, Status::Failed)
+        .with("log", log)
+        .with("stderr", report::tail(&text));
+    Some((entry, text))
+}
 
-const N: usize = 5;
+/// `field` quoted as CSV (RFC 4180) when it needs to be.
+fn csv_field(field: &str) -> String {
+    if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
 
-impl Registry {
-    fn read(&mut self) {
-        let index = Index::new(config_index_dir());
-        index.retrieve_or_update().expect("could not retrieve crates.io index");
-        for krate in index.crates() {
-            if config_latest_only() {
-                self.list.push(PraziCrate {
-                    name: krate.name().to_string(),
-                    version: krate.latest_version().version().to_string(),
-                });
-            } else {
-                for version in krate.versions().iter().rev() {
-                    //we also consider yanked versions
-                    self.list.push(PraziCrate {
-                        name: krate.name().to_string(),
-                        version: version.version().to_string(),
-                    });
-                }
-            }
+/// Why the call graph at `path` cannot be trusted, if it cannot.
+fn verify_graph(path: &Path) -> Result<(), String> {
+    let text = match graph::read_text(path) {
+        Ok(text) => text,
+        Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Err("missing".to_string())
         }
+        Err(error) => return Err(format!("unreadable: {}", error)),
+    };
+    if text.trim().is_empty() {
+        return Err("empty".to_string());
     }
+    if !text.trim_end().ends_with('}') {
+        return Err("truncated".to_string());
+    }
+    if CallGraph::parse(&text).nodes.is_empty() {
+        return Err("no nodes".to_string());
+    }
+    Ok(())
+}
 
-    fn download_src(&self) -> PraziResult<()> {
-        let mut core = tokio_core::reactor::Core::new()?;
-        let client = Client::new();
-        let responses = stream::iter_ok(self.list.iter().cloned())
-            .map(|krate| {
-                let success_file = krate.success_file();
-                if !path_exists(&success_file) {
-                    Some(client
-                        .get(&krate.url_src())
-                        .send()
-                        .and_then(|mut res| {
-                            std::mem::replace(res.body_mut(), Decoder::empty()).concat2()
-                        })
-                        .map(move |body| {
-                            let mut archive = Archive::new(GzDecoder::new(body.as_ref()));
-                            let tar_dir = krate.dir_src();
-                            let dst_dir = krate.dir();
-                            if path_exists(&tar_dir) {
-                                if let Err(error) = fs::remove_dir_all(&tar_dir) {
-                                    eprintln!("Error deleting: {} {:?}", tar_dir, error);
-                                    return;
-                                }
-                            }
-                            if let Err(error) = archive.unpack(&tar_dir) {
-                                eprintln!("Error unpacking: {} {:?}", tar_dir, error);
-                                return;
-                            }
-                            if let Err(error) = fs::rename(
-                                format!("/{0}/{1}-{2}", &tar_dir, krate.name, krate.version),
-                                &dst_dir,
-                            ) {
-                                eprintln!("Error renaming: {} {:?}", dst_dir, error);
-                                return;
-                            }
-                            println!("Untared: {:?}", &krate.url_src());
-                            let timestamp = Utc::now();
-                            fs::write(
-                                &success_file,
-                                format!("{}", timestamp.format("%Y-%m-%d %H:%M:%S"))
-                            ).expect("Unable to write file");
-                        })
-                        .then(|res: Result<(), reqwest::Error>| -> Result<(), reqwest::Error> {
-                            if let Err(error) = res {
-                                eprintln!("Error downloading: {:?}", error);
-                            }
-                            Ok(())
-                        })
-                    )
-                } else {
-                    None
-                }
-            }).buffer_unordered(N);
-        let work = responses.for_each(|_| Ok(()));
-        core.run(work)?;
-        Ok(())
+/// Run `opt -dot-callgraph` on `bitcode` and move its output to `out`.
+///
+/// `opt` always writes `callgraph.dot` into its working directory, so every
+/// run gets a scratch directory of its own next to `out`, which also creates
+/// the folder of `out`.
+fn opt_callgraph(llvm_path: &str, bitcode: &Path, out: &Path) -> PraziResult<()> {
+    let scratch = out.with_extension("tmp");
+    if scratch.exists() {
+        fs::remove_dir_all(&scratch)?;
     }
+    fs::create_dir_all(&scratch)?;
+    let output = children::output(
+        "opt",
+        Command::new(opt_binary(llvm_path))
+            .current_dir(&scratch)
+            .arg("-dot-callgraph")
+            .arg(fs::canonicalize(bitcode)?),
+    )?;
+    if !output.status.success() {
+        fs::remove_dir_all(&scratch)?;
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
+    }
+    fs::rename(scratch.join("callgraph.dot"), out)?;
+    fs::remove_dir_all(&scratch)?;
+    Ok(())
+}
 
-    fn validate_manifests(&self) {
-        self.list.par_iter().for_each(|krate| {
-            let dir = krate.dir();
-            if Path::new(&dir).exists() {
-                let output = Command::new("cargo")
-                    .arg("read-manifest")
-                    .current_dir(dir)
-                    .output()
-                    .expect("failed to execute read-manifest");
-
-                if output.status.success() {
-                    //  println!("Valid manifest");
-                  //let data = String::from_utf8_lossy(&output.stdout);
-                  //let v: serde_json::Value = serde_json::from_str(&data).unwrap();
-                  //let targets = v["targets"].as_array().unwrap();
-                  //for target in targets.iter() {
-                  //    for t in target["crate_types"].as_array().unwrap().iter() {
-                  //        println!("crate_type: {}", t);
-                  //    }
-                  //}
-                } else {
-                    println!("Not valid manifest");
-                    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-                }
-            }
-        });
+/// Passes of `opt` that turn indirect calls with a known target into direct
+/// ones, by default: constant propagation across functions and folding of
+/// loads from constant vtables (`-ipsccp`, `-instcombine`) and
+/// devirtualization with type metadata (`-wholeprogramdevirt`). Passes that
+/// delete dead functions, like `-globalopt`, would drop nodes of the graph.
+const RESOLVE_PASSES: &str = "-ipsccp -instcombine -wholeprogramdevirt";
+
+/// Like `opt_callgraph`, after running the `passes` resolving indirect calls
+/// on a copy of `bitcode`.
+fn opt_resolved_callgraph(llvm_path: &str, bitcode: &Path, passes: &str, out: &Path) -> PraziResult<()> {
+    let resolved = out.with_extension("resolved.bc");
+    let output = children::output(
+        "opt",
+        Command::new(opt_binary(llvm_path))
+            .args(passes.split_whitespace())
+            .arg(bitcode)
+            .arg("-o")
+            .arg(&resolved),
+    )?;
+    if !output.status.success() {
+        if resolved.exists() {
+            fs::remove_file(&resolved)?;
+        }
+        return Err(String::from_utf8_lossy(&output.stderr).into_owned().into());
     }
+    let res = opt_callgraph(llvm_path, &resolved, out);
+    fs::remove_file(&resolved)?;
+    res
+}
 
-    fn rewrite_manifests(&self) {
-        self.list.par_iter().for_each(|krate| {
-            let dir = krate.dir();
-            if Path::new(&dir).exists() && !Path::new(&format!("{}/Cargo.toml.orig", &dir)).exists()
-            {
-                let output = Command::new("cargo")
-                    .arg("publish")
-                    .args(&["--no-verify", "--dry-run", "--allow-dirty"])
-                    .current_dir(&dir)
-                    .output()
-                    .expect("failed to execute dry-run publish");
-
-                if output.status.success() {
-                    let new_file = format!(
-                        "{0}/target/package/{1}-{2}.crate",
-                        &dir, krate.name, krate.version
-                    );
-                    if Path::new(&new_file).exists() {
-                        let data = File::open(&new_file).unwrap();
-                        let decompressed = GzDecoder::new(data);
-                        let mut archive = Archive::new(decompressed);
-                        let tar_dir = krate.dir_src();
-                        let dst_dir = krate.dir();
-                        archive.unpack(&tar_dir).unwrap();
-                        fs::remove_dir_all(&dst_dir).unwrap();
-                        fs::rename(
-                            format!("/{0}/{1}-{2}", &tar_dir, krate.name, krate.version),
-                            &dst_dir,
-                        ).unwrap();
-                        println!("Repackaged: {:?}", &krate.url_src());
-                    }
-                } else {
-                    println!("Package not publishable with the running Cargo version");
-                }
-            }
-        });
+/// Record the `content_hash` of the call graphs of `krate` in `entry`.
+fn with_graph_hash(entry: Entry, krate: &PraziCrate, graphs: &[PathBuf]) -> Entry {
+    match graph::content_hash_files(graphs) {
+        Ok(hash) => entry.with("graph_hash", hash),
+        Err(error) => {
+            eprintln!("Error hashing graphs: {:?} {:?}", krate, error);
+            entry
+        }
     }
+}
 
-    fn compile(&self) {
-        self.list.par_iter().for_each(|krate| {
-            let dir = krate.dir();
-            if Path::new(&dir).exists() {
-                let output = Command::new("cargo")
-                    .arg("build")
-                    .current_dir(&dir)
-                    .output()
-                    .expect("failed to execute cargo build");
-                if output.status.success() {
-                    println!("build done!");
-                } else {
-                    println!("build failed");
-                    eprintln!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-                }
-            }
-        });
+/// Order in which the crates of the registry are processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    /// The order in which the crates appear in the index.
+    Index,
+    /// Alphabetically by crate name, then by version.
+    Name,
+    /// Crates with the most reverse dependencies first.
+    Popularity,
+    /// Crates with the most downloads first, from `ingest-downloads`.
+    Downloads,
+    /// Shuffled, reproducible when a seed is given.
+    Random,
+}
+
+impl FromStr for Order {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Order, String> {
+        match s {
+            "index" => Ok(Order::Index),
+            "name" => Ok(Order::Name),
+            "popularity" => Ok(Order::Popularity),
+            "downloads" => Ok(Order::Downloads),
+            "random" => Ok(Order::Random),
+            _ => Err(format!("unknown order: {}", s)),
+        }
     }
+}
 
-    fn build_callgraph(&self) {
-        let llvm_path = CONFIG.section(Some("llvm")).unwrap().get("path").unwrap();
-        self.list.par_iter().for_each(|krate| {
-            let dir = krate.dir();
-            if krate.has_bitcode() {
-                let output = Command::new(format!("{}/bin/opt", llvm_path))
-                    .current_dir(&dir)
-                    .arg("-dot-callgraph")
-                    .arg(krate.bitcode_path())
-                    .output()
-                    .expect("failed to execute llvm opt");
-                if output.status.success() {
-                    println!("callgraph built: {:?}", krate);
-                } else {
-                    println!("callgraph failed failed");
-                    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
-                }
-            } else {
-                println!("no bitcode: {:?}", krate)
-            }
-        });
+/// A `cargo` command using the shared `CARGO_HOME` if one is configured.
+///
+/// Sharing `CARGO_HOME` means every dependency is only downloaded once for the
+/// whole corpus. Concurrent cargo processes coordinate through cargo's own
+/// file locks on the registry cache, so parallel builds do not corrupt it.
+fn cargo_command() -> Command {
+    let mut cmd = Command::new("cargo");
+    if let Some(cargo_home) = config_cargo_home() {
+        cmd.env("CARGO_HOME", cargo_home);
     }
+    cmd
 }
 
-fn main() {
-    let mut reg = Registry { list: Vec::new() };
+/// Flags of `cargo build` for reproducible builds, set by `[build]` keys and
+/// `build-crates` options of the same names.
+const CARGO_FLAGS: &[&str] = &["locked", "frozen", "offline"];
 
-    let matches = App::new("rustprazi")
-        .version("0.1.0")
-        .about("Rustpräzi: generate call-based dependency networks of crates.io registry")
-        .subcommand(SubCommand::with_name("download").about("download registry crate sources"))
-        .subcommand(SubCommand::with_name("validate").about("validate Cargo.toml files"))
-        .subcommand(
-            SubCommand::with_name("rewrite")
-                .about("rewrite Cargo.toml to remove local Path dependencies"),
-        ).subcommand(
-            SubCommand::with_name("build-callgraphs")
-                .about("construct Crate-wide LLVM callgraphss"),
-        ).subcommand(
-            SubCommand::with_name("build-crates")
-                .about("build all crates"),
-        ).get_matches();
+/// Whether `flags` hold unstable `-Z` options, which need a nightly compiler.
+fn has_unstable_flags(flags: &str) -> bool {
+    flags
+        .split_whitespace()
+        .any(|flag| flag == "-Z" || flag.starts_with("-Z"))
+}
 
-    if let Some(_matches) = matches.subcommand_matches("download") {
-        reg.read();
-        if let Err(error) = reg.download_src() {
-            eprintln!("Error downloading: {:?}", error);
-        } else {
-            println!("Done with downloading!");
+/// Whether the `rustc` the builds run with is a nightly (or dev) compiler.
+fn rustc_is_nightly() -> bool {
+    rustc_version().map_or(false, |v| v.contains("-nightly") || v.contains("-dev"))
+}
+
+/// The output of `rustc --version`, e.g. `rustc 1.31.0 (abe02cefd 2018-12-04)`.
+fn rustc_version() -> Option<String> {
+    match Command::new("rustc").arg("--version").output() {
+        Ok(output) => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        Err(error) => {
+            eprintln!("Error running rustc --version: {:?}", error);
+            None
         }
     }
+}
 
-    if let Some(_matches) = matches.subcommand_matches("validate") {
-        reg.read();
-        reg.validate_manifests();
+/// The fields of `rustc --version --verbose`, e.g. `commit-hash`, `host` and
+/// `LLVM version`, which name the exact compiler build.
+fn rustc_verbose() -> Option<BTreeMap<String, String>> {
+    let output = match Command::new("rustc").args(&["--version", "--verbose"]).output() {
+        Ok(output) => output,
+        Err(error) => {
+            eprintln!("Error running rustc --version --verbose: {:?}", error);
+            return None;
+        }
+    };
+    let fields = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ": ");
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => Some((key.trim().to_string(), value.trim().to_string())),
+                _ => None,
+            }
+        }).collect();
+    Some(fields)
+}
+
+/// Check that `rustup run {toolchain}` works, so that a toolchain that is not
+/// installed fails the phase once instead of every crate with the same error.
+fn check_toolchain(toolchain: &str) -> Result<(), String> {
+    let output = Command::new("rustup")
+        .args(&["run", toolchain, "rustc", "-V"])
+        .output()
+        .map_err(|e| format!("failed to execute rustup: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "toolchain {0} not installed, run `rustup toolchain install {0}`: {1}",
+            toolchain,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
     }
+}
 
-    if let Some(_matches) = matches.subcommand_matches("rewrite") {
-        reg.read();
-        reg.rewrite_manifests();
+/// Check that the `cargo` of the builds runs, e.g. that the toolchain rustup
+/// selects for it is installed.
+fn check_cargo() -> Result<(), String> {
+    let output = Command::new("cargo")
+        .arg("--version")
+        .output()
+        .map_err(|e| format!("failed to execute cargo: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "cargo does not run, install its toolchain with `rustup toolchain install`: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
     }
+}
 
-    if let Some(_matches) = matches.subcommand_matches("build-callgraphs") {
-        reg.read();
-        reg.build_callgraph();
+/// Log whether the shared `CARGO_HOME` already holds a registry cache.
+fn report_cargo_home() {
+    if let Some(cargo_home) = config_cargo_home() {
+        let warm = cargo_home.join("registry").join("cache").exists();
+        println!(
+            "Using shared CARGO_HOME {:?} (registry cache {})",
+            cargo_home,
+            if warm { "warm" } else { "cold" }
+        );
     }
+}
 
-    if let Some(_matches) = matches.subcommand_matches("build-crates") {
-        reg.read();
-        reg.compile();
+/// Write the report of a phase, logging where it went.
+fn write_report(phase: &str, entries: &[Entry]) {
+    match report::write(phase, entries) {
+        Ok(path) => progress!("Report written to {:?}", path),
+        Err(error) => {
+            disk::check(&report::reports_dir(), &error);
+            eprintln!("Error writing {} report: {:?}", phase, error)
+        }
+    }
+    checkpoint::finish(phase);
+    report::flush_failures();
+    let count = |status: Status| entries.iter().filter(|e| e.status == status).count();
+    let empty = if phase == "callgraph" {
+        Some(entries.iter().filter(|e| e.details.contains_key("empty_graph")).count())
+    } else {
+        None
+    };
+    summary::print(phase, entries.len(), count(Status::Ok), empty, count(Status::Failed), 0);
+}
+
+/// Add the edition and the symlinks of the sources of `krate` to its
+/// `validate` entry.
+fn with_source_details(krate: &PraziCrate, entry: Entry) -> Entry {
+    let entry = match manifest::read(&krate.dir()) {
+        Ok(ref m) => {
+            let mut entry = entry
+                .with("edition", manifest::edition(m))
+                .with("build_script", manifest::has_build_script(&krate.dir(), m));
+            if manifest::is_proc_macro(m) {
+                entry = entry.with("proc_macro", true);
+            }
+            if let Some(rust_version) = manifest::rust_version(m) {
+                entry = entry.with("rust_version", rust_version);
+            }
+            let cargo_features = manifest::cargo_features(m);
+            if !cargo_features.is_empty() {
+                entry = entry.with("cargo_features", cargo_features);
+            }
+            entry
+        }
+        Err(_) => entry,
+    };
+    match Provenance::read(krate) {
+        Some(ref p) if !p.symlinks.is_empty() || !p.skipped_symlinks.is_empty() => entry
+            .with("symlinks", &p.symlinks)
+            .with("skipped_symlinks", &p.skipped_symlinks),
+        _ => entry,
+    }
+}
+
+/// What `unpack_crate` observed while extracting a crate.
+#[derive(Debug, Default)]
+struct Unpacked {
+    /// Top-level directory of the archive when it is not `{name}-{version}`.
+    archive_root: Option<String>,
+    /// Symlinks within the tree that were recreated, relative to the crate.
+    symlinks: Vec<String>,
+    /// Symlinks pointing outside the tree, which were not extracted.
+    skipped_symlinks: Vec<String>,
+    /// The regular files of the archive as recorded in their tar headers.
+    files: Vec<ArchiveFile>,
+}
+
+/// Extract a gzipped crate tarball into `krate.dir()`.
+///
+/// The archive is unpacked into `krate.dir_tmp()` and its top-level directory
+/// is only moved into place once extraction fully succeeded, so `krate.dir()`
+/// is either absent or a complete tree. The scratch directory is removed on
+/// failure, including when the archive has more entries than `[download]
+/// max_files_per_crate` or its entries more bytes than
+/// `max_uncompressed_bytes`; both are checked on the tar headers, before
+/// the entry is written.
+fn unpack_crate<R: Read>(krate: &PraziCrate, tarball: R) -> PraziResult<Unpacked> {
+    let tmp_dir = krate.dir_tmp();
+    if tmp_dir.exists() {
+        fs::remove_dir_all(&tmp_dir)?;
+    }
+    fs::create_dir_all(&tmp_dir)?;
+    let res = unpack_into(krate, tarball, &tmp_dir);
+    if tmp_dir.exists() {
+        if let Err(error) = fs::remove_dir_all(&tmp_dir) {
+            eprintln!("Error deleting: {:?} {:?}", tmp_dir, error);
+        }
+    }
+    res
+}
+
+fn unpack_into<R: Read>(krate: &PraziCrate, tarball: R, tmp_dir: &Path) -> PraziResult<Unpacked> {
+    let mut unpacked = Unpacked::default();
+    let mut archive = Archive::new(GzDecoder::new(tarball));
+    // keep the modes and mtimes of the tarball, not those of the extraction
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    let mut entries = 0;
+    let mut bytes = 0;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        entries += 1;
+        bytes += entry.header().size()?;
+        if entries > *MAX_FILES_PER_CRATE {
+            return Err(format!(
+                "too many files: more than {} archive entries ([download] max_files_per_crate)",
+                *MAX_FILES_PER_CRATE
+            ).into());
+        }
+        if bytes > *MAX_UNCOMPRESSED_BYTES {
+            return Err(format!(
+                "too large: more than {} bytes uncompressed ([download] max_uncompressed_bytes)",
+                *MAX_UNCOMPRESSED_BYTES
+            ).into());
+        }
+        if entry.header().entry_type().is_file() {
+            let header = entry.header();
+            unpacked.files.push(ArchiveFile {
+                path: path.components().skip(1).collect::<PathBuf>().to_string_lossy().into_owned(),
+                size: header.size()?,
+                mode: header.mode()?,
+                mtime: header.mtime()?,
+            });
+        }
+        if entry.header().entry_type() == EntryType::Symlink {
+            let target = entry.link_name()?.map(|target| target.into_owned());
+            // relative to the crate, without the top-level directory
+            let name = path.components().skip(1).collect::<PathBuf>().to_string_lossy().into_owned();
+            match target {
+                Some(ref target) if stays_in_tree(&path, target) => unpacked.symlinks.push(name),
+                _ => {
+                    eprintln!("Skipping symlink out of the tree: {:?} {:?} -> {:?}", krate, path, target);
+                    unpacked.skipped_symlinks.push(name);
+                    continue;
+                }
+            }
+        }
+        // `unpack_in` refuses paths escaping `tmp_dir`
+        if !entry.unpack_in(tmp_dir)? {
+            eprintln!("Skipping entry out of the tree: {:?} {:?}", krate, path);
+        }
+    }
+    let mut root = tmp_dir.join(krate.archive_root());
+    if !root.is_dir() {
+        root = find_archive_root(tmp_dir)?;
+        let actual = root.file_name().unwrap().to_string_lossy().into_owned();
+        eprintln!(
+            "Unexpected archive root: {:?} expected {} found {}",
+            krate,
+            krate.archive_root(),
+            actual
+        );
+        unpacked.archive_root = Some(actual);
+    }
+    let dst_dir = krate.dir();
+    if dst_dir.exists() {
+        fs::remove_dir_all(&dst_dir)?;
+    }
+    fs::create_dir_all(krate.dir_src())?;
+    move_dir(&root, &dst_dir)?;
+    Ok(unpacked)
+}
+
+/// Record what was unpacked from the `.crate` file `body` of `krate`, whose
+/// SHA-256 is `sha256`, and its hashes in `algos` in its provenance.
+fn record_unpacked(
+    provenance: &mut Provenance,
+    krate: &PraziCrate,
+    unpacked: Unpacked,
+    archive: &Path,
+    sha256: &str,
+    algos: &[Algo],
+) {
+    provenance.archive_root = unpacked.archive_root;
+    provenance.symlinks = unpacked.symlinks;
+    provenance.skipped_symlinks = unpacked.skipped_symlinks;
+    provenance.files = unpacked.files;
+    match swhid::source_tree(&krate.dir(), false) {
+        Ok(id) => provenance.swhid = Some(id),
+        Err(error) => eprintln!("Error hashing sources: {:?} {:?}", krate, error),
+    }
+    for &algo in algos {
+        let digest = match algo {
+            Algo::Sha256 => sha256.to_string(),
+            algo => match algo.digest_file(archive) {
+                Ok(digest) => digest,
+                Err(error) => {
+                    eprintln!("Error hashing: {:?} {:?}", archive, error);
+                    continue;
+                }
+            },
+        };
+        provenance.checksums.insert(algo.name().to_string(), digest);
+    }
+}
+
+/// Delete the `.crate.part` file of a finished download, which is complete
+/// or corrupt and never resumed again.
+fn remove_partial(part: &Path) {
+    if let Err(error) = fs::remove_file(part) {
+        eprintln!("Error deleting: {:?} {:?}", part, error);
+    }
+}
+
+/// Remove what a failed unpack may have left of `krate`: the tree, which a
+/// copy across filesystems may have moved halfway, and the marker of an
+/// earlier download it replaced, so the next run downloads it again.
+fn remove_unpacked(krate: &PraziCrate) {
+    let dir = krate.dir();
+    if dir.exists() {
+        if let Err(error) = fs::remove_dir_all(&dir) {
+            eprintln!("Error deleting: {:?} {:?}", dir, error);
+        }
+    }
+    let success_file = krate.success_file();
+    if success_file.exists() {
+        remove_partial(&success_file);
+    }
+}
+
+/// Attempts of a rename that fails for another reason than crossing
+/// filesystems, e.g. a file briefly held open by a virus scanner.
+const RENAME_ATTEMPTS: u32 = 3;
+
+/// Whether a rename failed because `from` and `to` are on different
+/// filesystems.
+#[cfg(unix)]
+fn crosses_devices(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(::libc::EXDEV)
+}
+
+#[cfg(not(unix))]
+fn crosses_devices(error: &std::io::Error) -> bool {
+    // ERROR_NOT_SAME_DEVICE
+    error.raw_os_error() == Some(17)
+}
+
+/// `fs::rename`, retried with a growing pause while it fails for another
+/// reason than crossing filesystems.
+fn rename(from: &Path, to: &Path) -> std::io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match fs::rename(from, to) {
+            Err(ref error) if attempt < RENAME_ATTEMPTS && !crosses_devices(error) => {
+                progress!("Retrying rename of {:?}: {}", from, error);
+                std::thread::sleep(Duration::from_millis(100 << attempt));
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Move the directory `from` to `to`, which must not exist.
+///
+/// A rename fails when `from` is on another filesystem (`[storage]
+/// tmp_path`); the tree is then copied next to `to` and renamed into place,
+/// so `to` still only ever appears complete.
+fn move_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    match rename(from, to) {
+        Err(ref error) if crosses_devices(error) => {}
+        res => return res,
+    }
+    let staging = to.with_file_name(format!(
+        ".{}.move",
+        to.file_name().unwrap().to_string_lossy()
+    ));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    let res = copy_dir(from, &staging).and_then(|()| rename(&staging, to));
+    if res.is_err() && staging.exists() {
+        if let Err(error) = fs::remove_dir_all(&staging) {
+            eprintln!("Error deleting: {:?} {:?}", staging, error);
+        }
+    }
+    res?;
+    fs::remove_dir_all(from)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let (src, dst) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&src, &dst)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&src, &dst)?;
+        } else {
+            fs::copy(&src, &dst)?;
+        }
+    }
+    Ok(())
+}
+
+/// A copy of the sources of a crate to build in, deleted when dropped.
+struct BuildCopy {
+    dir: PathBuf,
+}
+
+impl BuildCopy {
+    /// Copy the sources in `from` to `to`, without the artifacts of builds.
+    fn new(from: &Path, to: PathBuf) -> std::io::Result<BuildCopy> {
+        if to.exists() {
+            fs::remove_dir_all(&to)?;
+        }
+        fs::create_dir_all(&to)?;
+        // deleted again if the copy fails halfway
+        let copy = BuildCopy { dir: to };
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // a published lockfile belongs to the sources
+            if swhid::is_artifact(&name) && name != "Cargo.lock" {
+                continue;
+            }
+            let (src, dst) = (entry.path(), copy.dir.join(entry.file_name()));
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                copy_dir(&src, &dst)?;
+            } else if file_type.is_symlink() {
+                copy_symlink(&src, &dst)?;
+            } else {
+                fs::copy(&src, &dst)?;
+            }
+        }
+        Ok(copy)
+    }
+}
+
+impl Drop for BuildCopy {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_dir_all(&self.dir) {
+            eprintln!("Error deleting: {:?} {:?}", self.dir, error);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(src)?, dst)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(src: &Path, dst: &Path) -> std::io::Result<()> {
+    fs::copy(src, dst).map(|_| ())
+}
+
+/// Whether the symlink at `path` (relative to the root of the archive) to
+/// `target` resolves within the top-level directory of the archive.
+fn stays_in_tree(path: &Path, target: &Path) -> bool {
+    let mut resolved = path.parent().map_or(Vec::new(), |dir| dir.components().collect::<Vec<_>>());
+    for component in target.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(_) => resolved.push(component),
+            Component::ParentDir => {
+                resolved.pop();
+                if resolved.is_empty() {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    !resolved.is_empty()
+}
+
+/// The top-level directory of an archive that was not packaged as
+/// `{name}-{version}`: the only directory the unpack created, or else the only
+/// one holding a `Cargo.toml`.
+fn find_archive_root(tmp_dir: &Path) -> PraziResult<PathBuf> {
+    let dirs = fs::read_dir(tmp_dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect::<Vec<_>>();
+    if dirs.len() == 1 {
+        return Ok(dirs[0].clone());
+    }
+    let mut manifests = dirs.into_iter().filter(|d| d.join("Cargo.toml").exists());
+    match (manifests.next(), manifests.next()) {
+        (Some(root), None) => Ok(root),
+        _ => Err("could not determine the top-level directory of the archive".into()),
+    }
+}
+
+/// Which versions of a crate are processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VersionSelection {
+    Latest,
+    All,
+    /// The highest version of every major version.
+    LatestPerMajor,
+    /// The highest version of every major.minor version.
+    LatestPerMinor,
+}
+
+impl FromStr for VersionSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<VersionSelection, String> {
+        match s {
+            "latest" => Ok(VersionSelection::Latest),
+            "all" => Ok(VersionSelection::All),
+            "latest-per-major" => Ok(VersionSelection::LatestPerMajor),
+            "latest-per-minor" => Ok(VersionSelection::LatestPerMinor),
+            _ => Err(format!("unknown version selection: {}", s)),
+        }
+    }
+}
+
+/// Options that work on the whole list of crates, which `download --stream`
+/// never holds.
+const LIST_OPTIONS: &[&str] = &[
+    "crate", "closure", "from-list", "category", "owner", "edition", "order", "sample", "batch", "resume",
+];
+
+/// The versions `selection` picks among `versions` (in index order) of
+/// `krate`, most recent first.
+fn select_versions(krate: &Crate, versions: &[&Version], selection: VersionSelection) -> Vec<String> {
+    match selection {
+        VersionSelection::Latest => versions
+            .last()
+            .map(|v| v.version().to_string())
+            .into_iter()
+            .collect(),
+        //we also consider yanked versions
+        VersionSelection::All => versions
+            .iter()
+            .rev()
+            .map(|v| v.version().to_string())
+            .collect(),
+        VersionSelection::LatestPerMajor | VersionSelection::LatestPerMinor => {
+            let mut latest: BTreeMap<(u64, u64), (semver::Version, String)> = BTreeMap::new();
+            for version in versions {
+                // `select` only passes the versions `Registry::is_semver` admits
+                let parsed = semver::Version::parse(version.version()).unwrap_or_else(|error| {
+                    panic!("unparseable version {} {}: {:?}", krate.name(), version.version(), error)
+                });
+                let key = match selection {
+                    VersionSelection::LatestPerMajor => (parsed.major, 0),
+                    _ => (parsed.major, parsed.minor),
+                };
+                let newer = latest.get(&key).map_or(true, |current| parsed > current.0);
+                if newer {
+                    latest.insert(key, (parsed, version.version().to_string()));
+                }
+            }
+            latest.into_iter().rev().map(|(_, (_, v))| v).collect()
+        }
+    }
+}
+
+pub(crate) struct Registry {
+    pub list: Vec<PraziCrate>,
+    /// Number of distinct crates depending on a crate (by name).
+    pub reverse_deps: HashMap<String, usize>,
+    /// Names of the dependencies of the latest version of every crate.
+    dependencies: HashMap<String, Vec<String>>,
+    /// The kinds of dependencies counted in `dependencies` and `reverse_deps`.
+    dep_kinds: DepKinds,
+    /// Keep at most this many versions of every crate, the most recent ones.
+    max_versions_per_crate: Option<usize>,
+    /// The `--policy` crates are selected by when the index is read.
+    policy: Option<Policy>,
+    /// Only select among the yanked versions of every crate.
+    only_yanked: bool,
+    /// With `--version-selection latest`, take the latest version that was
+    /// not yanked, if any.
+    prefer_unyanked: bool,
+    /// Keep only the selected versions without normal dependencies with
+    /// `Some(true)` (`--leaf-only`), only those with some with `Some(false)`
+    /// (`--has-deps`).
+    leaf: Option<bool>,
+    /// Versions of the index left out because they are not valid semver.
+    unparseable_versions: AtomicUsize,
+    /// Profile crates are built with and bitcode is searched in.
+    pub profile: Profile,
+    version_selection: VersionSelection,
+    /// Crates to take in their latest version, looked up in the index instead
+    /// of enumerating it, as `selftest` does.
+    latest_of: &'static [&'static str],
+    /// Extra environment variables for build subprocesses.
+    pub build_env: BTreeMap<String, String>,
+    /// Re-check already downloaded crates with conditional requests.
+    pub revalidate: bool,
+    /// Keep the downloaded `.crate` files, for `verify-archives`.
+    keep_archive: bool,
+    /// Folder of `{name}-{version}.crate` files taken instead of downloading
+    /// them.
+    source_dir: Option<PathBuf>,
+    /// Fail the crates missing from `source_dir` instead of downloading them.
+    source_dir_only: bool,
+    /// Skip downloading crate files larger than this, `--max-crate-size`.
+    max_crate_bytes: Option<u64>,
+    /// The crates skipped for `max_crate_bytes`, for `reports/oversized.json`.
+    oversized: Mutex<Vec<Entry>>,
+    /// Download the crates while they are selected from the index, without
+    /// holding the list of all of them.
+    stream: bool,
+    /// With `stream`, leave out the crates whose folder exists.
+    only_missing: bool,
+    /// The crates.io `cksum` (SHA-256) of every crate in `list`.
+    checksums: HashMap<(String, String), String>,
+    /// Hashes recorded in the provenance of downloaded crates.
+    checksum_algos: Vec<Algo>,
+    /// Command run after every successful build.
+    post_build: Option<Hook>,
+    /// Command every build runs in, from `[build] sandbox`.
+    sandbox: Option<Sandbox>,
+    /// `--locked`, `--frozen` and `--offline` passed to every build.
+    cargo_flags: Vec<&'static str>,
+    /// Local `.crate` file processed instead of the crates of the index.
+    crate_file: Option<PathBuf>,
+    /// Build with `-C codegen-units=1`, so every crate emits one bitcode file.
+    single_codegen_unit: bool,
+    /// Build with `--message-format=json`, which counts the diagnostics
+    /// exactly.
+    json_messages: bool,
+    /// Build a crate whose build failed once more, to tell flaky failures.
+    retry_failed_once: bool,
+    /// Build crates again whose build marker names the current toolchain.
+    force: bool,
+    /// Build the crates in waves, after the crates of the list they depend on.
+    topo_order: bool,
+    /// Build in a copy of the sources, so the crate's folder only gains the
+    /// `target` folder.
+    out_of_tree_build: bool,
+    /// Extra `RUSTFLAGS` of every build, from `[compiler] rustflags`.
+    rustflags: Option<String>,
+    /// Write the whole output of every build to `{dir}/build.log`.
+    build_log: bool,
+    /// Build with `--emit=mir` as well, which needs a nightly rustc.
+    emit_mir: bool,
+    /// Build with `-C debuginfo=1` and record the source locations of the
+    /// functions of the call graphs.
+    with_debuginfo: bool,
+    /// Feature sets every crate is built with besides its default features.
+    feature_matrix: Vec<FeatureSet>,
+    /// Keep the repeated edges `opt` emits for multiple call sites.
+    keep_multi_edges: bool,
+    /// Call graphs of a crate with fewer edges in total are recorded as
+    /// `empty_graph`.
+    min_edges: usize,
+    /// Build the call graphs of crates whose bitcode did not change as well.
+    rebuild_graphs: bool,
+    /// Link the bitcode files of a crate into one module before building its
+    /// call graph, unless `--no-link`.
+    link_bitcode: bool,
+    /// Leave procedural macro crates out of the build and callgraph phases.
+    skip_proc_macros: bool,
+    /// Do not build crates that would run a build script, their own or one
+    /// of a dependency.
+    no_build_scripts: bool,
+    /// Leave crates with more bytes of sources out of the build and callgraph
+    /// phases.
+    max_source_bytes: Option<u64>,
+    /// `opt` passes run before building the call graphs, with
+    /// `--resolve-indirect`.
+    resolve_passes: Option<String>,
+    /// Number of crates downloaded at the same time.
+    download_concurrency: usize,
+    /// Number of crates built or turned into call graphs at the same time,
+    /// one per worker thread of the global pool when not set.
+    jobs: Option<usize>,
+    /// Ignore the cached `read-manifest` output.
+    refresh: bool,
+    /// Retries of a dry-run publish that failed for a transient reason.
+    publish_retries: u32,
+    index_update: index::UpdateOptions,
+}
+
+type PraziResult<T> = std::result::Result<T, Box<std::error::Error>>;
+
+const N: usize = 5;
+
+/// Default retries of a transiently failing dry-run publish.
+const PUBLISH_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a dry-run publish, doubled on every retry.
+const PUBLISH_BACKOFF: Duration = Duration::from_secs(2);
+
+/// `cargo` errors of a dry-run publish that stem from the network or the
+/// filesystem rather than from the manifest.
+const TRANSIENT_PUBLISH_ERRORS: &[&str] = &[
+    "spurious network error",
+    "failed to update registry",
+    "failed to fetch",
+    "failed to download",
+    "Couldn't resolve host",
+    "Connection reset",
+    "Connection refused",
+    "timed out",
+    "failed to get successful HTTP response",
+    "Blocking waiting for file lock",
+];
+
+fn is_transient_publish_failure(stderr: &str) -> bool {
+    TRANSIENT_PUBLISH_ERRORS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+}
+
+/// The call graphs `Registry::opt_graphs` built from the bitcode of a build.
+#[derive(Debug, Default)]
+struct OptGraphs {
+    /// The graph files, one per bitcode file or one of the linked bitcode.
+    graphs: Vec<PathBuf>,
+    /// The edges of all graphs, after dropping the repeated ones.
+    edges: usize,
+    /// The repeated edges dropped, unless `--keep-multi-edges`.
+    duplicate_edges: usize,
+    /// The edges `--resolve-indirect` added to the direct calls.
+    indirect_edges: usize,
+    /// The functions with a source location, with `--with-debuginfo`.
+    located_functions: usize,
+}
+
+impl Registry {
+    /// The local index checkout, brought up to date.
+    fn index(&self) -> Index {
+        let index_dir = config_index_dir();
+        if let Err(error) = index::retrieve_or_update(&index_dir, &self.index_update) {
+            panic!("{}", error);
+        }
+        Index::new(index_dir)
+    }
+
+    fn read(&mut self) {
+        let index = self.index();
+        for krate in index.crates() {
+            let dependencies = krate
+                .latest_version()
+                .dependencies()
+                .iter()
+                .filter(|dep| self.dep_kinds.includes(dep))
+                .map(|dep| dep.name().to_string())
+                .collect::<HashSet<_>>();
+            for dependency in &dependencies {
+                *self.reverse_deps.entry(dependency.clone()).or_insert(0) += 1;
+            }
+            self.dependencies
+                .insert(krate.name().to_string(), dependencies.into_iter().collect());
+            for (selected, checksum) in self.select(&krate) {
+                self.checksums
+                    .insert((selected.name.clone(), selected.version.clone()), checksum);
+                self.list.push(selected);
+            }
+        }
+        let unparseable = self.unparseable_versions.load(Ordering::SeqCst);
+        if unparseable > 0 {
+            message!("Skipped {} versions that are not valid semver, see prazi lint-index", unparseable);
+        }
+    }
+
+    /// Is `version` of `krate` valid semver? Other versions are counted and
+    /// left out, the requirements of dependents and the version filters could
+    /// not match them. Versions whose canonical form differs are kept as
+    /// published, the registry serves them by that name.
+    fn is_semver(&self, krate: &Crate, version: &Version) -> bool {
+        match semver::Version::parse(version.version()) {
+            Ok(parsed) => {
+                if parsed.to_string() != version.version() {
+                    eprintln!(
+                        "Non-canonical version: {} {} is {}",
+                        krate.name(),
+                        version.version(),
+                        parsed
+                    );
+                }
+                true
+            }
+            Err(error) => {
+                eprintln!(
+                    "Skipping unparseable version: {} {} {:?}",
+                    krate.name(),
+                    version.version(),
+                    error
+                );
+                self.unparseable_versions.fetch_add(1, Ordering::SeqCst);
+                false
+            }
+        }
+    }
+
+    /// The versions of `krate` the policy and the version selection of the
+    /// command line pick, most recent first, with their checksums.
+    fn select(&self, krate: &Crate) -> Vec<(PraziCrate, String)> {
+        let policy = self.policy.as_ref();
+        if !policy.map_or(true, |p| p.admits(krate.name())) {
+            return Vec::new();
+        }
+        let mut candidates = krate
+            .versions()
+            .iter()
+            .filter(|v| self.is_semver(krate, v))
+            .filter(|v| !self.only_yanked || v.is_yanked())
+            .filter(|v| policy.map_or(true, |p| p.admits_version(krate.name(), v.version())))
+            .collect::<Vec<_>>();
+        // a yanked latest version is only taken if all of them are yanked
+        if self.prefer_unyanked
+            && self.version_selection == VersionSelection::Latest
+            && candidates.iter().any(|v| !v.is_yanked())
+        {
+            candidates.retain(|v| !v.is_yanked());
+        }
+        let versions = select_versions(krate, &candidates, self.version_selection);
+        // the selections list the most recent versions first
+        let cap = self
+            .max_versions_per_crate
+            .or_else(|| policy.and_then(|p| p.max_versions_per_crate))
+            .unwrap_or(versions.len());
+        versions
+            .into_iter()
+            .take(cap)
+            .filter_map(|version| {
+                let found = krate.versions().iter().find(|v| v.version() == version)?;
+                if let Some(leaf) = self.leaf {
+                    // dev- and build dependencies are not needed by dependents
+                    let normal = found.dependencies().iter().any(|dep| dep.kind().map_or(true, |kind| kind == "normal"));
+                    if normal == leaf {
+                        return None;
+                    }
+                }
+                let checksum = found.checksum().to_string();
+                let selected = PraziCrate {
+                    name: krate.name().to_string(),
+                    version,
+                };
+                Some((selected, checksum))
+            }).collect()
+    }
+
+    /// Put exactly the `name@version` crates of `specs` in the list, looking
+    /// them up in the index instead of enumerating it.
+    fn read_crates<'a, I: Iterator<Item = &'a str>>(&mut self, specs: I) {
+        let index = self.index();
+        for spec in specs {
+            let mut parts = spec.splitn(2, '@');
+            let (name, version) = match (parts.next(), parts.next()) {
+                (Some(name), Some(version)) if !name.is_empty() && !version.is_empty() => {
+                    (name, version)
+                }
+                _ => panic!("--crate expects name@version, got {}", spec),
+            };
+            let krate = index
+                .crate_(name)
+                .unwrap_or_else(|| panic!("crate {} not found in index", name));
+            let found = krate
+                .versions()
+                .iter()
+                .find(|v| v.version() == version)
+                .unwrap_or_else(|| panic!("version {} of {} not found in index", version, name));
+            self.checksums.insert(
+                (name.to_string(), version.to_string()),
+                found.checksum().to_string(),
+            );
+            self.list.push(PraziCrate {
+                name: name.to_string(),
+                version: version.to_string(),
+            });
+        }
+    }
+
+    /// Put the latest version of each of the `names` crates in the list,
+    /// looking them up in the index instead of enumerating it.
+    fn read_latest(&mut self, names: &[&str]) {
+        let index = self.index();
+        for &name in names {
+            let krate = index
+                .crate_(name)
+                .unwrap_or_else(|| panic!("crate {} not found in index", name));
+            let latest = krate.latest_version();
+            self.checksums.insert(
+                (name.to_string(), latest.version().to_string()),
+                latest.checksum().to_string(),
+            );
+            self.list.push(PraziCrate {
+                name: name.to_string(),
+                version: latest.version().to_string(),
+            });
+        }
+    }
+
+    /// Put the crates of the dependency closure of `root`, a `name@version`,
+    /// in the list and record its conflicts in `reports/closure.json`.
+    fn read_closure(&mut self, root: &str, kinds: closure::Kinds) -> PraziResult<()> {
+        let index = self.index();
+        let closure = resolve_closure(&index, root, kinds)?;
+        message!(
+            "{} crates in the closure of {}, {} conflicts",
+            closure.crates.len(),
+            root,
+            closure.conflicts.len()
+        );
+        for conflict in &closure.conflicts {
+            progress!(
+                "conflict: {} {} of {}: {}",
+                conflict.name,
+                conflict.requirement,
+                conflict.required_by,
+                conflict.reason
+            );
+        }
+        for krate in &closure.crates {
+            self.checksums.insert(
+                (krate.name.clone(), krate.version.clone()),
+                krate.checksum.clone(),
+            );
+            self.list.push(PraziCrate {
+                name: krate.name.clone(),
+                version: krate.version.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    fn new() -> Registry {
+        Registry {
+            list: Vec::new(),
+            reverse_deps: HashMap::new(),
+            dependencies: HashMap::new(),
+            dep_kinds: DepKinds::default(),
+            max_versions_per_crate: None,
+            policy: None,
+            only_yanked: false,
+            prefer_unyanked: false,
+            leaf: None,
+            unparseable_versions: AtomicUsize::new(0),
+            profile: config_profile(),
+            version_selection: if config_latest_only() {
+                VersionSelection::Latest
+            } else {
+                VersionSelection::All
+            },
+            latest_of: &[],
+            build_env: config_build_env(),
+            revalidate: false,
+            keep_archive: false,
+            source_dir: None,
+            source_dir_only: false,
+            max_crate_bytes: None,
+            oversized: Mutex::new(Vec::new()),
+            stream: false,
+            only_missing: false,
+            checksums: HashMap::new(),
+            checksum_algos: vec![Algo::Sha256],
+            post_build: Hook::post_build(),
+            sandbox: Sandbox::from_config(),
+            cargo_flags: CARGO_FLAGS
+                .iter()
+                .cloned()
+                .filter(|flag| config_value("build", flag).map_or(false, |v| v == "true"))
+                .collect(),
+            crate_file: None,
+            single_codegen_unit: false,
+            json_messages: false,
+            retry_failed_once: false,
+            force: false,
+            topo_order: false,
+            out_of_tree_build: false,
+            rustflags: config_value("compiler", "rustflags"),
+            build_log: false,
+            emit_mir: false,
+            with_debuginfo: false,
+            feature_matrix: Vec::new(),
+            keep_multi_edges: false,
+            min_edges: 1,
+            rebuild_graphs: false,
+            link_bitcode: true,
+            resolve_passes: None,
+            skip_proc_macros: false,
+            no_build_scripts: false,
+            max_source_bytes: None,
+            download_concurrency: N,
+            jobs: None,
+            refresh: false,
+            publish_retries: config_value("rewrite", "retries")
+                .map_or(PUBLISH_RETRIES, |v| v.parse().expect("invalid [rewrite] retries")),
+            index_update: index::UpdateOptions {
+                timeout: config_value("crates", "index_timeout")
+                    .map(|v| Duration::from_secs(v.parse().expect("invalid [crates] index_timeout"))),
+                shallow: config_value("crates", "index_shallow").map_or(false, |v| v == "true"),
+                offline: config_value("crates", "index_offline").map_or(false, |v| v == "true"),
+                reclone: false,
+            },
+        }
+    }
+
+    /// Run `f`, a CPU-bound phase, on `jobs` worker threads when they are
+    /// set, on the global pool of one thread per core otherwise.
+    fn with_jobs<F: FnOnce() + Send>(&self, f: F) {
+        match self.jobs {
+            Some(jobs) => ::rayon::ThreadPoolBuilder::new()
+                .num_threads(jobs)
+                .build()
+                .expect("failed to start the worker threads")
+                .install(f),
+            None => f(),
+        }
+    }
+
+    /// Read the index and order the crates as requested on the command line.
+    fn prepare(&mut self, matches: &ArgMatches) {
+        summary::start(matches.is_present("quiet"), matches.is_present("json-lines"));
+        if matches.is_present("adaptive") {
+            throttle::enable();
+        }
+        if matches.is_present("nice") {
+            nice::enable();
+            self.download_concurrency = nice::DOWNLOAD_CONCURRENCY;
+        }
+        // explicit settings win over --nice
+        let concurrency = matches
+            .value_of("download-concurrency")
+            .map(|v| v.to_string())
+            .or_else(|| config_value("download", "concurrency"));
+        if let Some(concurrency) = concurrency {
+            let concurrency = concurrency.parse().expect("download concurrency must be a number");
+            assert!(concurrency > 0, "download concurrency must be at least 1");
+            self.download_concurrency = concurrency;
+        }
+        let jobs = matches
+            .value_of("jobs")
+            .map(|v| v.to_string())
+            .or_else(|| config_value("compiler", "jobs"));
+        if let Some(jobs) = jobs {
+            let jobs = jobs.parse().expect("jobs must be a number");
+            assert!(jobs > 0, "jobs must be at least 1");
+            self.jobs = Some(jobs);
+        }
+        self.revalidate = matches.is_present("revalidate");
+        self.keep_archive = matches.is_present("keep-archive");
+        self.source_dir = matches.value_of("source-dir").map(PathBuf::from);
+        self.source_dir_only = matches.is_present("offline") && self.source_dir.is_some();
+        if let Some(max) = matches.value_of("max-crate-size") {
+            let megabytes: u64 = max.parse().expect("--max-crate-size must be a number of megabytes");
+            self.max_crate_bytes = Some(megabytes * 1_000_000);
+        }
+        self.refresh = matches.is_present("refresh");
+        if let Some(retries) = matches.value_of("retries") {
+            self.publish_retries = retries.parse().expect("--retries must be a number");
+        }
+        if let Some(algos) = matches.values_of("checksum-algo") {
+            self.checksum_algos = algos.map(|v| v.parse().unwrap()).collect();
+        }
+        let max_runtime = matches
+            .value_of("max-runtime")
+            .map(|v| stop::parse_duration(v).unwrap());
+        stop::install(max_runtime);
+        if matches.is_present("fail-fast") {
+            stop::fail_fast();
+        }
+        if let Some(timeout) = matches.value_of("index-timeout") {
+            let secs = timeout.parse().expect("--index-timeout must be in seconds");
+            self.index_update.timeout = Some(Duration::from_secs(secs));
+        }
+        if matches.is_present("index-shallow") {
+            self.index_update.shallow = true;
+        }
+        if matches.is_present("index-offline") {
+            self.index_update.offline = true;
+        }
+        self.index_update.reclone = matches.is_present("reclone");
+        if let Some(addr) = matches.value_of("metrics-addr") {
+            if let Err(error) = metrics::serve(addr) {
+                eprintln!("Error starting metrics server on {}: {}", addr, error);
+            }
+        }
+        children::log_periodically();
+        if let Some(profile) = matches.value_of("profile") {
+            self.profile = profile.parse().unwrap();
+        }
+        self.single_codegen_unit = matches.is_present("single-codegen-unit");
+        self.json_messages = matches.value_of("message-format") == Some("json");
+        self.retry_failed_once = matches.is_present("retry-failed-once");
+        self.force = matches.is_present("force");
+        self.topo_order = matches.is_present("topo-order");
+        self.out_of_tree_build = matches.is_present("out-of-tree-build");
+        self.no_build_scripts = matches.is_present("no-build-scripts");
+        self.build_log = matches.is_present("build-log");
+        self.emit_mir = matches.value_of("emit") == Some("mir");
+        self.with_debuginfo = matches.is_present("with-debuginfo");
+        if let Some(name) = matches.value_of("bin") {
+            targets::select(Targets::Bin(name.to_string()));
+        } else if matches.is_present("bins") {
+            targets::select(Targets::Bins);
+        }
+        if matches.is_present("feature-matrix") {
+            let sets = match matches.values_of("feature-matrix") {
+                Some(sets) => sets.map(|v| v.parse().unwrap()).collect::<Vec<_>>(),
+                None => features::MATRIX.to_vec(),
+            };
+            // the default features are always built, into the regular folders
+            self.feature_matrix = sets
+                .into_iter()
+                .filter(|&set| set != FeatureSet::Default)
+                .collect();
+            self.feature_matrix.dedup();
+        }
+        self.keep_multi_edges = matches.is_present("keep-multi-edges");
+        if let Some(min) = matches.value_of("min-edges") {
+            self.min_edges = min.parse().expect("--min-edges must be a number");
+        }
+        self.rebuild_graphs = matches.is_present("rebuild");
+        self.link_bitcode = !matches.is_present("no-link");
+        if matches.is_present("resolve-indirect") {
+            self.resolve_passes =
+                Some(config_value("llvm", "resolve_passes").unwrap_or_else(|| RESOLVE_PASSES.to_string()));
+        }
+        for &flag in CARGO_FLAGS {
+            if matches.is_present(flag) && !self.cargo_flags.contains(&flag) {
+                self.cargo_flags.push(flag);
+            }
+        }
+        if let Some(vars) = matches.values_of("build-env") {
+            for var in vars {
+                let mut parts = var.splitn(2, '=');
+                match (parts.next(), parts.next()) {
+                    (Some(key), Some(value)) if !key.is_empty() => {
+                        self.build_env.insert(key.to_string(), value.to_string());
+                    }
+                    _ => panic!("--build-env expects KEY=VALUE, got {}", var),
+                }
+            }
+        }
+        if let Some(selection) = matches.value_of("version-selection") {
+            self.version_selection = selection.parse().unwrap();
+        }
+        self.only_yanked = matches.is_present("only-yanked");
+        self.prefer_unyanked = matches.is_present("prefer-unyanked");
+        if matches.is_present("leaf-only") {
+            self.leaf = Some(true);
+        } else if matches.is_present("has-deps") {
+            self.leaf = Some(false);
+        }
+        self.dep_kinds = dep_kinds(matches);
+        self.skip_proc_macros = matches.is_present("skip-proc-macros");
+        if let Some(max) = matches.value_of("max-source-bytes") {
+            self.max_source_bytes = Some(source_size::parse_size(max).unwrap());
+        }
+        if let Some(max) = matches.value_of("max-versions-per-crate") {
+            let max = max.parse().expect("--max-versions-per-crate must be a number");
+            self.max_versions_per_crate = Some(max);
+        }
+        if let Some(file) = matches.value_of("policy") {
+            let policy = Policy::read(Path::new(file)).unwrap_or_else(|error| panic!("failed to read --policy: {}", error));
+            self.policy = Some(policy);
+        }
+        if let Some(file) = matches.value_of("crate-file") {
+            // neither the index nor the filters apply to a local crate
+            let krate = manifest::crate_file_package(Path::new(file))
+                .unwrap_or_else(|error| panic!("failed to read --crate-file: {}", error));
+            message!("Processing {}@{} from {}", krate.name, krate.version, file);
+            self.list = vec![krate];
+            self.crate_file = Some(PathBuf::from(file));
+            checkpoint::set_total(1);
+            run_record::start(1);
+            return;
+        }
+        if matches.is_present("stream") {
+            if let Some(option) = LIST_OPTIONS.iter().find(|&&option| matches.is_present(option)) {
+                panic!("--stream cannot be combined with --{}, which needs the whole list", option);
+            }
+            // the number of crates is only known at the end
+            self.stream = true;
+            self.only_missing = matches.is_present("only-missing");
+            run_record::start(0);
+            return;
+        }
+        if let Some(root) = matches.value_of("closure") {
+            if let Err(error) = self.read_closure(root, closure_kinds(matches)) {
+                eprintln!("Error resolving the closure of {}: {}", root, error);
+                std::process::exit(1);
+            }
+        } else {
+            match matches.values_of("crate") {
+                Some(specs) => self.read_crates(specs),
+                None if !self.latest_of.is_empty() => {
+                    let names = self.latest_of;
+                    self.read_latest(names)
+                }
+                None => self.read(),
+            }
+        }
+        if let Some(file) = matches.value_of("from-list") {
+            let mut list = CrateList::read(Path::new(file)).expect("failed to read --from-list");
+            if let Some(expand) = matches.value_of("expand") {
+                let expand: Expand = expand.parse().unwrap();
+                let limit = matches
+                    .value_of("expand-limit")
+                    .map_or(1000, |v| v.parse().expect("--expand-limit must be a number"));
+                list.names = selection::expand(&list.names, &self.dependencies, expand, limit);
+            }
+            self.list
+                .retain(|krate| list.contains(&krate.name, &krate.version));
+        }
+        if let Some(wanted) = matches.values_of("category") {
+            let wanted = wanted.map(|v| v.to_string()).collect::<Vec<_>>();
+            let source = matches
+                .value_of("category-source")
+                .map(|v| v.to_string())
+                .or_else(|| config_value("crates", "db_dump"))
+                .expect("--category needs --category-source or [crates] db_dump");
+            let categories = Categories::read(Path::new(&source)).expect("failed to read the crates.io database dump");
+            let mut uncategorized = BTreeSet::new();
+            self.list.retain(|krate| match categories.of(&krate.name) {
+                Some(of) => categories::matches(of, &wanted),
+                None => {
+                    uncategorized.insert(krate.name.clone());
+                    false
+                }
+            });
+            for name in &uncategorized {
+                progress!("no category: {}", name);
+            }
+            message!(
+                "{} crates in categories {:?}, {} crates without category left out",
+                self.list.len(),
+                wanted,
+                uncategorized.len()
+            );
+        }
+        if let Some(logins) = matches.values_of("owner") {
+            let logins = logins.collect::<Vec<_>>();
+            let source = matches
+                .value_of("owner-source")
+                .map(|v| v.to_string())
+                .or_else(|| config_value("crates", "db_dump"))
+                .unwrap_or_else(|| "api".to_string());
+            let max_age = stop::parse_duration(matches.value_of("owner-max-age").unwrap()).unwrap();
+            let mut owned = BTreeSet::new();
+            for login in &logins {
+                let of = owners::crates_of(login, &source, max_age)
+                    .unwrap_or_else(|error| panic!("failed to list the crates of {}: {}", login, error));
+                progress!("{} crates owned by {}", of.crates.len(), login);
+                owned.extend(of.crates);
+            }
+            self.list.retain(|krate| owned.contains(&krate.name));
+            message!("{} crates of owners {:?}", self.list.len(), logins);
+        }
+        if let Some(edition) = matches.value_of("edition") {
+            // the edition is not part of the index, only downloaded crates can match
+            self.list.retain(|krate| {
+                manifest::read(&krate.dir())
+                    .map(|m| manifest::edition(&m) == edition)
+                    .unwrap_or(false)
+            });
+        }
+        if let Some(max) = self.max_crate_bytes {
+            if let Some(dump) = config_value("crates", "db_dump") {
+                self.skip_oversized(Path::new(&dump), max);
+            }
+        }
+        if matches.is_present("only-missing") {
+            let before = self.list.len();
+            self.list.retain(|krate| !krate.dir().exists());
+            progress!("{} of {} crates have no sources on disk", self.list.len(), before);
+        }
+        let order = matches
+            .value_of("order")
+            .map(|v| v.parse().unwrap())
+            .unwrap_or(Order::Index);
+        let seed = matches
+            .value_of("seed")
+            .map(|v| v.parse::<u64>().expect("seed must be an unsigned integer"));
+        if let Some(sample) = matches.value_of("sample") {
+            self.sample(sample.parse().expect("--sample must be a number"), seed);
+        }
+        self.sort(order, seed);
+        if let Some(batch) = matches.value_of("batch") {
+            let batch: usize = batch.parse().expect("--batch must be a number");
+            assert!(batch > 0, "--batch must be at least 1");
+            let offset: usize = matches
+                .value_of("offset")
+                .map_or(0, |v| v.parse().expect("--offset must be a number"));
+            let total = self.list.len();
+            let start = (offset * batch).min(total);
+            let end = (start + batch).min(total);
+            self.list.truncate(end);
+            self.list.drain(..start);
+            message!("Batch {} ({} crates each): crates {}..{} of {}", offset, batch, start, end, total);
+        }
+        if matches.is_present("include-deps-sources") {
+            // building a crate needs its build dependencies as well
+            self.add_dependency_sources(closure::Kinds {
+                build: true,
+                dev: matches.is_present("include-dev-deps"),
+            });
+        }
+        checkpoint::set_total(self.list.len());
+        run_record::start(self.list.len());
+    }
+
+    /// Drop the crates whose `.crate` file is larger than `max` bytes as per
+    /// the database dump in `dump`, before anything is downloaded.
+    fn skip_oversized(&mut self, dump: &Path, max: u64) {
+        let sizes = crate_sizes::read(dump).expect("failed to read the crates.io database dump");
+        let before = self.list.len();
+        let mut oversized = Vec::new();
+        self.list.retain(|krate| match sizes.get(&(krate.name.clone(), krate.version.clone())) {
+            Some(&bytes) if bytes > max => {
+                progress!("too large ({} bytes) skipped: {:?}", bytes, krate);
+                oversized.push(
+                    Entry::new(krate, "download", Status::Skipped)
+                        .with("reason", "too large")
+                        .with("crate_bytes", bytes)
+                        .with("size_source", "db-dump"),
+                );
+                false
+            }
+            _ => true,
+        });
+        message!("{} of {} crates larger than --max-crate-size left out", before - self.list.len(), before);
+        self.oversized.lock().unwrap().extend(oversized);
+    }
+
+    /// Add the versions in the dependency closures of the listed crates that
+    /// are not listed yet, after them, for `download --include-deps-sources`.
+    fn add_dependency_sources(&mut self, kinds: closure::Kinds) {
+        let index = self.index();
+        let closures = self
+            .list
+            .par_iter()
+            .filter_map(|krate| match closure::resolve(&index, &krate.name, &krate.version, kinds) {
+                Ok(closure) => Some(closure),
+                Err(error) => {
+                    eprintln!("Error resolving the dependencies of {:?}: {}", krate, error);
+                    None
+                }
+            }).collect::<Vec<_>>();
+        let selected = self.list.len();
+        let mut listed = self.list.iter().cloned().collect::<HashSet<_>>();
+        let mut conflicts = 0;
+        for closure in closures {
+            conflicts += closure.conflicts.len();
+            for dependency in closure.crates {
+                let krate = PraziCrate {
+                    name: dependency.name,
+                    version: dependency.version,
+                };
+                if listed.insert(krate.clone()) {
+                    self.checksums
+                        .insert((krate.name.clone(), krate.version.clone()), dependency.checksum);
+                    self.list.push(krate);
+                }
+            }
+        }
+        message!(
+            "Added {} dependency versions of {} crates, {} conflicts",
+            self.list.len() - selected,
+            selected,
+            conflicts
+        );
+    }
+
+    /// Keep `n` crates of the list picked at random, in their order.
+    fn sample(&mut self, n: usize, seed: Option<u64>) {
+        let total = self.list.len();
+        let amount = n.min(total);
+        let mut picked = match seed {
+            Some(seed) => rand::seq::index::sample(&mut StdRng::seed_from_u64(seed), total, amount),
+            None => rand::seq::index::sample(&mut rand::thread_rng(), total, amount),
+        }.into_vec();
+        picked.sort();
+        self.list = picked.into_iter().map(|i| self.list[i].clone()).collect();
+        let names = self
+            .list
+            .iter()
+            .map(|krate| format!("{}@{}", krate.name, krate.version))
+            .collect::<Vec<_>>();
+        message!("Sampled {} of {} crates: {}", amount, total, names.join(" "));
+    }
+
+    fn sort(&mut self, order: Order, seed: Option<u64>) {
+        match order {
+            Order::Index => {}
+            Order::Name => self.list.sort(),
+            Order::Popularity => {
+                let reverse_deps = &self.reverse_deps;
+                // stable sort, so equally popular crates keep their index order
+                self.list.sort_by(|a, b| {
+                    let rank_a = reverse_deps.get(&a.name).unwrap_or(&0);
+                    let rank_b = reverse_deps.get(&b.name).unwrap_or(&0);
+                    rank_b.cmp(rank_a)
+                });
+            }
+            Order::Downloads => {
+                let downloads = downloads::Downloads::read()
+                    .expect("--order downloads needs the counts of prazi ingest-downloads")
+                    .crates;
+                self.list.sort_by(|a, b| {
+                    let rank_a = downloads.get(&a.name).unwrap_or(&0);
+                    let rank_b = downloads.get(&b.name).unwrap_or(&0);
+                    rank_b.cmp(rank_a)
+                });
+            }
+            Order::Random => {
+                if let Some(seed) = seed {
+                    self.list.shuffle(&mut StdRng::seed_from_u64(seed));
+                } else {
+                    self.list.shuffle(&mut rand::thread_rng());
+                }
+            }
+        }
+    }
+
+    fn download_src(&self) -> PraziResult<()> {
+        if !disk::has_room("download") {
+            return Ok(());
+        }
+        if let Some(ref file) = self.crate_file {
+            return self.unpack_crate_file(file);
+        }
+        if self.stream {
+            let index = self.index();
+            let only_missing = self.only_missing;
+            let crates = index
+                .crates()
+                .flat_map(|krate| self.select(&krate))
+                .filter(|&(ref krate, _)| !only_missing || !krate.dir().exists())
+                .map(|(krate, checksum)| (krate, Some(checksum)));
+            return self.download(crates);
+        }
+        let crates = self.list.iter().map(|krate| {
+            let checksum = self
+                .checksums
+                .get(&(krate.name.clone(), krate.version.clone()))
+                .cloned();
+            (krate.clone(), checksum)
+        });
+        self.download(crates)
+    }
+
+    /// Download `crates`, each with the checksum of the index if known, as
+    /// the iterator yields them.
+    fn download<I: Iterator<Item = (PraziCrate, Option<String>)>>(&self, crates: I) -> PraziResult<()> {
+        let mut core = tokio_core::reactor::Core::new()?;
+        let handle = core.handle();
+        let client = fetch::client()?;
+        let checksum_algos = &self.checksum_algos;
+        let keep_archive = self.keep_archive;
+        let max_crate_bytes = self.max_crate_bytes;
+        let oversized = &self.oversized;
+        // downloads start `[download] request_delay` apart
+        let mut next_start = Instant::now();
+        let total = Cell::new(0);
+        let responses = stream::iter_ok(crates)
+            .map(|(krate, cksum)| {
+                total.set(total.get() + 1);
+                if stop::should_stop() {
+                    return None;
+                }
+                let success_file = krate.success_file();
+                let downloaded = path_exists(&success_file);
+                if downloaded && !self.revalidate {
+                    return None;
+                }
+                let mut conditional = Vec::new();
+                if downloaded {
+                    // published crates are immutable, so a 304 means we are current
+                    if let Some(provenance) = Provenance::read(&krate) {
+                        if let Some(etag) = provenance.etag {
+                            conditional.push((IF_NONE_MATCH, etag));
+                        }
+                        if let Some(last_modified) = provenance.last_modified {
+                            conditional.push((IF_MODIFIED_SINCE, last_modified));
+                        }
+                    }
+                }
+                let in_flight = InFlight::new();
+                let failed_krate = krate.clone();
+                let part = krate.partial_file();
+                if let Err(error) = fs::create_dir_all(krate.dir_src()) {
+                    eprintln!("Error creating: {:?} {:?}", krate.dir_src(), error);
+                }
+                let local = self
+                    .source_dir
+                    .as_ref()
+                    .map(|dir| dir.join(format!("{}.crate", krate.archive_root())))
+                    .filter(|file| file.exists());
+                let source_url = match local {
+                    Some(ref file) => format!("file://{}", fs::canonicalize(file).unwrap_or_else(|_| file.clone()).display()),
+                    None => krate.url_src(),
+                };
+                let source_dir_only = self.source_dir_only;
+                let start = match *fetch::REQUEST_DELAY {
+                    // local files are not requested from the registry
+                    Some(delay) if local.is_none() => {
+                        let start = std::cmp::max(next_start, Instant::now());
+                        next_start = start + delay;
+                        start
+                    }
+                    _ => Instant::now(),
+                };
+                let (client, url, fetch_part) = (client.clone(), krate.url_src(), part.clone());
+                Some(future::result(Timeout::new_at(start, &handle))
+                    .flatten()
+                    .map_err(|error| error.to_string())
+                    .and_then(move |()| -> Box<Future<Item = Fetched, Error = String>> {
+                        match local {
+                            Some(file) => Box::new(future::ok(fetch::copy_local(&file, &fetch_part))),
+                            None if source_dir_only => {
+                                Box::new(future::ok(Fetched::Failed("not in --source-dir".to_string())))
+                            }
+                            None => fetch::fetch(&client, url, fetch_part, conditional, max_crate_bytes),
+                        }
+                    })
+                    .map(move |fetched| -> Status {
+                        // a panic while unpacking or recording one crate must not end the
+                        // downloads of the others
+                        let res = panic::catch_unwind(AssertUnwindSafe(|| -> Status {
+                            let mut provenance = match fetched {
+                                Fetched::NotModified => {
+                                    progress!("Up to date: {:?}", &krate.url_src());
+                                    return Status::Skipped;
+                                }
+                                Fetched::TooLarge(bytes) => {
+                                    progress!("too large ({} bytes) skipped: {:?}", bytes, krate);
+                                    oversized.lock().unwrap().push(
+                                        Entry::new(&krate, "download", Status::Skipped)
+                                            .with("reason", "too large")
+                                            .with("crate_bytes", bytes)
+                                            .with("size_source", "content-length"),
+                                    );
+                                    return Status::Skipped;
+                                }
+                                Fetched::Failed(error) => {
+                                    eprintln!("Error downloading: {:?} {}", krate, error);
+                                    metrics::inc(&METRICS.downloads_failed);
+                                    report::record_failure(
+                                        &Entry::new(&krate, "download", Status::Failed).with("error", error),
+                                    );
+                                    return Status::Failed;
+                                }
+                                Fetched::Complete { provenance, resumed } => {
+                                    if resumed {
+                                        progress!("Resumed download: {:?}", &krate.url_src());
+                                    }
+                                    provenance
+                                }
+                            };
+                            // the body is hashed and extracted from the partial file, never
+                            // held in memory as a whole
+                            let sha256 = match Algo::Sha256.digest_file(&part) {
+                                Ok(sha256) => sha256,
+                                Err(error) => {
+                                    eprintln!("Error reading: {:?} {:?}", part, error);
+                                    remove_partial(&part);
+                                    metrics::inc(&METRICS.downloads_failed);
+                                    report::record_failure(
+                                        &Entry::new(&krate, "download", Status::Failed)
+                                            .with("error", error.to_string()),
+                                    );
+                                    return Status::Failed;
+                                }
+                            };
+                            if let Some(cksum) = cksum {
+                                if sha256 != cksum {
+                                    eprintln!("Checksum mismatch: {:?}", krate);
+                                    remove_partial(&part);
+                                    metrics::inc(&METRICS.downloads_failed);
+                                    report::record_failure(
+                                        &Entry::new(&krate, "download", Status::Failed).with(
+                                            "error",
+                                            format!("checksum mismatch: expected {}, got {}", cksum, sha256),
+                                        ),
+                                    );
+                                    return Status::Failed;
+                                }
+                            }
+                            let unpacked = File::open(&part)
+                                .map_err(|error| error.into())
+                                .and_then(|archive| unpack_crate(&krate, BufReader::new(archive)));
+                            let unpacked = match unpacked {
+                                Ok(unpacked) => unpacked,
+                                Err(error) => {
+                                    remove_partial(&part);
+                                    remove_unpacked(&krate);
+                                    if disk::is_full_output(&error.to_string()) {
+                                        disk::full(&krate.dir());
+                                    }
+                                    eprintln!("Error unpacking: {:?} {:?}", krate.dir(), error);
+                                    metrics::inc(&METRICS.downloads_failed);
+                                    report::record_failure(
+                                        &Entry::new(&krate, "download", Status::Failed)
+                                            .with("error", error.to_string()),
+                                    );
+                                    return Status::Failed;
+                                }
+                            };
+                            progress!("Untared: {:?}", &source_url);
+                            metrics::inc(&METRICS.downloads_completed);
+                            let timestamp = Utc::now();
+                            provenance.url = source_url;
+                            provenance.downloaded_at = timestamp.to_rfc3339();
+                            record_unpacked(&mut provenance, &krate, unpacked, &part, &sha256, checksum_algos);
+                            if !keep_archive {
+                                remove_partial(&part);
+                            } else if let Err(error) = rename(&part, &krate.archive_file()) {
+                                eprintln!("Error keeping: {:?} {:?}", part, error);
+                            }
+                            if let Err(error) = provenance.write(&krate) {
+                                disk::check(&krate.provenance_file(), &error);
+                                eprintln!("Error writing provenance: {:?} {:?}", krate, error);
+                            }
+                            if let Err(error) = fs::write(
+                                &success_file,
+                                format!("{}", timestamp.format("%Y-%m-%d %H:%M:%S"))
+                            ) {
+                                disk::check(&success_file, &error);
+                                eprintln!("Error writing: {:?} {:?}", success_file, error);
+                                report::record_failure(
+                                    &Entry::new(&krate, "download", Status::Failed)
+                                        .with("error", error.to_string()),
+                                );
+                                return Status::Failed;
+                            }
+                            Status::Ok
+                        }));
+                        res.unwrap_or_else(|payload| {
+                            remove_partial(&part);
+                            remove_unpacked(&krate);
+                            eprintln!("Panic downloading: {:?}", krate);
+                            metrics::inc(&METRICS.downloads_failed);
+                            report::record_failure(&report::panic_entry(&krate, "download", &*payload));
+                            Status::Failed
+                        })
+                    })
+                    .then(move |res: Result<Status, String>| -> Result<(), String> {
+                        drop(in_flight);
+                        checkpoint::record(&failed_krate, "download", None);
+                        let status = res.unwrap_or_else(|error| {
+                            eprintln!("Error downloading: {:?}", error);
+                            metrics::inc(&METRICS.downloads_failed);
+                            report::record_failure(
+                                &Entry::new(&failed_krate, "download", Status::Failed)
+                                    .with("error", error),
+                            );
+                            Status::Failed
+                        });
+                        summary::result(&failed_krate, "download", status);
+                        Ok(())
+                    })
+                )
+            }).buffer_unordered(self.download_concurrency);
+        let work = responses.for_each(|_| Ok(()));
+        core.run(work)?;
+        checkpoint::finish("download");
+        report::flush_failures();
+        if self.max_crate_bytes.is_some() {
+            let skipped = self.oversized.lock().unwrap();
+            if let Err(error) = report::write_named("oversized", "download", &skipped) {
+                eprintln!("Error writing oversized report: {:?}", error);
+            }
+        }
+        summary::print(
+            "download",
+            total.get(),
+            METRICS.downloads_completed.load(Ordering::SeqCst),
+            None,
+            METRICS.downloads_failed.load(Ordering::SeqCst),
+            METRICS.bytes_downloaded.load(Ordering::SeqCst),
+        );
+        Ok(())
+    }
+
+    /// Unpack the `--crate-file` like a downloaded crate, with a provenance
+    /// naming the file.
+    fn unpack_crate_file(&self, file: &Path) -> PraziResult<()> {
+        let krate = match self.list.first() {
+            Some(krate) => krate,
+            // already unpacked, with `--resume`
+            None => return Ok(()),
+        };
+        let unpacked = unpack_crate(krate, BufReader::new(File::open(file)?)).map_err(|error| {
+            remove_unpacked(krate);
+            error
+        })?;
+        progress!("Untared: {:?}", file);
+        let timestamp = Utc::now();
+        let mut provenance = Provenance {
+            url: format!("file://{}", fs::canonicalize(file)?.display()),
+            downloaded_at: timestamp.to_rfc3339(),
+            ..Provenance::default()
+        };
+        let sha256 = Algo::Sha256.digest_file(file)?;
+        record_unpacked(&mut provenance, krate, unpacked, file, &sha256, &self.checksum_algos);
+        provenance.write(krate)?;
+        fs::write(
+            krate.success_file(),
+            format!("{}", timestamp.format("%Y-%m-%d %H:%M:%S")),
+        )?;
+        Ok(())
+    }
+
+    /// Record the dependency graph cargo resolves for every crate in its
+    /// `resolved.json`, with its direct dependencies in the report.
+    fn resolve_dependencies(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "resolve", || {
+                if !krate.dir().exists() {
+                    return None;
+                }
+                let graph = match self.cargo_metadata(krate) {
+                    Ok(ref metadata) => ResolvedGraph::from_metadata(metadata),
+                    Err(stderr) => {
+                        progress!("cargo metadata failed: {:?}", krate);
+                        let entry = Entry::new(krate, "resolve", Status::Failed)
+                            .with("error_category", unbuildable::categorize(&stderr))
+                            .with("stderr", report::tail(&stderr));
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                };
+                let graph = match graph {
+                    Some(graph) => graph,
+                    None => {
+                        // e.g. `cargo metadata --no-deps` output, or a cargo without `resolve`
+                        let entry = Entry::new(krate, "resolve", Status::Failed)
+                            .with("error", "no resolve in the cargo metadata output");
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                };
+                let file = krate.resolved_file();
+                let res = fs::create_dir_all(file.parent().unwrap())
+                    .and_then(|()| fs::write(&file, Envelope::new("resolved", &graph).to_json()));
+                if let Err(error) = res {
+                    eprintln!("Error writing {:?}: {:?}", file, error);
+                }
+                Some(Entry::new(krate, "resolve", Status::Ok)
+                    .with("packages", graph.packages.len())
+                    .with("dependencies", graph.direct())
+                    .with("duplicates", graph.duplicates()))
+            })).collect::<Vec<_>>();
+        write_report("resolve", &entries);
+    }
+
+    fn validate_manifests(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "validate", || {
+                let dir = krate.dir();
+                if !dir.exists() {
+                    return None;
+                }
+                if !self.refresh && manifest::cached(krate).is_some() {
+                    let entry = Entry::new(krate, "validate", Status::Ok).with("cached", true);
+                    return Some(with_source_details(krate, entry));
+                }
+                let output = match children::output(
+                    "cargo read-manifest",
+                    cargo_command().arg("read-manifest").current_dir(&dir),
+                ) {
+                    Ok(output) => output,
+                    Err(error) => {
+                        eprintln!("Error running read-manifest: {:?} {:?}", krate, error);
+                        return Some(Entry::new(krate, "validate", Status::Failed)
+                            .with("error", error.to_string()));
+                    }
+                };
+
+                let entry = if output.status.success() {
+                    if let Err(error) = manifest::store(krate, &output.stdout) {
+                        eprintln!("Error caching manifest: {:?} {:?}", krate, error);
+                    }
+                    Entry::new(krate, "validate", Status::Ok)
+                } else {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let category = if manifest::is_too_new(&stderr) {
+                        eprintln!("Warning: the manifest of {:?} needs a newer cargo, consider upgrading", krate);
+                        "manifest-too-new"
+                    } else {
+                        progress!("Not valid manifest");
+                        "invalid"
+                    };
+                    progress!("stderr: {}", stderr);
+                    Entry::new(krate, "validate", Status::Failed)
+                        .with("error_category", category)
+                        .with("stderr", report::tail(&stderr))
+                };
+                Some(with_source_details(krate, entry))
+            })).collect::<Vec<_>>();
+        write_report("validate", &entries);
+    }
+
+    fn rewrite_manifests(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "rewrite", || {
+                let dir = krate.dir();
+                if !dir.exists() || dir.join("Cargo.toml.orig").exists() {
+                    return None;
+                }
+                // the dry-run publish is expensive and only needed to drop path dependencies
+                let path_deps = manifest::read(&dir).ok().map(|m| manifest::path_dependencies(&m));
+                if path_deps.as_ref().map_or(false, BTreeSet::is_empty) {
+                    return Some(Entry::new(krate, "rewrite", Status::Skipped)
+                        .with("reason", "no rewrite needed"));
+                }
+                let original = fs::read_to_string(dir.join("Cargo.toml")).unwrap_or_default();
+                let mut attempts = 0;
+                let output = loop {
+                    attempts += 1;
+                    let output = match children::output(
+                        "cargo publish",
+                        cargo_command()
+                            .arg("publish")
+                            .args(&["--no-verify", "--dry-run", "--allow-dirty"])
+                            .current_dir(&dir),
+                    ) {
+                        Ok(output) => output,
+                        Err(error) => {
+                            eprintln!("Error running dry-run publish: {:?} {:?}", krate, error);
+                            return Some(Entry::new(krate, "rewrite", Status::Failed)
+                                .with("error", error.to_string())
+                                .with("attempts", attempts));
+                        }
+                    };
+                    if output.status.success() {
+                        break output;
+                    }
+                    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+                    if !is_transient_publish_failure(&stderr) {
+                        progress!("Package not publishable with the running Cargo version");
+                        return Some(Entry::new(krate, "rewrite", Status::Failed)
+                            .with("failure", "not publishable")
+                            .with("stderr", report::tail(&stderr))
+                            .with("attempts", attempts));
+                    }
+                    if attempts > self.publish_retries || stop::should_stop() {
+                        eprintln!("Giving up dry-run publish after {} attempts: {:?}", attempts, krate);
+                        return Some(Entry::new(krate, "rewrite", Status::Failed)
+                            .with("failure", "transient")
+                            .with("stderr", report::tail(&stderr))
+                            .with("attempts", attempts));
+                    }
+                    let delay = PUBLISH_BACKOFF * 2u32.pow(attempts - 1);
+                    eprintln!(
+                        "Transient dry-run publish failure, retrying in {:?}: {:?}",
+                        delay, krate
+                    );
+                    std::thread::sleep(delay);
+                };
+                let new_file = dir
+                    .join("target")
+                    .join("package")
+                    .join(format!("{0}-{1}.crate", krate.name, krate.version));
+                if !new_file.exists() {
+                    return None;
+                }
+                let res: PraziResult<Unpacked> = File::open(&new_file)
+                    .map_err(|e| e.into())
+                    .and_then(|data| unpack_crate(&krate, data));
+                Some(match res {
+                    Ok(_) => {
+                        progress!("Repackaged: {:?}", &krate.url_src());
+                        let rewritten = fs::read_to_string(dir.join("Cargo.toml")).unwrap_or_default();
+                        let diff_file = manifest::rewrite_diff_file(&dir);
+                        if let Err(error) = fs::write(&diff_file, manifest::rewrite_diff(&original, &rewritten)) {
+                            eprintln!("Error writing {:?}: {:?}", diff_file, error);
+                        }
+                        let remaining = manifest::read(&dir)
+                            .map(|m| manifest::path_dependencies(&m))
+                            .unwrap_or_default();
+                        let removed = path_deps
+                            .unwrap_or_default()
+                            .difference(&remaining)
+                            .cloned()
+                            .collect::<BTreeSet<_>>();
+                        Entry::new(krate, "rewrite", Status::Ok)
+                            .with("attempts", attempts)
+                            .with("path_dependencies_removed", removed)
+                    }
+                    Err(error) => {
+                        eprintln!("Error repackaging: {:?} {:?}", krate, error);
+                        let entry = Entry::new(krate, "rewrite", Status::Failed)
+                            .with("error", error.to_string());
+                        report::record_failure(&entry);
+                        entry
+                    }
+                })
+            })).collect::<Vec<_>>();
+        write_report("rewrite", &entries);
+        let removed = entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .details
+                    .get("path_dependencies_removed")
+                    .and_then(|removed| removed.as_array())
+                    .map_or(false, |removed| !removed.is_empty())
+            }).count();
+        message!("Path dependencies removed from {} of {} crates", removed, entries.len());
+    }
+
+    /// `RUSTFLAGS` of the builds: the ones of the environment, then
+    /// `[compiler] rustflags`, then the flags of the command line options.
+    fn effective_rustflags(&self) -> Option<String> {
+        let mut flags = self
+            .build_env
+            .get("RUSTFLAGS")
+            .cloned()
+            .or_else(|| std::env::var("RUSTFLAGS").ok())
+            .into_iter()
+            .collect::<Vec<_>>();
+        flags.extend(self.rustflags.clone());
+        if self.single_codegen_unit {
+            flags.push("-C codegen-units=1".to_string());
+        }
+        if self.with_debuginfo {
+            flags.push("-C debuginfo=1".to_string());
+        }
+        if self.emit_mir {
+            // adds to the `--emit` of cargo, the bitcode is still emitted
+            flags.push("--emit=mir".to_string());
+        }
+        let flags = flags.join(" ").trim().to_string();
+        if flags.is_empty() {
+            None
+        } else {
+            Some(flags)
+        }
+    }
+
+    /// `cargo args` for `krate` in `dir`, its folder or the copy it is built
+    /// in, with the shared `CARGO_HOME` and the build environment, run in the
+    /// `[build] sandbox` if there is one.
+    fn cargo(&self, krate: &PraziCrate, dir: &Path, args: &[String]) -> Command {
+        let mut cmd = match self.sandbox {
+            Some(ref sandbox) => {
+                let mut cmd = sandbox.command(krate, dir, "cargo", args);
+                if let Some(cargo_home) = config_cargo_home() {
+                    cmd.env("CARGO_HOME", cargo_home);
+                }
+                cmd
+            }
+            None => {
+                let mut cmd = cargo_command();
+                cmd.args(args);
+                cmd
+            }
+        };
+        cmd.current_dir(dir).envs(&self.build_env);
+        cmd
+    }
+
+    /// `cargo build` of `krate` in `dir` with the configured profile and
+    /// `rustflags`, followed by `extra_args`, see `cargo`.
+    fn cargo_build(
+        &self,
+        krate: &PraziCrate,
+        dir: &Path,
+        rustflags: &Option<String>,
+        extra_args: &[String],
+    ) -> Command {
+        let mut args = vec!["build".to_string()];
+        if self.profile == Profile::Release {
+            args.push("--release".to_string());
+        }
+        args.extend(self.cargo_flags.iter().map(|flag| format!("--{}", flag)));
+        args.extend(targets::selected().cargo_args());
+        args.extend(extra_args.iter().cloned());
+        let mut cmd = self.cargo(krate, dir, &args);
+        if RUN_LABEL.read().unwrap().is_some() {
+            cmd.env("CARGO_TARGET_DIR", krate.target_dir(FeatureSet::Default));
+        }
+        if let Some(ref flags) = *rustflags {
+            cmd.env("RUSTFLAGS", flags);
+        }
+        cmd
+    }
+
+    /// The output of `cargo metadata` for `krate`, its dependency graph; the
+    /// output of cargo when it failed. Cargo resolves and fetches the
+    /// dependencies as for a build, so it runs like one, see `cargo`.
+    fn cargo_metadata(&self, krate: &PraziCrate) -> Result<serde_json::Value, String> {
+        let mut args = vec!["metadata".to_string(), "--format-version".to_string(), "1".to_string()];
+        args.extend(self.cargo_flags.iter().map(|flag| format!("--{}", flag)));
+        let mut cmd = self.cargo(krate, &krate.dir(), &args);
+        let output = children::output("cargo metadata", &mut cmd).map_err(|e| e.to_string())?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).into_owned());
+        }
+        serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())
+    }
+
+    /// `name@version` of the packages with a build script among `krate` and
+    /// its dependencies, from `cargo metadata`; the output of cargo when it
+    /// failed.
+    fn build_script_packages(&self, krate: &PraziCrate) -> Result<Vec<String>, String> {
+        let metadata = self.cargo_metadata(krate)?;
+        let packages = metadata["packages"].as_array().cloned().unwrap_or_default();
+        Ok(packages
+            .iter()
+            .filter(|package| {
+                package["targets"].as_array().map_or(false, |targets| {
+                    targets.iter().any(|target| {
+                        target["kind"]
+                            .as_array()
+                            .map_or(false, |kinds| kinds.iter().any(|kind| kind == "custom-build"))
+                    })
+                })
+            }).filter_map(|package| {
+                Some(format!("{}@{}", package["name"].as_str()?, package["version"].as_str()?))
+            }).collect())
+    }
+
+    /// The skipped entry of `phase` when the sources of `krate` exceed
+    /// `--max-source-bytes`.
+    fn skip_too_large(&self, krate: &PraziCrate, phase: &str) -> Option<Entry> {
+        let max = self.max_source_bytes?;
+        let bytes = match source_size::source_bytes(&krate.dir()) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                eprintln!("Error measuring the sources of {:?}: {:?}", krate, error);
+                return None;
+            }
+        };
+        if bytes <= max {
+            return None;
+        }
+        progress!("too large ({} bytes) skipped: {:?}", bytes, krate);
+        Some(Entry::new(krate, phase, Status::Skipped)
+            .with("reason", "too large")
+            .with("source_bytes", bytes))
+    }
+
+    /// Build the crate once for every set of `--feature-matrix`, recording
+    /// the outcome of each build by feature set.
+    fn feature_builds(
+        &self,
+        krate: &PraziCrate,
+        dir: &Path,
+        rustflags: &Option<String>,
+    ) -> BTreeMap<&'static str, FeatureBuild> {
+        let mut builds = BTreeMap::new();
+        for &features in &self.feature_matrix {
+            let mut args = features
+                .cargo_args()
+                .iter()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>();
+            args.push("--target-dir".to_string());
+            args.push(krate.target_dir(features).to_string_lossy().into_owned());
+            let output = children::output("cargo build", &mut self.cargo_build(krate, dir, rustflags, &args));
+            let mut build = FeatureBuild::new(Status::Failed);
+            match output {
+                Ok(ref output) if output.status.success() => {
+                    build.status = Status::Ok;
+                    build.bitcode_files = Some(krate.feature_bitcode_paths(self.profile, features).len());
+                }
+                Ok(output) => {
+                    progress!("{} build failed: {:?}", features.name(), krate);
+                    build.stderr = Some(report::tail(&String::from_utf8_lossy(&output.stderr)));
+                }
+                Err(error) => {
+                    eprintln!("Error running cargo build: {:?} {:?}", krate, error);
+                    build.error = Some(error.to_string());
+                }
+            }
+            builds.insert(features.name(), build);
+        }
+        builds
+    }
+
+    /// Build the crates, `[compiler] jobs` at a time.
+    fn compile(&self) {
+        self.with_jobs(|| self.compile_crates())
+    }
+
+    /// The dependency-first order of the list for `--topo-order`, by the
+    /// normal and build dependencies of the downloaded manifests.
+    fn build_order(&self) -> topo::Order {
+        let names = self.list.iter().map(|krate| krate.name.as_str()).collect::<Vec<_>>();
+        let dependencies = self
+            .list
+            .par_iter()
+            .map(|krate| {
+                manifest::read(&krate.dir())
+                    .map(|m| manifest::build_dependencies(&m))
+                    .unwrap_or_default()
+            }).collect::<Vec<_>>();
+        let order = topo::order(&names, &dependencies);
+        for &i in &order.broken {
+            message!("Dependency cycle broken at {:?}, built without waiting for its dependencies", self.list[i]);
+        }
+        message!("Building {} crates in {} waves", self.list.len(), order.waves.len());
+        order
+    }
+
+    fn compile_crates(&self) {
+        if !disk::has_room("build") {
+            return;
+        }
+        if let Err(error) = check_cargo() {
+            eprintln!("{}; not building", error);
+            return;
+        }
+        report_cargo_home();
+        let rustflags = self.effective_rustflags();
+        if self.emit_mir && !rustc_is_nightly() {
+            // the MIR output is unstable, only nightlies are supported
+            eprintln!("--emit mir needs a nightly toolchain, but rustc is not nightly; not building");
+            return;
+        }
+        if let Some(ref flags) = rustflags {
+            // stable compilers reject `-Z` flags such as `-Z codegen-backend`,
+            // which would fail every build of the corpus
+            if has_unstable_flags(flags) && !rustc_is_nightly() {
+                eprintln!(
+                    "RUSTFLAGS {:?} need a nightly toolchain, but rustc is not nightly; not building",
+                    flags
+                );
+                return;
+            }
+            progress!("Building with RUSTFLAGS {:?}", flags);
+        }
+        let toolchain = rustc_version();
+        let rustc = rustc_verbose();
+        let targets = targets::selected();
+        let message_format = if self.json_messages {
+            vec!["--message-format=json".to_string()]
+        } else {
+            Vec::new()
+        };
+        let build = |krate: &PraziCrate| report::guard(krate, "build", || {
+            let dir = krate.dir();
+            if !dir.exists() {
+                return None;
+            }
+            let proc_macro = manifest::dir_is_proc_macro(&dir);
+            if proc_macro && self.skip_proc_macros {
+                progress!("proc-macro skipped: {:?}", krate);
+                return Some(Entry::new(krate, "build", Status::Skipped)
+                    .with("reason", "proc-macro")
+                    .with("proc_macro", true));
+            }
+            if let Some(entry) = self.skip_too_large(krate, "build") {
+                return Some(entry);
+            }
+            let marker = krate.build_marker();
+            let built_with = fs::read_to_string(&marker).ok();
+            if !self.force
+                && toolchain.is_some()
+                && built_with.as_ref().map(|v| v.trim()) == toolchain.as_ref().map(|v| v.as_str())
+                && krate.target_dir(FeatureSet::Default).exists()
+            {
+                progress!("already built: {:?}", krate);
+                return Some(Entry::new(krate, "build", Status::Skipped)
+                    .with("reason", "already built")
+                    .with("toolchain", &toolchain));
+            }
+            if built_with.is_some() {
+                // built with another toolchain, or rebuilt with --force
+                if let Err(error) = fs::remove_file(&marker) {
+                    eprintln!("Error deleting: {:?} {:?}", marker, error);
+                }
+            }
+            let cargo_toml = manifest::read(&dir).ok();
+            let build_script = cargo_toml
+                .as_ref()
+                .map_or(false, |m| manifest::has_build_script(&dir, m));
+            let target_kinds = cargo_toml
+                .as_ref()
+                .map_or(Vec::new(), |m| targets.built_kinds(&manifest::target_kinds(&dir, m)));
+            if targets.only_bins() && target_kinds.is_empty() {
+                progress!("no binary target skipped: {:?}", krate);
+                return Some(Entry::new(krate, "build", Status::Skipped)
+                    .with("reason", "no binary target")
+                    .with("targets", targets.name()));
+            }
+            if self.no_build_scripts {
+                match self.build_script_packages(krate) {
+                    Ok(ref packages) if !packages.is_empty() => {
+                        progress!("build script skipped: {:?}", krate);
+                        return Some(Entry::new(krate, "build", Status::Skipped)
+                            .with("reason", "build script")
+                            .with("build_script", build_script)
+                            .with("build_scripts", packages));
+                    }
+                    Ok(_) => {}
+                    Err(stderr) => {
+                        progress!("cargo metadata failed: {:?}", krate);
+                        metrics::inc(&METRICS.builds_failed);
+                        let entry = Entry::new(krate, "build", Status::Failed)
+                            .with("toolchain", &toolchain)
+                            .with("error_category", unbuildable::categorize(&stderr))
+                            .with("stderr", report::tail(&stderr));
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                }
+            }
+            let requires_lockfile = self.cargo_flags.iter().any(|&f| f == "locked" || f == "frozen");
+            if requires_lockfile && !dir.join("Cargo.lock").exists() {
+                // cargo would refuse to create the lockfile, do not bother running it
+                progress!("no Cargo.lock: {:?}", krate);
+                metrics::inc(&METRICS.builds_failed);
+                let entry = Entry::new(krate, "build", Status::Failed)
+                    .with("toolchain", &toolchain)
+                    .with("cargo_flags", &self.cargo_flags)
+                    .with("error_category", "missing-lockfile");
+                report::record_failure(&entry);
+                return Some(entry);
+            }
+            let copy = if self.out_of_tree_build {
+                match BuildCopy::new(&dir, krate.build_copy()) {
+                    Ok(copy) => Some(copy),
+                    Err(error) => {
+                        eprintln!("Error copying the sources of {:?}: {:?}", krate, error);
+                        metrics::inc(&METRICS.builds_failed);
+                        let entry = Entry::new(krate, "build", Status::Failed)
+                            .with("error", error.to_string())
+                            .with("out_of_tree", true);
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                }
+            } else {
+                None
+            };
+            let build_dir = copy.as_ref().map_or(dir.clone(), |copy| copy.dir.clone());
+            let _in_flight = InFlight::new();
+            let run = || {
+                let mut cmd = self.cargo_build(krate, &build_dir, &rustflags, &message_format);
+                // the target folder of a copy is moved back into the crate's folder
+                let build_tmp = krate
+                    .build_tmp()
+                    .or_else(|| copy.as_ref().map(|copy| copy.dir.join("target")));
+                if let Some(ref target) = build_tmp {
+                    // bring back the previous build, so cargo only rebuilds what changed
+                    let final_target = krate.target_dir(FeatureSet::Default);
+                    let res = fs::create_dir_all(target.parent().unwrap()).and_then(|()| {
+                        if final_target.exists() && !target.exists() {
+                            move_dir(&final_target, target)
+                        } else {
+                            Ok(())
+                        }
+                    });
+                    if let Err(error) = res {
+                        eprintln!("Error moving {:?} to {:?}: {:?}", final_target, target, error);
+                    }
+                    cmd.env("CARGO_TARGET_DIR", target);
+                }
+                let output = {
+                    let _running = children::Running::new("cargo build");
+                    rusage::output(&mut cmd)
+                };
+                match build_tmp {
+                    Some(ref target) if target.exists() => {
+                        let final_target = krate.target_dir(FeatureSet::Default);
+                        let res = if final_target.exists() {
+                            fs::remove_dir_all(&final_target)
+                        } else {
+                            Ok(())
+                        }.and_then(|()| fs::create_dir_all(final_target.parent().unwrap()))
+                        .and_then(|()| move_dir(target, &final_target));
+                        if let Err(error) = res {
+                            eprintln!("Error moving {:?} to {:?}: {:?}", target, final_target, error);
+                        }
+                    }
+                    _ => {}
+                }
+                output
+            };
+            let (output, usage) = match run() {
+                Ok(measured) => measured,
+                Err(error) => {
+                    eprintln!("Error running cargo build: {:?} {:?}", krate, error);
+                    metrics::inc(&METRICS.builds_failed);
+                    let entry = Entry::new(krate, "build", Status::Failed)
+                        .with("error", error.to_string());
+                    report::record_failure(&entry);
+                    return Some(entry);
+                }
+            };
+            // cargo prints the diagnostics as JSON on stdout, the report and log keep them rendered
+            let stderr_of = |output: &Output| {
+                let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+                if self.json_messages {
+                    stderr + &diagnostics::rendered(&String::from_utf8_lossy(&output.stdout))
+                } else {
+                    stderr
+                }
+            };
+            // a flaky build succeeds when run again, a deterministic failure fails again
+            let mut retry = None;
+            let mut first_stderr = None;
+            let (output, usage) = if !output.status.success() && self.retry_failed_once {
+                progress!("build failed, retrying once: {:?}", krate);
+                match run() {
+                    Ok(retried) => {
+                        if retried.0.status.success() {
+                            retry = Some("flaky");
+                            first_stderr = Some(stderr_of(&output));
+                        } else {
+                            retry = Some("deterministic-fail");
+                        }
+                        retried
+                    }
+                    Err(error) => {
+                        eprintln!("Error retrying cargo build: {:?} {:?}", krate, error);
+                        (output, usage)
+                    }
+                }
+            } else {
+                (output, usage)
+            };
+            let stderr = stderr_of(&output);
+            let diagnostics = if self.json_messages {
+                diagnostics::count_json(&String::from_utf8_lossy(&output.stdout))
+            } else {
+                diagnostics::count_text(&stderr)
+            };
+            let status = if output.status.success() {
+                progress!("build done!");
+                metrics::inc(&METRICS.builds_succeeded);
+                Status::Ok
+            } else {
+                progress!("build failed");
+                metrics::inc(&METRICS.builds_failed);
+                eprintln!("stderr: {}", stderr);
+                if disk::is_full_output(&stderr) {
+                    disk::full(&krate.target_dir(FeatureSet::Default));
+                }
+                Status::Failed
+            };
+            let mut entry = Entry::new(krate, "build", status)
+                .with("toolchain", &toolchain)
+                .with("rustc", &rustc)
+                .with("env", &self.build_env)
+                .with("rustflags", &rustflags)
+                .with("sandboxed", self.sandbox.is_some())
+                .with("out_of_tree", self.out_of_tree_build)
+                .with("cargo_flags", &self.cargo_flags)
+                .with("proc_macro", proc_macro)
+                .with("build_script", build_script)
+                .with("targets", targets.name())
+                .with("target_kinds", &target_kinds)
+                .with("codegen_units", if self.single_codegen_unit { Some(1) } else { None })
+                .with("bitcode_files", krate.bitcode_paths(self.profile).len())
+                .with("warnings", diagnostics.get("warning").cloned().unwrap_or(0))
+                .with("diagnostics", &diagnostics)
+                .with("usage", &usage);
+            if let (Some(rss), Some(max)) = (usage.max_rss_bytes, *MAX_RSS) {
+                if rss > max {
+                    progress!("peak memory of {} bytes: {:?}", rss, krate);
+                    entry = entry.with("rss_exceeded", true);
+                }
+            }
+            if self.emit_mir {
+                entry = entry.with("mir_files", krate.mir_paths(self.profile).len());
+            }
+            if status == Status::Failed {
+                entry = entry.with("stderr", report::tail(&stderr));
+                unbuildable::categorize_entry(&mut entry, &stderr);
+            }
+            if let Some(retry) = retry {
+                entry = entry.with("retry", retry);
+            }
+            if let Some(ref first_stderr) = first_stderr {
+                entry = entry
+                    .with("first_error_category", unbuildable::categorize(first_stderr))
+                    .with("first_stderr", report::tail(first_stderr));
+            }
+            if self.build_log {
+                let log = krate.build_log();
+                if let Err(error) = fs::write(&log, &stderr) {
+                    eprintln!("Error writing: {:?} {:?}", log, error);
+                }
+            }
+            if status == Status::Ok && !self.feature_matrix.is_empty() {
+                entry = entry.with("feature_builds", self.feature_builds(krate, &build_dir, &rustflags));
+            }
+            if let (Status::Ok, Some(hook)) = (status, self.post_build.as_ref()) {
+                // a failing hook does not fail the build, it is only reported
+                entry = match hook.run(krate) {
+                    Ok(output) => {
+                        if !output.success() {
+                            progress!("post_build hook failed: {:?}", krate);
+                        }
+                        entry.with("post_build", output)
+                    }
+                    Err(error) => {
+                        eprintln!("Error running post_build hook: {:?} {:?}", krate, error);
+                        entry.with("post_build_error", error.to_string())
+                    }
+                };
+            }
+            if status == Status::Failed {
+                report::record_failure(&entry);
+            } else if let Some(ref toolchain) = toolchain {
+                if let Err(error) = fs::write(&marker, toolchain) {
+                    disk::check(&marker, &error);
+                    eprintln!("Error writing: {:?} {:?}", marker, error);
+                }
+            }
+            Some(entry)
+        });
+        let entries = if self.topo_order {
+            let order = self.build_order();
+            order
+                .waves
+                .iter()
+                .enumerate()
+                .flat_map(|(wave, crates)| {
+                    crates
+                        .par_iter()
+                        .filter(|_| throttle::admit())
+                        .filter_map(|&i| {
+                            let entry = build(&self.list[i])?.with("wave", wave);
+                            Some(if order.broken.contains(&i) {
+                                entry.with("cycle_broken", true)
+                            } else {
+                                entry
+                            })
+                        }).collect::<Vec<_>>()
+                }).collect::<Vec<_>>()
+        } else {
+            self.list
+                .par_iter()
+                .filter(|_| throttle::admit())
+                .filter_map(&build)
+                .collect::<Vec<_>>()
+        };
+        write_report("build", &entries);
+        // one report per toolchain, merged by `unbuildable`
+        if let Some(version) = toolchain.as_ref().and_then(|v| v.split_whitespace().nth(1)) {
+            if let Err(error) = report::write_named(&format!("build-{}", version), "build", &entries) {
+                eprintln!("Error writing build report of {}: {:?}", version, error);
+            }
+        }
+    }
+
+    /// Extract the public API of every crate into `{dir}/prazi-api.json`.
+    fn export_api(&self) {
+        let nightly = config_value("compiler", "nightly").expect("no [compiler] nightly configured");
+        if let Err(error) = check_toolchain(&nightly) {
+            eprintln!("{}; not extracting APIs", error);
+            return;
+        }
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter(|krate| krate.dir().exists())
+            .filter_map(|krate| report::guard(krate, "api", || {
+                let items = match api::extract(krate, &nightly) {
+                    Ok(items) => items,
+                    Err(error) => {
+                        // e.g. crates without a library, or rustdoc choking on old code
+                        progress!("api extraction failed: {:?}", krate);
+                        let entry = Entry::new(krate, "api", Status::Failed)
+                            .with("error", report::tail(&error.to_string()));
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                };
+                let mut kinds = BTreeMap::new();
+                for item in &items {
+                    *kinds.entry(item.kind.clone()).or_insert(0) += 1;
+                }
+                let file = krate.dir().join("prazi-api.json");
+                let count = items.len();
+                if let Err(error) = fs::write(&file, Envelope::new("api", items).to_json()) {
+                    eprintln!("Error writing: {:?} {:?}", file, error);
+                    let entry = Entry::new(krate, "api", Status::Failed)
+                        .with("error", error.to_string());
+                    report::record_failure(&entry);
+                    return Some(entry);
+                }
+                progress!("api extracted: {:?}", krate);
+                Some(Entry::new(krate, "api", Status::Ok).with("items", count).with("kinds", kinds))
+            })).collect::<Vec<_>>();
+        write_report("api", &entries);
+    }
+
+    /// Write the features of every downloaded crate to `prazi-features.json`
+    /// in its folder.
+    fn export_features(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter(|krate| krate.dir().exists())
+            .filter_map(|krate| report::guard(krate, "features", || {
+                let features = match manifest::read(&krate.dir()) {
+                    Ok(cargo_toml) => manifest::features(&cargo_toml),
+                    Err(error) => {
+                        eprintln!("Error reading Cargo.toml: {:?} {:?}", krate, error);
+                        let entry = Entry::new(krate, "features", Status::Failed)
+                            .with("error", error.to_string());
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                };
+                let file = krate.dir().join("prazi-features.json");
+                if let Err(error) = fs::write(&file, Envelope::new("features", &features).to_json()) {
+                    eprintln!("Error writing: {:?} {:?}", file, error);
+                    let entry = Entry::new(krate, "features", Status::Failed)
+                        .with("error", error.to_string());
+                    report::record_failure(&entry);
+                    return Some(entry);
+                }
+                progress!("features exported: {:?}", krate);
+                Some(Entry::new(krate, "features", Status::Ok)
+                    .with("features", features.features.len())
+                    .with("optional_dependencies", features.optional_dependencies.len()))
+            })).collect::<Vec<_>>();
+        write_report("features", &entries);
+    }
+
+    /// Build the call graphs of the `bitcodes` of a `features` build, or
+    /// return the failure entry.
+    fn opt_graphs(
+        &self,
+        llvm_path: &str,
+        krate: &PraziCrate,
+        bitcodes: &[PathBuf],
+        features: FeatureSet,
+    ) -> Result<OptGraphs, Entry> {
+        let mut built = OptGraphs::default();
+        let linked = krate.linked_bitcode_file(features);
+        let bitcodes = if self.link_bitcode && bitcodes.len() > 1 {
+            if let Err(error) = llvm_link(llvm_path, bitcodes, &linked) {
+                if disk::is_full_output(&error.to_string()) {
+                    disk::full(&linked);
+                }
+                progress!("llvm-link failed: {:?}", krate);
+                let entry = Entry::new(krate, "callgraph", Status::Failed)
+                    .with("linked_bitcode_files", bitcodes.len())
+                    .with("stderr", report::tail(&error.to_string()));
+                report::record_failure(&entry);
+                return Err(entry);
+            }
+            std::slice::from_ref(&linked)
+        } else {
+            bitcodes
+        };
+        for bitcode in bitcodes {
+            let file = krate.feature_graph_file(bitcode, features);
+            let res = match self.resolve_passes {
+                Some(ref passes) => {
+                    // the edges only in the resolved graph are the resolved indirect calls
+                    let direct = file.with_extension("direct.dot");
+                    opt_callgraph(llvm_path, bitcode, &direct)
+                        .and_then(|_| opt_resolved_callgraph(llvm_path, bitcode, passes, &file))
+                        .and_then(|_| {
+                            let direct_edges = CallGraph::load(&direct)?.labeled_edges();
+                            let resolved = CallGraph::load(&file)?.labeled_edges();
+                            let mut lines = String::new();
+                            for &(ref from, ref to) in resolved.difference(&direct_edges) {
+                                lines.push_str(&format!("{}\t{}\n", from.trim(), to.trim()));
+                                built.indirect_edges += 1;
+                            }
+                            fs::write(krate.indirect_edges_file(&file), lines)?;
+                            fs::remove_file(&direct)?;
+                            Ok(())
+                        })
+                }
+                None => opt_callgraph(llvm_path, bitcode, &file),
+            };
+            let res = res.and_then(|_| {
+                let mut text = graph::read_text(&file)?;
+                if !self.keep_multi_edges {
+                    let (deduped, dropped) = graph::dedup_edges(&text);
+                    text = deduped;
+                    built.duplicate_edges += dropped;
+                }
+                if !self.feature_matrix.is_empty() {
+                    text = features::label_graph(&text, features);
+                }
+                built.edges += graph::edge_count(&text);
+                Ok(graph::write(&file, &text)?)
+            });
+            let stored = match res {
+                Ok(stored) => stored,
+                Err(error) => {
+                    if disk::is_full_output(&error.to_string()) {
+                        disk::full(&file);
+                    }
+                    progress!("callgraph failed failed");
+                    progress!("stderr: {}", error);
+                    let entry = Entry::new(krate, "callgraph", Status::Failed)
+                        .with("bitcode", bitcode)
+                        .with("stderr", report::tail(&error.to_string()));
+                    report::record_failure(&entry);
+                    if linked.exists() {
+                        fs::remove_file(&linked).ok();
+                    }
+                    return Err(entry);
+                }
+            };
+            if self.with_debuginfo {
+                match llvm_dis(llvm_path, bitcode) {
+                    Ok(ir) => {
+                        let functions = locations::parse(&ir);
+                        built.located_functions += functions.len();
+                        let res = if functions.is_empty() {
+                            Ok(())
+                        } else {
+                            fs::write(krate.locations_file(&file), Envelope::new("locations", &functions).to_json())
+                        };
+                        if let Err(error) = res {
+                            eprintln!("Error writing: {:?} {:?}", krate.locations_file(&file), error);
+                        }
+                    }
+                    Err(error) => eprintln!("Error disassembling: {:?} {:?}", bitcode, error),
+                }
+            }
+            built.graphs.push(stored);
+        }
+        if linked.exists() {
+            if let Err(error) = fs::remove_file(&linked) {
+                eprintln!("Error deleting: {:?} {:?}", linked, error);
+            }
+        }
+        Ok(built)
+    }
+
+    /// Build the call graphs of the crates, `[compiler] jobs` at a time.
+    fn build_callgraph(&self) {
+        self.with_jobs(|| self.build_callgraphs())
+    }
+
+    fn build_callgraphs(&self) {
+        if !disk::has_room("callgraph") {
+            return;
+        }
+        let llvm_path = &config_value("llvm", "path").expect("no [llvm] path configured");
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "callgraph", || {
+                let proc_macro = manifest::dir_is_proc_macro(&krate.dir());
+                if proc_macro && self.skip_proc_macros {
+                    progress!("proc-macro skipped: {:?}", krate);
+                    return Some(Entry::new(krate, "callgraph", Status::Skipped)
+                        .with("reason", "proc-macro")
+                        .with("proc_macro", true));
+                }
+                if let Some(entry) = self.skip_too_large(krate, "callgraph") {
+                    return Some(entry);
+                }
+                let bitcodes = krate.bitcode_paths(self.profile);
+                if bitcodes.is_empty() {
+                    progress!("no bitcode: {:?}", krate);
+                    return Some(Entry::new(krate, "callgraph", Status::Skipped)
+                        .with("reason", "no bitcode"));
+                }
+                let mut all_bitcodes = bitcodes.clone();
+                let default_graphs = krate.feature_graph_files(&bitcodes, FeatureSet::Default, self.link_bitcode);
+                let mut all_graphs = default_graphs.clone();
+                for &features in &self.feature_matrix {
+                    let feature_bitcodes = krate.feature_bitcode_paths(self.profile, features);
+                    all_graphs.extend(krate.feature_graph_files(&feature_bitcodes, features, self.link_bitcode));
+                    all_bitcodes.extend(feature_bitcodes);
+                }
+                let state = GraphState::new(
+                    krate,
+                    &all_bitcodes,
+                    self.keep_multi_edges,
+                    self.link_bitcode && bitcodes.len() > 1,
+                    &self.resolve_passes,
+                    self.with_debuginfo,
+                );
+                let state = match state {
+                    Ok(state) => Some(state),
+                    Err(error) => {
+                        eprintln!("Error hashing bitcode: {:?} {:?}", krate, error);
+                        None
+                    }
+                };
+                let unchanged = state.is_some()
+                    && GraphState::read(krate) == state
+                    && all_graphs.iter().all(|graph| graph::stored(graph).exists());
+                if unchanged && !self.rebuild_graphs {
+                    progress!("bitcode unchanged: {:?}", krate);
+                    let entry = Entry::new(krate, "callgraph", Status::Skipped)
+                        .with("reason", "bitcode unchanged");
+                    return Some(with_graph_hash(entry, krate, &default_graphs));
+                }
+                let OptGraphs {
+                    graphs,
+                    edges,
+                    duplicate_edges,
+                    indirect_edges,
+                    located_functions,
+                } = match self.opt_graphs(llvm_path, krate, &bitcodes, FeatureSet::Default) {
+                    Ok(built) => built,
+                    Err(entry) => return Some(entry),
+                };
+                if graphs.len() == 1 {
+                    let copied = graph::read_text(&graphs[0])
+                        .and_then(|text| graph::write(&krate.callgraph_file(), &text));
+                    if let Err(error) = copied {
+                        eprintln!("Error copying: {:?} {:?}", graphs[0], error);
+                    }
+                }
+                progress!("callgraph built: {:?}", krate);
+                let mut entry = Entry::new(krate, "callgraph", Status::Ok)
+                    .with("proc_macro", proc_macro)
+                    .with("bitcode_files", bitcodes.len())
+                    .with("linked", self.link_bitcode && bitcodes.len() > 1)
+                    .with("graphs", &graphs)
+                    .with("edges", edges);
+                // opt succeeds on crates without functions too, e.g. ones only re-exporting others
+                if edges < self.min_edges {
+                    progress!("callgraph empty: {:?} ({} edges)", krate, edges);
+                    entry = entry.with("empty_graph", true);
+                }
+                entry = with_graph_hash(entry, krate, &graphs);
+                if !self.keep_multi_edges {
+                    entry = entry.with("duplicate_edges", duplicate_edges);
+                }
+                if self.resolve_passes.is_some() {
+                    entry = entry.with("indirect_edges", indirect_edges);
+                }
+                if self.with_debuginfo {
+                    entry = entry.with("located_functions", located_functions);
+                }
+                let mut feature_graphs = BTreeMap::new();
+                for &features in &self.feature_matrix {
+                    let bitcodes = krate.feature_bitcode_paths(self.profile, features);
+                    if bitcodes.is_empty() {
+                        continue;
+                    }
+                    match self.opt_graphs(llvm_path, krate, &bitcodes, features) {
+                        Ok(built) => feature_graphs.insert(features.name(), built.graphs),
+                        Err(entry) => return Some(entry.with("features", features.name())),
+                    };
+                }
+                if !feature_graphs.is_empty() {
+                    entry = entry.with("feature_graphs", feature_graphs);
+                }
+                if let Some(state) = state {
+                    if let Err(error) = state.write(krate) {
+                        eprintln!("Error writing: {:?} {:?}", krate.graph_state_file(), error);
+                    }
+                }
+                Some(entry)
+            })).collect::<Vec<_>>();
+        write_report("callgraph", &entries);
+        let empty = entries.iter().filter(|e| e.details.contains_key("empty_graph")).count();
+        if empty > 0 {
+            message!(
+                "{} crates got call graphs with fewer than {} edges, flagged as empty_graph in the report",
+                empty, self.min_edges
+            );
+        }
+    }
+
+    /// Copy the MIR files of every crate built with `--emit mir` to its
+    /// `mir/` folder, the counterpart of `build_callgraph` for MIR.
+    fn collect_mir(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "mir", || {
+                if !krate.dir().exists() {
+                    return None;
+                }
+                let mirs = krate.mir_paths(self.profile);
+                if mirs.is_empty() {
+                    progress!("no MIR: {:?}", krate);
+                    return Some(Entry::new(krate, "mir", Status::Skipped).with("reason", "no MIR"));
+                }
+                let mir_dir = krate.mir_dir();
+                let mut files = Vec::with_capacity(mirs.len());
+                for mir in &mirs {
+                    let file = mir_dir.join(mir.file_name().unwrap());
+                    if let Err(error) = fs::create_dir_all(&mir_dir).and_then(|()| fs::copy(mir, &file)) {
+                        disk::check(&file, &error);
+                        eprintln!("Error copying: {:?} {:?}", mir, error);
+                        let entry = Entry::new(krate, "mir", Status::Failed)
+                            .with("mir", mir)
+                            .with("error", error.to_string());
+                        report::record_failure(&entry);
+                        return Some(entry);
+                    }
+                    files.push(file);
+                }
+                progress!("MIR collected: {:?}", krate);
+                Some(Entry::new(krate, "mir", Status::Ok)
+                    .with("mir_files", mirs.len())
+                    .with("files", files))
+            })).collect::<Vec<_>>();
+        write_report("mir", &entries);
+    }
+
+    /// Check that every crate with bitcode has non-empty call graphs that parse
+    /// and have nodes, reporting the crates that do not.
+    fn verify_graphs(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "verify-graphs", || {
+                let bitcodes = krate.bitcode_paths(self.profile);
+                if bitcodes.is_empty() {
+                    return None;
+                }
+                let problems = krate
+                    .feature_graph_files(&bitcodes, FeatureSet::Default, self.link_bitcode)
+                    .into_iter()
+                    .filter_map(|graph| verify_graph(&graph).err().map(|e| (graph, e)))
+                    .collect::<Vec<_>>();
+                if problems.is_empty() {
+                    return Some(Entry::new(krate, "verify-graphs", Status::Ok));
+                }
+                for &(ref graph, ref problem) in &problems {
+                    progress!("bad graph: {:?} {}", graph, problem);
+                }
+                let problems = problems
+                    .into_iter()
+                    .map(|(graph, problem)| (graph.to_string_lossy().into_owned(), problem))
+                    .collect::<BTreeMap<_, _>>();
+                Some(Entry::new(krate, "verify-graphs", Status::Failed).with("problems", problems))
+            })).collect::<Vec<_>>();
+        let bad = entries.iter().filter(|e| e.status == Status::Failed).count();
+        message!("Verified {} crates, {} with bad graphs", entries.len(), bad);
+        write_report("verify-graphs", &entries);
+    }
+
+    /// Compare the SHA-256 recorded in the provenance of every downloaded
+    /// crate with the checksum the index currently holds, reporting the
+    /// crates that differ or have no recorded checksum.
+    fn audit_checksums(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "audit-checksums", || {
+                if !krate.dir().exists() {
+                    return None;
+                }
+                let index = self
+                    .checksums
+                    .get(&(krate.name.clone(), krate.version.clone()))
+                    .cloned();
+                let recorded = Provenance::read(krate).map(|p| p.checksums.get("sha256").cloned());
+                let problem = match (&recorded, &index) {
+                    (&None, _) => Some("no provenance"),
+                    (&Some(None), _) => Some("no recorded checksum"),
+                    (_, &None) => Some("not in index"),
+                    (&Some(Some(ref recorded)), &Some(ref index)) if recorded != index => Some("mismatch"),
+                    _ => None,
+                };
+                let entry = match problem {
+                    None => Entry::new(krate, "audit-checksums", Status::Ok),
+                    Some(problem) => {
+                        progress!("checksum audit failed: {:?} {}", krate, problem);
+                        Entry::new(krate, "audit-checksums", Status::Failed).with("problem", problem)
+                    }
+                };
+                Some(
+                    entry
+                        .with("recorded", recorded.and_then(|r| r))
+                        .with("index", index),
+                )
+            })).collect::<Vec<_>>();
+        let bad = entries.iter().filter(|e| e.status == Status::Failed).count();
+        message!("Audited {} crates, {} with bad or missing checksums", entries.len(), bad);
+        write_report("audit-checksums", &entries);
+    }
+
+    /// Compare the SHA-256 of the `.crate` file kept by `--keep-archive` of
+    /// every downloaded crate with the checksum of the index, reporting the
+    /// crates whose archive differs or is missing.
+    fn verify_archives(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "verify-archives", || {
+                if !krate.success_file().exists() {
+                    return None;
+                }
+                let index = self
+                    .checksums
+                    .get(&(krate.name.clone(), krate.version.clone()))
+                    .cloned();
+                let archive = krate.archive_file();
+                let (problem, actual) = match Algo::Sha256.digest_file(&archive) {
+                    Ok(actual) => {
+                        let problem = match index {
+                            None => Some("not in index".to_string()),
+                            Some(ref index) if *index != actual => Some("mismatch".to_string()),
+                            Some(_) => None,
+                        };
+                        (problem, Some(actual))
+                    }
+                    Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => {
+                        (Some("missing".to_string()), None)
+                    }
+                    Err(error) => (Some(format!("unreadable: {}", error)), None),
+                };
+                let entry = match problem {
+                    None => Entry::new(krate, "verify-archives", Status::Ok),
+                    Some(problem) => {
+                        progress!("archive verification failed: {:?} {}", krate, problem);
+                        Entry::new(krate, "verify-archives", Status::Failed).with("problem", problem)
+                    }
+                };
+                Some(entry.with("archive", actual).with("index", index))
+            })).collect::<Vec<_>>();
+        let bad = entries.iter().filter(|e| e.status == Status::Failed).count();
+        message!("Verified {} archives, {} mismatched or missing", entries.len(), bad);
+        write_report("verify-archives", &entries);
+    }
+
+    /// Count the lines of every downloaded crate by file kind and extension,
+    /// into `reports/sources.json` and their sum into
+    /// `reports/sources-summary.json`.
+    fn analyze_sources(&self) {
+        let summary = Mutex::new(SourcesSummary::default());
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "sources", || {
+                if !krate.dir().exists() {
+                    return None;
+                }
+                match sources::stats(&krate.dir()) {
+                    Ok(stats) => {
+                        progress!("sources counted: {:?}", krate);
+                        {
+                            let mut summary = summary.lock().unwrap();
+                            summary.crates += 1;
+                            summary.stats.add(&stats);
+                        }
+                        Some(Entry::new(krate, "sources", Status::Ok)
+                            .with("total", &stats.total)
+                            .with("by_kind", &stats.by_kind)
+                            .with("by_extension", &stats.by_extension))
+                    }
+                    Err(error) => {
+                        eprintln!("Error counting sources: {:?} {:?}", krate, error);
+                        let entry = Entry::new(krate, "sources", Status::Failed)
+                            .with("error", error.to_string());
+                        report::record_failure(&entry);
+                        Some(entry)
+                    }
+                }
+            })).collect::<Vec<_>>();
+        write_report("sources", &entries);
+        let summary = summary.into_inner().unwrap();
+        let out = report::reports_dir().join("sources-summary.json");
+        match fs::write(&out, Envelope::new("sources-summary", &summary).to_json()) {
+            Ok(()) => message!(
+                "{} lines in {} files of {} crates: {:?}",
+                summary.stats.total.lines, summary.stats.total.files, summary.crates, out
+            ),
+            Err(error) => eprintln!("Error writing {:?}: {:?}", out, error),
+        }
+    }
+
+    /// Count the lines of Rust code of every downloaded crate into
+    /// `reports/loc.csv`.
+    fn count_loc(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter_map(|krate| report::guard(krate, "loc", || {
+                if !krate.dir().exists() {
+                    return None;
+                }
+                match sources::rust_loc(&krate.dir()) {
+                    Ok(loc) => {
+                        progress!("lines of code counted: {:?}", krate);
+                        Some(Entry::new(krate, "loc", Status::Ok).with("loc", loc))
+                    }
+                    Err(error) => {
+                        eprintln!("Error counting lines of code: {:?} {:?}", krate, error);
+                        let entry = Entry::new(krate, "loc", Status::Failed)
+                            .with("error", error.to_string());
+                        report::record_failure(&entry);
+                        Some(entry)
+                    }
+                }
+            })).collect::<Vec<_>>();
+        write_report("loc", &entries);
+        let mut csv = "name,version,loc\n".to_string();
+        let mut total = 0;
+        for entry in &entries {
+            if let Some(loc) = entry.details.get("loc").and_then(|loc| loc.as_u64()) {
+                csv.push_str(&format!("{},{},{}\n", csv_field(&entry.name), csv_field(&entry.version), loc));
+                total += loc;
+            }
+        }
+        let out = report::reports_dir().join("loc.csv");
+        match fs::write(&out, csv) {
+            Ok(()) => message!("{} lines of code in {} crates: {:?}", total, entries.len(), out),
+            Err(error) => eprintln!("Error writing {:?}: {:?}", out, error),
+        }
+    }
+
+    /// Recover the `build` and `callgraph` outcomes of crates processed
+    /// without reports from their artifacts and logs, and rebuild the failures
+    /// file from them.
+    ///
+    /// A crate built when it has bitcode, and failed to when its `build.log`
+    /// has errors; likewise for its call graphs and `callgraph.log`. Crates
+    /// without either are not reported.
+    fn scan_logs(&self) {
+        let entries = self
+            .list
+            .par_iter()
+            .filter(|_| throttle::admit())
+            .filter(|krate| krate.dir().exists())
+            .flat_map(|krate| {
+                let mut entries = Vec::new();
+                let build = if krate.has_bitcode(self.profile) {
+                    Some(Entry::new(krate, "build", Status::Ok))
+                } else {
+                    scanned_failure(krate, "build", &krate.build_log())
+                        .map(|(entry, log)| entry.with("error_category", unbuildable::categorize(&log)))
+                };
+                entries.extend(build);
+                if krate.has_callgraphs(self.profile, self.link_bitcode) || graph::stored(&krate.callgraph_file()).exists() {
+                    entries.push(Entry::new(krate, "callgraph", Status::Ok));
+                } else if krate.has_bitcode(self.profile) {
+                    entries.extend(scanned_failure(krate, "callgraph", &krate.callgraph_log()).map(|(entry, _)| entry));
+                }
+                entries
+            }).collect::<Vec<_>>();
+        let failures = entries
+            .iter()
+            .filter(|e| e.status == Status::Failed)
+            .cloned()
+            .collect::<Vec<_>>();
+        match report::replace_failures(&entries, &failures) {
+            Ok(()) => message!(
+                "Scanned {} crates: {} outcomes, {} failures written to {:?}",
+                self.list.len(),
+                entries.len(),
+                failures.len(),
+                report::failures_file()
+            ),
+            Err(error) => eprintln!("Error writing {:?}: {:?}", report::failures_file(), error),
+        }
+        if let Err(error) = report::write_named("scan-logs", "scan-logs", &entries) {
+            eprintln!("Error writing scan-logs report: {:?}", error);
+        }
+    }
+
+    /// Write a `name,version,bitcode_path` CSV row for every bitcode file of
+    /// the built crates.
+    fn list_bitcode<W: std::io::Write>(&self, mut w: W) -> std::io::Result<()> {
+        writeln!(w, "name,version,bitcode_path")?;
+        for krate in &self.list {
+            for bitcode in krate.bitcode_paths(self.profile) {
+                writeln!(
+                    w,
+                    "{},{},{}",
+                    csv_field(&krate.name),
+                    csv_field(&krate.version),
+                    csv_field(&bitcode.to_string_lossy())
+                )?;
+            }
+        }
+        w.flush()
+    }
+
+    /// Print a CSV of the crates of the latest build report that built but
+    /// have no bitcode, with the likely reason. Returns how many there are.
+    fn list_no_bitcode<W: std::io::Write>(&self, mut w: W) -> std::io::Result<usize> {
+        let built = report::read(&report::reports_dir().join("build.json"))?
+            .into_iter()
+            .filter(|entry| entry.status == Status::Ok)
+            .map(|entry| ((entry.name.clone(), entry.version.clone()), entry))
+            .collect::<HashMap<_, _>>();
+        writeln!(w, "name,version,reason")?;
+        let mut count = 0;
+        for krate in &self.list {
+            let entry = match built.get(&(krate.name.clone(), krate.version.clone())) {
+                Some(entry) if krate.dir().exists() => entry,
+                _ => continue,
+            };
+            if krate.has_bitcode(self.profile) {
+                continue;
+            }
+            let deps_dir = krate
+                .target_dir(FeatureSet::Default)
+                .join(self.profile.target_dir())
+                .join("deps");
+            let kinds = entry.details.get("target_kinds").and_then(|kinds| kinds.as_array());
+            let reason = if !deps_dir.exists() {
+                "no build output"
+            } else if entry.details.get("proc_macro").and_then(|v| v.as_bool()) == Some(true) {
+                "proc-macro"
+            } else if kinds.map_or(false, |kinds| !kinds.is_empty() && kinds.iter().all(|kind| kind.as_str() != Some("lib"))) {
+                "only a bin target"
+            } else {
+                "build emitted no bitcode"
+            };
+            writeln!(w, "{},{},{}", csv_field(&krate.name), csv_field(&krate.version), reason)?;
+            count += 1;
+        }
+        w.flush()?;
+        Ok(count)
+    }
+
+    /// Drop the crates `phase` already handled in an earlier run, so that a
+    /// re-invoked phase continues where it stopped.
+    ///
+    /// A crate is done when its artifact of the phase exists (`download`: the
+    /// success marker, `build`: its bitcode, `callgraph`: the graphs of all its
+    /// bitcode files) or when it is listed for the phase in the failures file.
+    fn resume(&mut self, phase: &str) {
+        let failed = report::failed(phase);
+        let profile = self.profile;
+        let link_bitcode = self.link_bitcode;
+        let before = self.list.len();
+        self.list.retain(|krate| {
+            let done = match phase {
+                "download" => krate.success_file().exists(),
+                "build" => krate.has_bitcode(profile),
+                "callgraph" => krate.has_callgraphs(profile, link_bitcode),
+                _ => false,
+            };
+            !done && !failed.contains(&(krate.name.clone(), krate.version.clone()))
+        });
+        checkpoint::set_total(self.list.len());
+        message!(
+            "Resuming {}: {} of {} crates remaining",
+            phase,
+            self.list.len(),
+            before
+        );
+    }
+}
+
+/// The dependency kinds of the network, `--dep-kinds`, else `[deps] kinds`,
+/// else the normal dependencies.
+fn dep_kinds(matches: &ArgMatches) -> DepKinds {
+    let kinds = matches
+        .value_of("dep-kinds")
+        .map(|kinds| kinds.to_string())
+        .or_else(|| config_value("deps", "kinds"));
+    match kinds {
+        Some(kinds) => kinds
+            .parse()
+            .unwrap_or_else(|error| panic!("invalid dependency kinds {:?}: {}", kinds, error)),
+        None => DepKinds::default(),
+    }
+}
+
+/// The dependency kinds `--include-build-deps` and `--include-dev-deps`
+/// have the closure follow.
+fn closure_kinds(matches: &ArgMatches) -> closure::Kinds {
+    closure::Kinds {
+        build: matches.is_present("include-build-deps"),
+        dev: matches.is_present("include-dev-deps"),
+    }
+}
+
+/// Resolve the dependency closure of `root`, a `name@version`, along the
+/// normal and `kinds` dependencies, and write it with its conflicts to
+/// `reports/closure.json`.
+fn resolve_closure(index: &Index, root: &str, kinds: closure::Kinds) -> PraziResult<closure::Closure> {
+    let mut parts = root.splitn(2, '@');
+    let (name, version) = match (parts.next(), parts.next()) {
+        (Some(name), Some(version)) if !name.is_empty() && !version.is_empty() => (name, version),
+        _ => return Err(format!("--closure expects name@version, got {}", root).into()),
+    };
+    let closure = closure::resolve(index, name, version, kinds)?;
+    let dir = report::reports_dir();
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("closure.json"), Envelope::new("closure", &closure).to_json())?;
+    Ok(closure)
+}
+
+#[derive(Debug, Serialize)]
+struct VersionInfo {
+    version: String,
+    yanked: bool,
+}
+
+/// Delete, or only print with `dry_run`, every crate folder below the
+/// registry folder whose version is not in `index`, along with the files of
+/// the version next to it. Yanked versions are still in the index and kept.
+/// Returns the number of folders and their bytes.
+fn prune(index: &Index, dry_run: bool) -> std::io::Result<(usize, u64)> {
+    let mut pruned = 0;
+    let mut bytes = 0;
+    for name_dir in fs::read_dir(registry_dir())? {
+        let name_dir = name_dir?;
+        if !name_dir.file_type()?.is_dir() {
+            continue;
+        }
+        let known = index
+            .crate_(&name_dir.file_name().to_string_lossy())
+            .map_or(HashSet::new(), |krate| {
+                krate
+                    .versions()
+                    .iter()
+                    .map(|v| paths::path_version(v.version()))
+                    .collect()
+            });
+        for entry in fs::read_dir(name_dir.path())? {
+            let entry = entry?;
+            let dir_name = entry.file_name().to_string_lossy().into_owned();
+            // the markers are files, the scratch folders start with a dot
+            if !entry.file_type()?.is_dir() || dir_name.starts_with('.') || known.contains(&dir_name) {
+                continue;
+            }
+            let size = source_size::tree_bytes(&entry.path())?;
+            println!("{}\t{}", entry.path().display(), size);
+            pruned += 1;
+            bytes += size;
+            if dry_run {
+                continue;
+            }
+            fs::remove_dir_all(entry.path())?;
+            // a folder not named by `path_version` has no files next to it
+            let version = match paths::version_of_path(&dir_name) {
+                Some(version) => version,
+                None => continue,
+            };
+            for sibling in paths::sibling_names(&version) {
+                let path = name_dir.path().join(sibling);
+                if path.is_dir() {
+                    fs::remove_dir_all(&path)?;
+                } else if path.exists() {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+        if !dry_run && fs::read_dir(name_dir.path())?.next().is_none() {
+            fs::remove_dir(name_dir.path())?;
+        }
+    }
+    Ok((pruned, bytes))
+}
+
+/// All versions of the crate `name` known to the local index, in semver order.
+fn list_versions(name: &str) -> PraziResult<Vec<VersionInfo>> {
+    let index = Index::new(config_index_dir());
+    if !index.exists() {
+        index.retrieve()?;
+    }
+    let krate = index
+        .crate_(name)
+        .ok_or_else(|| format!("crate {} not found in index", name))?;
+    let mut versions = krate
+        .versions()
+        .iter()
+        .map(|v| (semver::Version::parse(v.version()).ok(), v))
+        .collect::<Vec<_>>();
+    // unparseable versions go last
+    versions.sort_by(|a, b| match (&a.0, &b.0) {
+        (Some(a), Some(b)) => a.cmp(b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a.1.version().cmp(b.1.version()),
+    });
+    Ok(versions
+        .into_iter()
+        .map(|(_, v)| VersionInfo {
+            version: v.version().to_string(),
+            yanked: v.is_yanked(),
+        }).collect())
+}
+
+/// Compare the call graphs of two versions of the same crate.
+fn diff_callgraphs(name: &str, from: &str, to: &str) -> PraziResult<CallGraphDiff> {
+    let load = |version: &str| {
+        let krate = PraziCrate {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        let path = krate.callgraph_file();
+        CallGraph::load(&path).map_err(|e| format!("could not read {:?}: {}", path, e))
+    };
+    let from_graph = load(from)?;
+    let to_graph = load(to)?;
+    Ok(CallGraphDiff::new(&from_graph, &to_graph))
+}
+
+/// The call graphs of the default features of the `name@version` crate
+/// `spec`.
+fn load_callgraphs(spec: &str) -> PraziResult<Vec<CallGraph>> {
+    let mut parts = spec.splitn(2, '@');
+    let krate = match (parts.next(), parts.next()) {
+        (Some(name), Some(version)) if !name.is_empty() && !version.is_empty() => PraziCrate {
+            name: name.to_string(),
+            version: version.to_string(),
+        },
+        _ => return Err(format!("expected name@version, got {}", spec).into()),
+    };
+    // `.callgraph.dot.gz` with `[output] compress`
+    let pattern = glob_pattern(&krate.graphs_dir(), "*.callgraph.dot*");
+    let mut graphs = Vec::new();
+    for path in glob(&pattern)?.filter_map(|path| path.ok()) {
+        graphs.push(CallGraph::load(&path).map_err(|e| format!("could not read {:?}: {}", path, e))?);
+    }
+    if graphs.is_empty() {
+        return Err(format!("no call graphs of {} in {:?}", spec, krate.graphs_dir()).into());
+    }
+    Ok(graphs)
+}
+
+fn main() {
+    let mut reg = Registry::new();
+
+    let matches = App::new("rustprazi")
+        .version("0.1.0")
+        .about("Rustpräzi: generate call-based dependency networks of crates.io registry")
+        .arg(
+            Arg::with_name("order")
+                .long("order")
+                .takes_value(true)
+                .possible_values(&["index", "name", "popularity", "downloads", "random"])
+                .global(true)
+                .help("order in which crates are processed"),
+        ).arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .global(true)
+                .help("seed for --order random and --sample"),
+        ).arg(
+            Arg::with_name("dep-kinds")
+                .long("dep-kinds")
+                .takes_value(true)
+                .global(true)
+                .help("dependency kinds counted for the network, e.g. normal,build (default: normal)"),
+        ).arg(
+            Arg::with_name("sample")
+                .long("sample")
+                .takes_value(true)
+                .global(true)
+                .help("only process this many crates picked at random from the filtered list"),
+        ).arg(
+            Arg::with_name("batch")
+                .long("batch")
+                .takes_value(true)
+                .global(true)
+                .help("only process a slice of this many crates of the ordered list"),
+        ).arg(
+            Arg::with_name("offset")
+                .long("offset")
+                .takes_value(true)
+                .global(true)
+                .requires("batch")
+                .help("number of the --batch slice, from 0: crates offset*batch to (offset+1)*batch"),
+        ).arg(
+            Arg::with_name("version-selection")
+                .long("version-selection")
+                .takes_value(true)
+                .possible_values(&["latest", "all", "latest-per-major", "latest-per-minor"])
+                .global(true)
+                .help("versions of each crate to process (default: from [crates] latest_only)"),
+        ).arg(
+            Arg::with_name("category")
+                .long("category")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .help("only process crates in this crates.io category (slug) or its subcategories, repeatable"),
+        ).arg(
+            Arg::with_name("category-source")
+                .long("category-source")
+                .takes_value(true)
+                .global(true)
+                .help("data/ folder of a crates.io database dump (default: [crates] db_dump)"),
+        ).arg(
+            Arg::with_name("owner")
+                .long("owner")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .help("only process crates owned by this crates.io user or github:org:team, repeatable"),
+        ).arg(
+            Arg::with_name("owner-source")
+                .long("owner-source")
+                .takes_value(true)
+                .global(true)
+                .help("data/ folder of a crates.io database dump, or api (default: [crates] db_dump, else api)"),
+        ).arg(
+            Arg::with_name("owner-max-age")
+                .long("owner-max-age")
+                .takes_value(true)
+                .default_value("1d")
+                .global(true)
+                .help("reuse the crates of an owner fetched from the API if younger, e.g. 6h"),
+        ).arg(
+            Arg::with_name("feature-matrix")
+                .long("feature-matrix")
+                .takes_value(true)
+                .min_values(0)
+                .use_delimiter(true)
+                .possible_values(&["default", "all-features", "no-default-features"])
+                .global(true)
+                .help("also build and graph crates with these feature sets (default: all three)"),
+        ).arg(
+            Arg::with_name("only-yanked")
+                .long("only-yanked")
+                .global(true)
+                .help("only select among the yanked versions of each crate"),
+        ).arg(
+            Arg::with_name("leaf-only")
+                .long("leaf-only")
+                .global(true)
+                .conflicts_with("has-deps")
+                .help("only select versions without normal dependencies"),
+        ).arg(
+            Arg::with_name("has-deps")
+                .long("has-deps")
+                .global(true)
+                .help("only select versions with normal dependencies"),
+        ).arg(
+            Arg::with_name("prefer-unyanked")
+                .long("prefer-unyanked")
+                .global(true)
+                .conflicts_with("only-yanked")
+                .help("with --version-selection latest, select the latest version that is not yanked"),
+        ).arg(
+            Arg::with_name("max-versions-per-crate")
+                .long("max-versions-per-crate")
+                .takes_value(true)
+                .global(true)
+                .help("keep at most this many of the selected versions of each crate, the most recent"),
+        ).arg(
+            Arg::with_name("policy")
+                .long("policy")
+                .takes_value(true)
+                .global(true)
+                .help("select crates by the include/exclude globs and version requirements of a TOML file"),
+        ).arg(
+            Arg::with_name("edition")
+                .long("edition")
+                .takes_value(true)
+                .global(true)
+                .help("only crates of this edition (only downloaded crates can match)"),
+        ).arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .possible_values(&["debug", "release"])
+                .global(true)
+                .help("cargo profile to build with and search bitcode in (default: debug)"),
+        ).arg(
+            Arg::with_name("index-timeout")
+                .long("index-timeout")
+                .takes_value(true)
+                .global(true)
+                .help("seconds after which cloning/updating the index is aborted"),
+        ).arg(
+            Arg::with_name("index-shallow")
+                .long("index-shallow")
+                .global(true)
+                .help("clone/update the index shallowly with git"),
+        ).arg(
+            Arg::with_name("index-offline")
+                .long("index-offline")
+                .global(true)
+                .help("use the local index checkout as it is, without fetching it"),
+        ).arg(
+            Arg::with_name("reclone")
+                .long("reclone")
+                .global(true)
+                .conflicts_with("index-offline")
+                .help("clone the index again if its checkout is not a git repository or fails to update"),
+        ).arg(
+            Arg::with_name("no-lock")
+                .long("no-lock")
+                .global(true)
+                .help("run even if another run holds the lock of PRAZI_DIR"),
+        ).arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .global(true)
+                .help("skip crates download/build-crates/build-callgraphs already handled"),
+        ).arg(
+            Arg::with_name("crate")
+                .long("crate")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true)
+                .help("only process this name@version, repeatable; callgraph-diff takes a name"),
+        ).arg(
+            Arg::with_name("closure")
+                .long("closure")
+                .takes_value(true)
+                .global(true)
+                .conflicts_with_all(&["crate", "crate-file"])
+                .help("only process this name@version and its transitive dependencies at resolved versions"),
+        ).arg(
+            Arg::with_name("include-build-deps")
+                .long("include-build-deps")
+                .global(true)
+                .help("follow build dependencies in the closure, needed to build it"),
+        ).arg(
+            Arg::with_name("include-dev-deps")
+                .long("include-dev-deps")
+                .global(true)
+                .help("follow the dev-dependencies of the closure's root"),
+        ).arg(
+            Arg::with_name("skip-proc-macros")
+                .long("skip-proc-macros")
+                .global(true)
+                .help("leave procedural macro crates out of build-crates and build-callgraphs"),
+        ).arg(
+            Arg::with_name("max-source-bytes")
+                .long("max-source-bytes")
+                .takes_value(true)
+                .global(true)
+                .help("skip building crates with more bytes of sources, e.g. 50M"),
+        ).arg(
+            Arg::with_name("crate-file")
+                .long("crate-file")
+                .takes_value(true)
+                .global(true)
+                .help("process this local .crate file instead of the crates of the index"),
+        ).arg(
+            Arg::with_name("from-list")
+                .long("from-list")
+                .takes_value(true)
+                .global(true)
+                .help("only process the crates listed in this file, one name or name@version per line"),
+        ).arg(
+            Arg::with_name("expand")
+                .long("expand")
+                .takes_value(true)
+                .global(true)
+                .requires("from-list")
+                .help("add the {dependents,dependencies,both}[:depth] of the --from-list crates"),
+        ).arg(
+            Arg::with_name("expand-limit")
+                .long("expand-limit")
+                .takes_value(true)
+                .global(true)
+                .help("stop --expand at this many crates (default: 1000)"),
+        ).arg(
+            Arg::with_name("label")
+                .long("label")
+                .takes_value(true)
+                .global(true)
+                .help("keep builds, graphs and reports under runs/<label>, overrides [storage] run_label"),
+        ).arg(
+            Arg::with_name("registry")
+                .long("registry")
+                .takes_value(true)
+                .global(true)
+                .help("process the crates of the registry of [registry.<name>] instead of crates.io"),
+        ).arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .global(true)
+                .help("only print errors and the PRAZI_SUMMARY line of the phase"),
+        ).arg(
+            Arg::with_name("json-lines")
+                .long("json-lines")
+                .global(true)
+                .help("print the outcome of every crate as a JSON line on stdout, other messages on stderr"),
+        ).arg(
+            Arg::with_name("nice")
+                .long("nice")
+                .global(true)
+                .help("leave a core free, download less in parallel and run at a lower priority"),
+        ).arg(
+            Arg::with_name("adaptive")
+                .long("adaptive")
+                .global(true)
+                .help("start fewer crates at a time while the load or memory use is above [throttle]"),
+        ).arg(
+            Arg::with_name("download-concurrency")
+                .long("download-concurrency")
+                .takes_value(true)
+                .global(true)
+                .help("download this many crates at a time, overrides [download] concurrency"),
+        ).arg(
+            Arg::with_name("jobs")
+                .long("jobs")
+                .takes_value(true)
+                .global(true)
+                .help("build this many crates or call graphs at a time, overrides [compiler] jobs"),
+        ).arg(
+            Arg::with_name("max-runtime")
+                .long("max-runtime")
+                .takes_value(true)
+                .global(true)
+                .help("stop starting new crates after this long, e.g. 6h"),
+        ).arg(
+            Arg::with_name("fail-fast")
+                .long("fail-fast")
+                .global(true)
+                .help("stop starting new crates after the first failure"),
+        ).arg(
+            Arg::with_name("metrics-addr")
+                .long("metrics-addr")
+                .takes_value(true)
+                .global(true)
+                .help("expose Prometheus metrics on this address, e.g. 127.0.0.1:9100"),
+        )
+        .subcommand(
+            SubCommand::with_name("download")
+                .about("download registry crate sources")
+                .arg(
+                    Arg::with_name("revalidate")
+                        .long("revalidate")
+                        .help("re-check downloaded crates using their stored ETag/Last-Modified"),
+                ).arg(
+                    Arg::with_name("keep-archive")
+                        .long("keep-archive")
+                        .help("keep the downloaded .crate files next to the sources, for verify-archives"),
+                ).arg(
+                    Arg::with_name("only-missing")
+                        .long("only-missing")
+                        .conflicts_with("revalidate")
+                        .help("only consider crates whose folder does not exist"),
+                ).arg(
+                    Arg::with_name("source-dir")
+                        .long("source-dir")
+                        .takes_value(true)
+                        .help("take the {name}-{version}.crate files found in this folder instead of downloading them"),
+                ).arg(
+                    Arg::with_name("offline")
+                        .long("offline")
+                        .requires("source-dir")
+                        .help("fail the crates not in --source-dir instead of downloading them"),
+                ).arg(
+                    Arg::with_name("max-crate-size")
+                        .long("max-crate-size")
+                        .takes_value(true)
+                        .help("skip crate files larger than this many megabytes, as per [crates] db_dump or the Content-Length"),
+                ).arg(
+                    Arg::with_name("include-deps-sources")
+                        .long("include-deps-sources")
+                        .conflicts_with("stream")
+                        .help("also download the versions in the dependency closures of the crates, to build them"),
+                ).arg(
+                    Arg::with_name("stream")
+                        .long("stream")
+                        .help("download the crates as they are read from the index, without listing them first"),
+                ).arg(
+                    Arg::with_name("checksum-algo")
+                        .long("checksum-algo")
+                        .takes_value(true)
+                        .multiple(true)
+                        .use_delimiter(true)
+                        .possible_values(&["sha256", "sha512", "blake3"])
+                        .help("hashes of the .crate file to record in its provenance (default: sha256)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("validate")
+                .about("validate Cargo.toml files")
+                .arg(
+                    Arg::with_name("refresh")
+                        .long("refresh")
+                        .help("run cargo read-manifest even when its output is cached"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("resolve")
+                .about("record the dependency versions cargo resolves for every crate"),
+        )
+        .subcommand(
+            SubCommand::with_name("rewrite")
+                .about("rewrite Cargo.toml to remove local Path dependencies")
+                .arg(
+                    Arg::with_name("retries")
+                        .long("retries")
+                        .takes_value(true)
+                        .help("retries of a dry-run publish failing on the network (default: 3)"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("build-callgraphs")
+                .about("construct Crate-wide LLVM callgraphss")
+                .arg(
+                    Arg::with_name("keep-multi-edges")
+                        .long("keep-multi-edges")
+                        .help("keep the repeated edges opt emits for multiple call sites"),
+                ).arg(
+                    Arg::with_name("min-edges")
+                        .long("min-edges")
+                        .takes_value(true)
+                        .help("record crates whose call graphs have fewer edges as empty_graph (default: 1)"),
+                ).arg(
+                    Arg::with_name("rebuild")
+                        .long("rebuild")
+                        .help("also rebuild the graphs of crates whose bitcode did not change"),
+                ).arg(
+                    Arg::with_name("resolve-indirect")
+                        .long("resolve-indirect")
+                        .help("resolve indirect calls with opt passes first, listing the edges they add"),
+                ).arg(
+                    Arg::with_name("no-link")
+                        .long("no-link")
+                        .help("build one graph per bitcode file instead of linking them first"),
+                ).arg(
+                    Arg::with_name("bin")
+                        .long("bin")
+                        .takes_value(true)
+                        .help("only build the graphs of the bitcode of the binary <name>"),
+                ).arg(
+                    Arg::with_name("with-debuginfo")
+                        .long("with-debuginfo")
+                        .help("record the source locations of the functions, from bitcode built with debug info"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("build-crates")
+                .about("build all crates")
+                .arg(
+                    Arg::with_name("build-env")
+                        .long("build-env")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("KEY=VALUE environment variable for the build, repeatable"),
+                ).arg(
+                    Arg::with_name("no-build-scripts")
+                        .long("no-build-scripts")
+                        .help("skip crates whose build would run a build script, their own or of a dependency"),
+                ).arg(
+                    Arg::with_name("single-codegen-unit")
+                        .long("single-codegen-unit")
+                        .help("build with -C codegen-units=1 to get one bitcode file per crate"),
+                ).arg(
+                    Arg::with_name("message-format")
+                        .long("message-format")
+                        .takes_value(true)
+                        .possible_values(&["human", "json"])
+                        .help("count the diagnostics of the builds in cargo's output (default) or its JSON messages"),
+                ).arg(
+                    Arg::with_name("bins")
+                        .long("bins")
+                        .help("build the binary targets only, skipping crates without any"),
+                ).arg(
+                    Arg::with_name("bin")
+                        .long("bin")
+                        .takes_value(true)
+                        .conflicts_with("bins")
+                        .help("build the binary <name> only"),
+                ).arg(
+                    Arg::with_name("with-debuginfo")
+                        .long("with-debuginfo")
+                        .help("build with -C debuginfo=1, for the source locations of build-callgraphs"),
+                ).arg(
+                    Arg::with_name("retry-failed-once")
+                        .long("retry-failed-once")
+                        .help("build failed crates again and report whether they are flaky"),
+                ).arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("also build crates already built with the current toolchain"),
+                ).arg(
+                    Arg::with_name("topo-order")
+                        .long("topo-order")
+                        .help("build the crates after the crates of the list they depend on, in waves"),
+                ).arg(
+                    Arg::with_name("out-of-tree-build")
+                        .long("out-of-tree-build")
+                        .help("build in a scratch copy of the sources, leaving the crate's folder untouched"),
+                ).arg(
+                    Arg::with_name("build-log")
+                        .long("build-log")
+                        .help("write the whole output of every build to {dir}/build.log"),
+                ).arg(
+                    Arg::with_name("locked")
+                        .long("locked")
+                        .help("pass --locked to cargo, crates without Cargo.lock fail"),
+                ).arg(
+                    Arg::with_name("frozen")
+                        .long("frozen")
+                        .help("pass --frozen to cargo: --locked and --offline"),
+                ).arg(
+                    Arg::with_name("offline")
+                        .long("offline")
+                        .help("pass --offline to cargo, dependencies must be in CARGO_HOME"),
+                ).arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .takes_value(true)
+                        .possible_values(&["llvm-bc", "mir"])
+                        .default_value("llvm-bc")
+                        .help("also emit MIR for collect-mir, needs a nightly rustc"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("export-api")
+                .about("extract the public API of built crates with rustdoc (nightly)"),
+        ).subcommand(
+            SubCommand::with_name("export-features")
+                .about("write the declared and implicit features of every downloaded crate"),
+        ).subcommand(
+            SubCommand::with_name("package-dataset")
+                .about("bundle provenance, reports and the merged graph into one archive")
+                .arg(Arg::with_name("out").long("out").takes_value(true).required(true))
+                .arg(
+                    Arg::with_name("with-graph")
+                        .long("with-graph")
+                        .help("include the merged call-based dependency network"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("merge-callgraphs")
+                .about("merge the unified crate callgraphs into one call-based dependency network")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .help("output file, - for stdout (default: cdn/graphs/callgraph.ufi.merged.graph)"),
+                ).arg(
+                    Arg::with_name("chunk-lines")
+                        .long("chunk-lines")
+                        .takes_value(true)
+                        .help("lines sorted in memory before spilling to disk (default: 1000000)"),
+                ).arg(
+                    Arg::with_name("boundary-only")
+                        .long("boundary-only")
+                        .help("only write the edges between functions of different crates"),
+                ).arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["dot", "binary", "graphml"])
+                        .help("output format (default: dot)"),
+                ).arg(
+                    Arg::with_name("node-id")
+                        .long("node-id")
+                        .takes_value(true)
+                        .possible_values(&["name-version", "content-hash"])
+                        .help("identify the crates of the merge by name and version or by source tree hash"),
+                ).arg(
+                    Arg::with_name("partitions")
+                        .long("partitions")
+                        .takes_value(true)
+                        .help("write N graphs {out}.0 to {out}.N-1, partitioned by crate name"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("verify-graphs")
+                .about("check that the built call graphs exist, parse and have nodes")
+                .arg(
+                    Arg::with_name("no-link")
+                        .long("no-link")
+                        .help("check the graphs of a build-callgraphs --no-link run"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("collect-mir")
+                .about("collect the MIR files of crates built with --emit mir"),
+        ).subcommand(
+            SubCommand::with_name("audit-checksums")
+                .about("compare the checksums recorded at download with the current index"),
+        ).subcommand(
+            SubCommand::with_name("verify-archives")
+                .about("compare the checksums of the archives kept by download --keep-archive with the index"),
+        ).subcommand(
+            SubCommand::with_name("list-bitcode")
+                .about("print a CSV of the bitcode files of every built crate")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .help("write the CSV to this file instead of stdout"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("list-no-bitcode")
+                .about("print a CSV of the crates that built but have no bitcode, with the likely reason")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .help("write the CSV to this file instead of stdout"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("dump-graph")
+                .about("print a merged graph in the binary format as DOT")
+                .arg(Arg::with_name("file").required(true)),
+        ).subcommand(
+            SubCommand::with_name("callgraph-diff")
+                .about("compare the callgraphs of two versions of a crate")
+                .arg(Arg::with_name("from").long("from").takes_value(true).required(true))
+                .arg(Arg::with_name("to").long("to").takes_value(true).required(true)),
+        ).subcommand(
+            SubCommand::with_name("callers")
+                .about("print the functions of the call graphs of the --crate name@version calling a function")
+                .arg(Arg::with_name("symbol").required(true))
+                .arg(
+                    Arg::with_name("transitive")
+                        .long("transitive")
+                        .help("also print the functions calling the callers, and so on"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("unbuildable")
+                .about("list the crates failing to build with every toolchain they were built with")
+                .arg(
+                    Arg::with_name("reports")
+                        .multiple(true)
+                        .help("build reports to merge (default: reports/build-*.json)"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("reclassify")
+                .about("categorize the failed builds of a report again with the current rules")
+                .arg(
+                    Arg::with_name("report")
+                        .long("report")
+                        .takes_value(true)
+                        .required(true)
+                        .help("report to read, e.g. reports/build.json"),
+                ).arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("file to write the report to, may be the --report"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("ingest-downloads")
+                .about("store the download counts of all crates for --order downloads")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .required(true)
+                        .help("data/ folder of a crates.io database dump, or api to page through the crates.io API"),
+                ).arg(
+                    Arg::with_name("max-age")
+                        .long("max-age")
+                        .takes_value(true)
+                        .default_value("1d")
+                        .help("keep stored counts from the same source that are younger, e.g. 6h"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("msrv")
+                .about("compare the declared rust-version of crates with the toolchains that built them")
+                .arg(
+                    Arg::with_name("validate")
+                        .long("validate")
+                        .takes_value(true)
+                        .help("validate report with the declared versions (default: reports/validate.json)"),
+                ).arg(
+                    Arg::with_name("reports")
+                        .multiple(true)
+                        .help("build reports to merge (default: reports/build-*.json)"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("unused-deps")
+                .about("list the declared dependencies of crates without calls in the merged call graph")
+                .arg(
+                    Arg::with_name("deps")
+                        .long("deps")
+                        .takes_value(true)
+                        .required(true)
+                        .help("dependencies as written by export-index, - for stdin"),
+                ).arg(
+                    Arg::with_name("graph")
+                        .long("graph")
+                        .takes_value(true)
+                        .required(true)
+                        .help("DOT graph written by merge-callgraphs"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("analyze")
+                .about("compute function reuse metrics over the merged call graph")
+                .arg(
+                    Arg::with_name("graph")
+                        .long("graph")
+                        .takes_value(true)
+                        .required(true)
+                        .help("DOT or binary graph written by merge-callgraphs"),
+                ).arg(
+                    Arg::with_name("top")
+                        .long("top")
+                        .takes_value(true)
+                        .default_value("20")
+                        .help("number of most called functions to list"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("analyze-sources")
+                .about("count the lines of every crate by file kind and extension"),
+        ).subcommand(
+            SubCommand::with_name("count-loc")
+                .about("count the non-blank, non-comment lines of the Rust sources of every crate"),
+        ).subcommand(
+            SubCommand::with_name("scan-logs")
+                .about("rebuild the failures file from the bitcode, graphs and logs of every crate"),
+        ).subcommand(
+            SubCommand::with_name("merge-reports")
+                .about("combine the reports of several runs, one entry per crate and phase")
+                .arg(
+                    Arg::with_name("inputs")
+                        .long("inputs")
+                        .takes_value(true)
+                        .multiple(true)
+                        .required(true)
+                        .help("phase reports or failures.jsonl files to merge"),
+                ).arg(Arg::with_name("out").long("out").takes_value(true).required(true)),
+        ).subcommand(
+            SubCommand::with_name("export-index")
+                .about("write the index metadata of every crate version as NDJSON")
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .takes_value(true)
+                        .required(true)
+                        .help("file to write, one JSON object per line, - for stdout"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("lint-index")
+                .about("report index versions that are not valid semver or published out of order"),
+        ).subcommand(
+            SubCommand::with_name("prune")
+                .about("delete the crate folders of versions no longer in the index")
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("only list the folders and their bytes"),
+                ),
+        ).subcommand(
+            SubCommand::with_name("list-versions")
+                .about("list the versions of a crate known to the index")
+                .arg(Arg::with_name("name").required(true))
+                .arg(Arg::with_name("json").long("json").help("print as JSON")),
+        ).subcommand(
+            SubCommand::with_name("closure")
+                .about("print the dependency closure of the --crate name@version, one name@version per line"),
+        ).subcommand(
+            SubCommand::with_name("selftest")
+                .about("run download, build and callgraph over a few well-known crates"),
+        ).get_matches();
+
+    if let (_, Some(sub_matches)) = matches.subcommand() {
+        if let Some(label) = sub_matches.value_of("label") {
+            set_run_label(label);
+        }
+        if let Some(name) = sub_matches.value_of("registry") {
+            source::select_registry(name);
+        }
+    }
+    if let Some(name) = matches.subcommand_name() {
+        run_record::set_phase(name);
+        // the other subcommands write to PRAZI_DIR, at least their reports,
+        // and prune deletes from it
+        let no_lock = matches.subcommand().1.map_or(false, |m| m.is_present("no-lock"));
+        if !lock::READ_ONLY.contains(&name) && !no_lock {
+            if let Err(error) = lock::acquire(Path::new(&**PRAZI_DIR)) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("download") {
+        reg.prepare(_matches);
+        if _matches.is_present("resume") {
+            reg.resume("download");
+        }
+        if let Err(error) = reg.download_src() {
+            eprintln!("Error downloading: {:?}", error);
+            summary::fail();
+        } else {
+            println!("Done with downloading!");
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("validate") {
+        reg.prepare(_matches);
+        reg.validate_manifests();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("resolve") {
+        reg.prepare(_matches);
+        reg.resolve_dependencies();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("rewrite") {
+        reg.prepare(_matches);
+        reg.rewrite_manifests();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("build-callgraphs") {
+        reg.prepare(_matches);
+        if _matches.is_present("resume") {
+            reg.resume("callgraph");
+        }
+        reg.build_callgraph();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("build-crates") {
+        reg.prepare(_matches);
+        if _matches.is_present("resume") {
+            reg.resume("build");
+        }
+        reg.compile();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("export-api") {
+        reg.prepare(_matches);
+        reg.export_api();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("export-features") {
+        reg.prepare(_matches);
+        reg.export_features();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("package-dataset") {
+        reg.prepare(_matches);
+        let out = Path::new(_matches.value_of("out").unwrap());
+        let filters = std::env::args().skip(1).collect::<Vec<_>>();
+        match dataset::package(
+            out,
+            &reg.list,
+            &config_index_dir(),
+            filters,
+            _matches.is_present("with-graph"),
+        ) {
+            Ok(manifest) => println!(
+                "Packaged {} crates and {} files into {:?}",
+                manifest.crate_count,
+                manifest.files.len(),
+                out
+            ),
+            Err(error) => {
+                eprintln!("Error packaging dataset: {}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("merge-callgraphs") {
+        reg.prepare(_matches);
+        let out = _matches
+            .value_of("out")
+            .map(PathBuf::from)
+            .unwrap_or_else(dataset::merged_graph);
+        let chunk_lines = _matches
+            .value_of("chunk-lines")
+            .map(|v| v.parse().expect("--chunk-lines must be a number"))
+            .unwrap_or(1_000_000);
+        let tmp_dir = Path::new(&**PRAZI_DIR).join("tmp").join("merge");
+        let options = merge::Options {
+            chunk_lines,
+            boundary_only: _matches.is_present("boundary-only"),
+            format: _matches.value_of("format").map_or(merge::Format::Dot, |v| v.parse().unwrap()),
+            node_id: _matches
+                .value_of("node-id")
+                .map_or(merge::NodeId::NameVersion, |v| v.parse().unwrap()),
+            partitions: _matches
+                .value_of("partitions")
+                .map(|v| v.parse().expect("--partitions must be a number")),
+        };
+        match merge::merge(&reg.list, &out, &tmp_dir, options) {
+            Ok(stats) => {
+                let message = format!(
+                    "Merged {} crates ({} without graph) into {:?}: {} nodes ({} unresolved), {} edges",
+                    stats.crates, stats.missing, out, stats.nodes, stats.unresolved, stats.edges
+                );
+                if stdio::is_std(&out) {
+                    eprintln!("{}", message);
+                } else {
+                    println!("{}", message);
+                }
+            }
+            Err(error) => {
+                eprintln!("Error merging callgraphs: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("verify-graphs") {
+        reg.prepare(_matches);
+        reg.verify_graphs();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("collect-mir") {
+        reg.prepare(_matches);
+        reg.collect_mir();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("audit-checksums") {
+        reg.prepare(_matches);
+        reg.audit_checksums();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("verify-archives") {
+        reg.prepare(_matches);
+        reg.verify_archives();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("list-bitcode") {
+        reg.prepare(_matches);
+        let res = match _matches.value_of("out") {
+            Some(out) => File::create(out).and_then(|file| reg.list_bitcode(std::io::BufWriter::new(file))),
+            None => reg.list_bitcode(std::io::stdout()),
+        };
+        if let Err(error) = res {
+            eprintln!("Error listing bitcode files: {:?}", error);
+            summary::fail();
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("list-no-bitcode") {
+        reg.prepare(_matches);
+        let res = match _matches.value_of("out") {
+            Some(out) => File::create(out).and_then(|file| reg.list_no_bitcode(std::io::BufWriter::new(file))),
+            None => reg.list_no_bitcode(std::io::stdout()),
+        };
+        match res {
+            // stdout only carries the CSV
+            Ok(count) if _matches.is_present("out") => message!("{} built crates without bitcode", count),
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("Error listing crates without bitcode: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("dump-graph") {
+        let file = Path::new(matches.value_of("file").unwrap());
+        let res = BinaryGraph::load(file).and_then(|graph| graph.write_dot(std::io::stdout()));
+        if let Err(error) = res {
+            eprintln!("Error dumping graph: {:?} {:?}", file, error);
+            summary::fail();
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("callgraph-diff") {
+        let name = matches
+            .value_of("crate")
+            .expect("callgraph-diff requires --crate <name>");
+        let from = matches.value_of("from").unwrap();
+        let to = matches.value_of("to").unwrap();
+        match diff_callgraphs(name, from, to) {
+            Ok(diff) => println!("{}", Envelope::new("callgraph-diff", diff).to_json()),
+            Err(error) => {
+                eprintln!("Error comparing callgraphs: {}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("callers") {
+        let spec = match matches.values_of("crate").map(|specs| specs.collect::<Vec<_>>()) {
+            Some(ref specs) if specs.len() == 1 => specs[0],
+            _ => panic!("callers needs exactly one --crate name@version"),
+        };
+        let symbol = matches.value_of("symbol").unwrap();
+        match load_callgraphs(spec) {
+            Ok(graphs) => {
+                for caller in graph::callers(&graphs, symbol, matches.is_present("transitive")) {
+                    println!("{}", caller);
+                }
+            }
+            Err(error) => {
+                eprintln!("Error reading the call graphs of {}: {}", spec, error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("unbuildable") {
+        let reports = match matches.values_of("reports") {
+            Some(reports) => Ok(reports.map(PathBuf::from).collect()),
+            None => unbuildable::default_reports(),
+        };
+        let res = reports.and_then(|reports| {
+            let crates = unbuildable::collect(&reports)?;
+            let out = report::reports_dir().join("unbuildable.json");
+            fs::write(&out, Envelope::new("unbuildable", &crates).to_json())?;
+            Ok((reports.len(), crates.len(), out))
+        });
+        match res {
+            Ok((reports, crates, out)) => {
+                println!("{} crates failed with all toolchains of {} reports: {:?}", crates, reports, out)
+            }
+            Err(error) => {
+                eprintln!("Error merging build reports: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("reclassify") {
+        let input = matches.value_of("report").unwrap();
+        let out = matches.value_of("out").unwrap();
+        match unbuildable::reclassify(Path::new(input), Path::new(out)) {
+            Ok((categorized, changed)) => println!(
+                "{} failed builds categorized, {} changed category: {}",
+                categorized, changed, out
+            ),
+            Err(error) => {
+                eprintln!("Error reclassifying {}: {:?}", input, error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("ingest-downloads") {
+        let from = matches.value_of("from").unwrap();
+        let max_age = stop::parse_duration(matches.value_of("max-age").unwrap()).unwrap();
+        let cached = downloads::Downloads::read()
+            .filter(|stored| stored.source == from && stored.age().map_or(false, |age| age < max_age));
+        match cached {
+            Some(stored) => println!(
+                "Keeping the download counts of {} crates fetched at {}",
+                stored.crates.len(),
+                stored.fetched_at
+            ),
+            None => match downloads::ingest(from).and_then(|fetched| {
+                fetched.write()?;
+                Ok(fetched.crates.len())
+            }) {
+                Ok(crates) => println!("Stored the download counts of {} crates: {:?}", crates, downloads::downloads_file()),
+                Err(error) => {
+                    eprintln!("Error ingesting download counts: {:?}", error);
+                    summary::fail();
+                }
+            },
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("msrv") {
+        let validate = matches
+            .value_of("validate")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| report::reports_dir().join("validate.json"));
+        let reports = match matches.values_of("reports") {
+            Some(reports) => Ok(reports.map(PathBuf::from).collect()),
+            None => unbuildable::default_reports(),
+        };
+        let res = reports.and_then(|reports| {
+            let crates = msrv::compare(&validate, &reports)?;
+            let out = report::reports_dir().join("msrv.json");
+            fs::write(&out, Envelope::new("msrv", &crates).to_json())?;
+            Ok((crates, out))
+        });
+        match res {
+            Ok((crates, out)) => {
+                let count = |verdict: msrv::Verdict| crates.iter().filter(|c| c.verdict == verdict).count();
+                println!(
+                    "{} crates with a declared MSRV: {} hold, {} violated, {} conservative, {} untested: {:?}",
+                    crates.len(),
+                    count(msrv::Verdict::Holds),
+                    count(msrv::Verdict::Violated),
+                    count(msrv::Verdict::Conservative),
+                    count(msrv::Verdict::Untested),
+                    out
+                )
+            }
+            Err(error) => {
+                eprintln!("Error comparing MSRVs: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("unused-deps") {
+        // reads a merged graph, not the index
+        run_record::start(0);
+        let deps = Path::new(matches.value_of("deps").unwrap());
+        let graph = Path::new(matches.value_of("graph").unwrap());
+        let res = unused_deps::compare(deps, graph).and_then(|crates| {
+            let out = report::reports_dir().join("unused-deps.json");
+            fs::create_dir_all(report::reports_dir())?;
+            fs::write(&out, Envelope::new("unused-deps", &crates).to_json())?;
+            let unused = crates.iter().filter(|c| !c.unused.is_empty()).count();
+            Ok((crates.len(), unused, out))
+        });
+        match res {
+            Ok((crates, unused, out)) => {
+                println!("{} of {} crates declare dependencies they do not call: {:?}", unused, crates, out)
+            }
+            Err(error) => {
+                eprintln!("Error comparing dependencies: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("analyze") {
+        // reads a merged graph, not the index
+        run_record::start(0);
+        let graph = Path::new(matches.value_of("graph").unwrap());
+        let top = matches
+            .value_of("top")
+            .unwrap()
+            .parse()
+            .expect("--top must be a number");
+        let res = analyze::load(graph).and_then(|merged| {
+            let analysis = analyze::analyze(&merged, top);
+            let out = report::reports_dir().join("analysis.json");
+            fs::create_dir_all(report::reports_dir())?;
+            fs::write(&out, Envelope::new("analysis", &analysis).to_json())?;
+            Ok((analysis.functions, analysis.components.count, out))
+        });
+        match res {
+            Ok((functions, components, out)) => {
+                println!("{} functions in {} connected components: {:?}", functions, components, out)
+            }
+            Err(error) => {
+                eprintln!("Error analyzing graph: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("analyze-sources") {
+        reg.prepare(_matches);
+        reg.analyze_sources();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("count-loc") {
+        reg.prepare(_matches);
+        reg.count_loc();
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("scan-logs") {
+        reg.prepare(_matches);
+        reg.scan_logs();
+    }
+
+    if let Some(matches) = matches.subcommand_matches("merge-reports") {
+        let inputs = matches.values_of("inputs").unwrap().map(PathBuf::from).collect::<Vec<_>>();
+        let out = matches.value_of("out").unwrap();
+        let res = report::merge(&inputs).and_then(|(kind, entries)| {
+            fs::write(out, Envelope::new(&kind, &entries).to_json())?;
+            Ok(entries.len())
+        });
+        match res {
+            Ok(count) => println!("Merged {} reports into {} entries: {}", inputs.len(), count, out),
+            Err(error) => {
+                eprintln!("Error merging reports: {}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("export-index") {
+        let out = Path::new(matches.value_of("out").unwrap());
+        let index = reg.index();
+        let kinds = dep_kinds(matches);
+        match stdio::create(out).and_then(|writer| index::export(&index, kinds, writer)) {
+            Ok(count) if stdio::is_std(out) => eprintln!("Exported {} versions", count),
+            Ok(count) => println!("Exported {} versions to {:?}", count, out),
+            Err(error) => {
+                eprintln!("Error exporting index: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("lint-index") {
+        let lint = index::lint(&reg.index());
+        let out = report::reports_dir().join("lint-index.json");
+        let res = fs::create_dir_all(report::reports_dir())
+            .and_then(|()| fs::write(&out, Envelope::new("lint-index", &lint).to_json()));
+        match res {
+            Ok(()) => println!(
+                "{} issues in {} versions of {} crates: {:?}",
+                lint.issues.len(),
+                lint.versions,
+                lint.crates,
+                out
+            ),
+            Err(error) => {
+                eprintln!("Error writing {:?}: {:?}", out, error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("prune") {
+        let dry_run = _matches.is_present("dry-run");
+        match prune(&reg.index(), dry_run) {
+            Ok((pruned, bytes)) => message!(
+                "{} {} crate folders no longer in the index, {} MiB",
+                if dry_run { "Would prune" } else { "Pruned" },
+                pruned,
+                bytes / (1024 * 1024)
+            ),
+            Err(error) => {
+                eprintln!("Error pruning: {:?}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("list-versions") {
+        let name = matches.value_of("name").unwrap();
+        match list_versions(name) {
+            Ok(versions) => {
+                if matches.is_present("json") {
+                    println!("{}", Envelope::new("list-versions", versions).to_json());
+                } else {
+                    for v in versions {
+                        println!("{}{}", v.version, if v.yanked { " (yanked)" } else { "" });
+                    }
+                }
+            }
+            Err(error) => {
+                eprintln!("Error listing versions: {}", error);
+                summary::fail();
+            }
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("closure") {
+        let root = match matches.values_of("crate").map(|specs| specs.collect::<Vec<_>>()) {
+            Some(ref specs) if specs.len() == 1 => specs[0],
+            _ => clap::Error::with_description(
+                "closure takes exactly one --crate name@version",
+                clap::ErrorKind::WrongNumberOfValues,
+            ).exit(),
+        };
+        match resolve_closure(&reg.index(), root, closure_kinds(matches)) {
+            Ok(closure) => {
+                for krate in &closure.crates {
+                    println!("{}@{}", krate.name, krate.version);
+                }
+                for conflict in &closure.conflicts {
+                    eprintln!(
+                        "conflict: {} {} of {}: {}",
+                        conflict.name, conflict.requirement, conflict.required_by, conflict.reason
+                    );
+                }
+            }
+            Err(error) => {
+                eprintln!("Error resolving the closure of {}: {}", root, error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(_matches) = matches.subcommand_matches("selftest") {
+        reg.latest_of = selftest::CRATES;
+        reg.prepare(_matches);
+        if !selftest::run(&mut reg) {
+            std::process::exit(1);
+        }
+    }
+
+    run_record::finish();
+    report::flush_failures();
+    if let Some(code) = stop::exit_code() {
+        if let Some(message) = disk::exit_message() {
+            eprintln!("{}", message);
+        }
+        std::process::exit(code);
+    }
+    if let Some(code) = summary::exit_code() {
+        std::process::exit(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::consts::EXE_SUFFIX;
+
+    #[test]
+    fn unpack_crate_leaves_nothing_of_a_broken_archive() {
+        let krate = PraziCrate {
+            name: "unpack-broken".to_string(),
+            version: "0.1.0".to_string(),
+        };
+        let tar = mock::tarball(&krate.name, &krate.version);
+        let crate_file = mock::gzip(&tar);
+        let mut bad_header = tar.clone();
+        bad_header[0] ^= 1;
+        let broken = vec![
+            ("truncated", crate_file[..crate_file.len() / 2].to_vec()),
+            ("not gzipped", b"not a crate".to_vec()),
+            ("header checksum", mock::gzip(&bad_header)),
+            ("empty", Vec::new()),
+        ];
+        for (case, archive) in broken {
+            assert!(unpack_crate(&krate, &archive[..]).is_err(), "{}", case);
+            assert!(!krate.dir().exists(), "{}", case);
+            assert!(!krate.dir_tmp().exists(), "{}", case);
+        }
+        let unpacked = unpack_crate(&krate, &crate_file[..]).unwrap();
+        assert_eq!(unpacked.files.len(), 2);
+        assert!(krate.dir().join("Cargo.toml").is_file());
+        assert!(krate.dir().join("src").join("lib.rs").is_file());
+        assert!(!krate.dir_tmp().exists());
+    }
+
+    #[test]
+    fn llvm_binaries_have_the_executable_suffix() {
+        let bin = Path::new("llvm").join("bin");
+        assert_eq!(opt_binary("llvm"), bin.join(format!("opt{}", EXE_SUFFIX)));
+        assert_eq!(llvm_link_binary("llvm"), bin.join(format!("llvm-link{}", EXE_SUFFIX)));
+        assert_eq!(llvm_dis_binary("llvm"), bin.join(format!("llvm-dis{}", EXE_SUFFIX)));
+        let opt = opt_binary("llvm");
+        if cfg!(windows) {
+            assert_eq!(opt.file_name().unwrap(), "opt.exe");
+        } else {
+            assert_eq!(opt.file_name().unwrap(), "opt");
+        }
+    }
+
+    #[test]
+    fn glob_pattern_joins_with_the_native_separator() {
+        let dir = Path::new("target").join("debug").join("deps");
+        assert_eq!(
+            glob_pattern(&dir, "*.bc"),
+            format!("target{0}debug{0}deps{0}*.bc", MAIN_SEPARATOR)
+        );
+    }
+
+    #[test]
+    fn glob_pattern_matches_the_dir_literally() {
+        let dir = Path::new("crates").join("a[1]?");
+        assert_eq!(
+            glob_pattern(&dir, "*.bc"),
+            format!("crates{}a[[]1[]][?]{}*.bc", MAIN_SEPARATOR, MAIN_SEPARATOR)
+        );
+        let dir = test_dir("glob").join("a[1]");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("x.bc"), "").unwrap();
+        fs::write(dir.join("x.ll"), "").unwrap();
+        fs::create_dir_all(dir.with_file_name("a1")).unwrap();
+        fs::write(dir.with_file_name("a1").join("y.bc"), "").unwrap();
+        let found = glob(&glob_pattern(&dir, "*.bc"))
+            .unwrap()
+            .map(|path| path.unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![dir.join("x.bc")]);
+    }
+
+    #[test]
+    fn select_versions_picks_by_semver_not_index_order() {
+        // 1.2.3 was published after 1.10.0, as a backport
+        let published = ["0.1.0", "0.1.1", "0.2.0", "1.0.0", "1.10.0", "1.2.3", "2.0.0-alpha.1"];
+        let name = "select-versions";
+        let dir = test_dir("select-versions");
+        let file = dir.join(paths::index_prefix(name)).join(name);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        let lines = published
+            .iter()
+            .map(|version| {
+                format!(
+                    "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[],\"cksum\":\"{}\",\"features\":{{}},\"yanked\":false}}\n",
+                    name,
+                    version,
+                    "0".repeat(64)
+                )
+            }).collect::<String>();
+        fs::write(&file, lines).unwrap();
+        let krate = Index::new(dir).crate_(name).unwrap();
+        let versions = krate.versions().iter().collect::<Vec<_>>();
+        let select = |selection| select_versions(&krate, &versions, selection);
+
+        assert_eq!(select(VersionSelection::Latest), vec!["2.0.0-alpha.1"]);
+        let mut all = published.to_vec();
+        all.reverse();
+        assert_eq!(select(VersionSelection::All), all);
+        assert_eq!(select(VersionSelection::LatestPerMajor), vec!["2.0.0-alpha.1", "1.10.0", "0.2.0"]);
+        assert_eq!(
+            select(VersionSelection::LatestPerMinor),
+            vec!["2.0.0-alpha.1", "1.10.0", "1.2.3", "1.0.0", "0.2.0", "0.1.1"]
+        );
+        assert!(select_versions(&krate, &[], VersionSelection::Latest).is_empty());
     }
 }
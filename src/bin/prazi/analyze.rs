@@ -0,0 +1,231 @@
+// Ecosystem-wide metrics of the merged call graph, for `prazi analyze`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Functions are attributed to the crate of the first unique function
+// identifier of their label, like `unused-deps` does, so a call is external
+// when caller and callee belong to different crates. Graphs merged with
+// `--node-id content-hash` have no such identifiers and no external calls.
+use graph::{self, BinaryGraph, BINARY_MAGIC};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// A function with many callers.
+#[derive(Debug, Serialize)]
+pub struct CalledFunction {
+    pub function: String,
+    /// `name@version` of the defining crate, in the identifier form of `ufi`.
+    #[serde(rename = "crate")]
+    pub krate: Option<String>,
+    /// Distinct callers.
+    pub fan_in: usize,
+    /// Distinct callers in other crates.
+    pub external_fan_in: usize,
+    /// Distinct crates the external callers belong to.
+    pub calling_crates: usize,
+}
+
+/// Number of functions by in-degree and by out-degree.
+#[derive(Debug, Serialize)]
+pub struct DegreeDistribution {
+    pub in_degree: BTreeMap<usize, usize>,
+    pub out_degree: BTreeMap<usize, usize>,
+}
+
+/// Weakly connected components of the graph.
+#[derive(Debug, Serialize)]
+pub struct Components {
+    pub count: usize,
+    /// Functions of the largest component.
+    pub largest: usize,
+    /// Components made of a single function without edges.
+    pub isolated: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Analysis {
+    pub functions: usize,
+    /// Distinct edges, repeated ones are counted once.
+    pub edges: usize,
+    pub crates: usize,
+    /// Edges between functions of different crates.
+    pub external_edges: usize,
+    pub mean_fan_in: f64,
+    /// Mean external fan-in of the functions called from other crates,
+    /// which approximates the public API actually used.
+    pub mean_external_fan_in: f64,
+    /// The functions with the most callers in other crates, ties broken by
+    /// their total callers.
+    pub top_called: Vec<CalledFunction>,
+    pub degrees: DegreeDistribution,
+    pub components: Components,
+}
+
+fn edge_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^\s*"(.*?)" -> "(.*?)"(?: \[.*\])?;$"#).unwrap();
+    }
+    &RE
+}
+
+fn node_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^\s*"(.*?)"(?: \[.*\])?;$"#).unwrap();
+    }
+    &RE
+}
+
+/// Read a graph written by `merge-callgraphs`, as DOT or in the binary
+/// format.
+pub fn load(path: &Path) -> io::Result<BinaryGraph> {
+    let mut magic = [0u8; 4];
+    let binary = File::open(path)?.read(&mut magic)? == magic.len() && &magic == BINARY_MAGIC;
+    if binary {
+        return BinaryGraph::load(path);
+    }
+    let mut res = BinaryGraph::default();
+    let mut positions = HashMap::new();
+    {
+        let mut position = |label: &str| -> u64 {
+            if let Some(&position) = positions.get(label) {
+                return position;
+            }
+            let position = res.nodes.len() as u64;
+            res.nodes.push(label.to_string());
+            positions.insert(label.to_string(), position);
+            position
+        };
+        let mut edges = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if let Some(caps) = edge_regex().captures(&line) {
+                edges.push((position(&caps[1]), position(&caps[2])));
+            } else if let Some(caps) = node_regex().captures(&line) {
+                position(&caps[1]);
+            }
+        }
+        res.edges = edges;
+    }
+    Ok(res)
+}
+
+fn root(parents: &mut Vec<usize>, mut node: usize) -> usize {
+    while parents[node] != node {
+        parents[node] = parents[parents[node]];
+        node = parents[node];
+    }
+    node
+}
+
+fn components(functions: usize, edges: &HashSet<(usize, usize)>) -> Components {
+    let mut parents = (0..functions).collect::<Vec<_>>();
+    for &(from, to) in edges {
+        let (from, to) = (root(&mut parents, from), root(&mut parents, to));
+        if from != to {
+            parents[from] = to;
+        }
+    }
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for node in 0..functions {
+        *sizes.entry(root(&mut parents, node)).or_insert(0) += 1;
+    }
+    let connected = edges
+        .iter()
+        .flat_map(|&(from, to)| vec![from, to])
+        .collect::<HashSet<_>>();
+    Components {
+        count: sizes.len(),
+        largest: sizes.values().cloned().max().unwrap_or(0),
+        isolated: functions - connected.len(),
+    }
+}
+
+fn mean(total: usize, count: usize) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+/// Compute the metrics of the `merged` graph, listing the `top` most called functions.
+pub fn analyze(merged: &BinaryGraph, top: usize) -> Analysis {
+    let functions = merged.nodes.len();
+    let crates = merged
+        .nodes
+        .iter()
+        .map(|label| graph::ufi_crate(label).map(|(name, version)| format!("{}@{}", name, version)))
+        .collect::<Vec<_>>();
+    let edges = merged
+        .edges
+        .iter()
+        .map(|&(from, to)| (from as usize, to as usize))
+        .collect::<HashSet<_>>();
+    let external = |from: usize, to: usize| match (&crates[from], &crates[to]) {
+        (&Some(ref caller), &Some(ref callee)) => caller != callee,
+        _ => false,
+    };
+
+    let mut in_degree = vec![0; functions];
+    let mut out_degree = vec![0; functions];
+    let mut external_fan_in = vec![0; functions];
+    let mut calling_crates: HashMap<usize, HashSet<&String>> = HashMap::new();
+    for &(from, to) in &edges {
+        out_degree[from] += 1;
+        in_degree[to] += 1;
+        if external(from, to) {
+            external_fan_in[to] += 1;
+            calling_crates
+                .entry(to)
+                .or_insert_with(HashSet::new)
+                .insert(crates[from].as_ref().unwrap());
+        }
+    }
+
+    let mut called = (0..functions)
+        .filter(|&node| in_degree[node] > 0)
+        .collect::<Vec<_>>();
+    called.sort_by(|&a, &b| {
+        (external_fan_in[b], in_degree[b])
+            .cmp(&(external_fan_in[a], in_degree[a]))
+            .then_with(|| merged.nodes[a].cmp(&merged.nodes[b]))
+    });
+    let top_called = called
+        .into_iter()
+        .take(top)
+        .map(|node| CalledFunction {
+            function: merged.nodes[node].clone(),
+            krate: crates[node].clone(),
+            fan_in: in_degree[node],
+            external_fan_in: external_fan_in[node],
+            calling_crates: calling_crates.get(&node).map_or(0, |c| c.len()),
+        }).collect();
+
+    let histogram = |degrees: &[usize]| {
+        let mut res = BTreeMap::new();
+        for &degree in degrees {
+            *res.entry(degree).or_insert(0) += 1;
+        }
+        res
+    };
+    let externally_called = external_fan_in.iter().filter(|&&n| n > 0).count();
+    Analysis {
+        functions,
+        edges: edges.len(),
+        crates: crates.iter().filter_map(|c| c.as_ref()).collect::<HashSet<_>>().len(),
+        external_edges: external_fan_in.iter().sum(),
+        mean_fan_in: mean(edges.len(), functions),
+        mean_external_fan_in: mean(external_fan_in.iter().sum(), externally_called),
+        top_called,
+        degrees: DegreeDistribution {
+            in_degree: histogram(&in_degree),
+            out_degree: histogram(&out_degree),
+        },
+        components: components(functions, &edges),
+    }
+}
@@ -0,0 +1,135 @@
+// Extraction of the public API of crates from rustdoc's JSON output
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use children;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use {PraziCrate, PraziResult};
+
+/// The item kinds extracted, in the older and newer names of rustdoc's JSON
+/// format (`typedef` became `type_alias`, methods became functions).
+const KINDS: &[&str] = &[
+    "function",
+    "method",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "trait_alias",
+    "typedef",
+    "type_alias",
+    "constant",
+    "static",
+    "macro",
+];
+
+/// A public item of a crate as described by rustdoc.
+#[derive(Debug, Serialize)]
+pub struct ApiItem {
+    /// Fully qualified path, when rustdoc knows it.
+    pub path: Option<String>,
+    pub name: String,
+    /// rustdoc's item kind, e.g. `function`.
+    pub kind: String,
+    /// rustdoc's description of the item: the signature of a function, the
+    /// generics and fields or variants of a type, and so on.
+    pub signature: Value,
+}
+
+/// Item kind and details of a rustdoc item, for both the older (`kind` plus
+/// `inner`) and newer (`inner: {kind: details}`) layouts of the JSON format.
+fn kind_of(item: &Value) -> Option<(String, Value)> {
+    if let Some(kind) = item["kind"].as_str() {
+        return Some((kind.to_string(), item["inner"].clone()));
+    }
+    item["inner"]
+        .as_object()
+        .and_then(|inner| inner.iter().next())
+        .map(|(kind, details)| (kind.clone(), details.clone()))
+}
+
+/// Collect the public functions, types, traits, constants and macros of a
+/// rustdoc JSON document.
+pub fn public_items(doc: &Value) -> Vec<ApiItem> {
+    let mut items = Vec::new();
+    let index = match doc["index"].as_object() {
+        Some(index) => index,
+        None => return items,
+    };
+    for (id, item) in index {
+        if item["visibility"].as_str() != Some("public") {
+            continue;
+        }
+        let (kind, details) = match kind_of(item) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        if !KINDS.contains(&kind.as_str()) {
+            continue;
+        }
+        let path = doc["paths"][id]["path"].as_array().map(|segments| {
+            segments
+                .iter()
+                .filter_map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join("::")
+        });
+        items.push(ApiItem {
+            path,
+            name: item["name"].as_str().unwrap_or("").to_string(),
+            kind,
+            signature: details,
+        });
+    }
+    items.sort_by(|a, b| a.path.cmp(&b.path).then(a.name.cmp(&b.name)));
+    items
+}
+
+/// Run `cargo rustdoc` with JSON output on `krate` and return its public API.
+///
+/// The JSON output is unstable, so this requires the nightly toolchain.
+pub fn extract(krate: &PraziCrate, nightly: &str) -> PraziResult<Vec<ApiItem>> {
+    let dir = krate.dir();
+    let output = children::output(
+        "cargo rustdoc",
+        Command::new("rustup")
+            .args(&["run", nightly, "cargo", "rustdoc", "--lib", "--"])
+            .args(&["-Z", "unstable-options", "--output-format", "json"])
+            .current_dir(&dir),
+    )?;
+    if !output.status.success() {
+        return Err(format!(
+            "rustdoc failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ).into());
+    }
+    let doc_dir = dir.join("target").join("doc");
+    let json_file = find_json(&doc_dir, &krate.name.replace("-", "_"))
+        .ok_or_else(|| format!("no rustdoc JSON in {:?}", doc_dir))?;
+    let doc: Value = ::serde_json::from_str(&fs::read_to_string(json_file)?)?;
+    Ok(public_items(&doc))
+}
+
+/// The JSON file for `lib_name`, or the only JSON file when the library
+/// target is named differently from the crate.
+fn find_json(doc_dir: &Path, lib_name: &str) -> Option<::std::path::PathBuf> {
+    let expected = doc_dir.join(format!("{}.json", lib_name));
+    if expected.exists() {
+        return Some(expected);
+    }
+    let candidates = fs::read_dir(doc_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().map_or(false, |ext| ext == "json"))
+        .collect::<Vec<_>>();
+    if candidates.len() == 1 {
+        candidates.into_iter().next()
+    } else {
+        None
+    }
+}
@@ -0,0 +1,103 @@
+// Crate categories from a crates.io database dump, for `--category`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The index does not know the categories of a crate, the database dump
+// (https://static.crates.io/db-dump.tar.gz) does. The `data/` folder of the
+// extracted dump holds `categories.csv`, `crates.csv` and
+// `crates_categories.csv`, which relate category slugs to crate names.
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The records of a CSV file (RFC 4180), quoted fields may span lines.
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => record.push(std::mem::replace(&mut field, String::new())),
+            '\n' if !quoted => {
+                record.push(std::mem::replace(&mut field, String::new()));
+                records.push(std::mem::replace(&mut record, Vec::new()));
+            }
+            '\r' if !quoted => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records
+}
+
+/// The rows of `{dir}/{file}` as maps from the header's column names.
+pub fn read_table(dir: &Path, file: &str) -> io::Result<Vec<HashMap<String, String>>> {
+    let text = fs::read_to_string(dir.join(file))?;
+    let mut records = parse_csv(&text).into_iter();
+    let header = records
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{} is empty", file)))?;
+    Ok(records
+        .map(|record| header.iter().cloned().zip(record).collect())
+        .collect())
+}
+
+fn column<'a>(row: &'a HashMap<String, String>, name: &str) -> Option<&'a str> {
+    row.get(name).map(|v| v.as_str())
+}
+
+/// The category slugs of every crate that has any, by crate name.
+pub struct Categories {
+    crates: HashMap<String, HashSet<String>>,
+}
+
+impl Categories {
+    /// Read the categories from the `data/` folder of a database dump.
+    pub fn read(dir: &Path) -> io::Result<Categories> {
+        let slugs = read_table(dir, "categories.csv")?
+            .iter()
+            .filter_map(|row| Some((column(row, "id")?.to_string(), column(row, "slug")?.to_string())))
+            .collect::<HashMap<_, _>>();
+        let names = read_table(dir, "crates.csv")?
+            .iter()
+            .filter_map(|row| Some((column(row, "id")?.to_string(), column(row, "name")?.to_string())))
+            .collect::<HashMap<_, _>>();
+        let mut crates: HashMap<String, HashSet<String>> = HashMap::new();
+        for row in read_table(dir, "crates_categories.csv")? {
+            let slug = column(&row, "category_id").and_then(|id| slugs.get(id));
+            let name = column(&row, "crate_id").and_then(|id| names.get(id));
+            if let (Some(slug), Some(name)) = (slug, name) {
+                crates.entry(name.clone()).or_insert_with(HashSet::new).insert(slug.clone());
+            }
+        }
+        Ok(Categories { crates })
+    }
+
+    /// The categories of the crate `name`, `None` when it has none.
+    pub fn of(&self, name: &str) -> Option<&HashSet<String>> {
+        self.crates.get(name)
+    }
+}
+
+/// Whether one of `categories` is `wanted` or one of its subcategories, e.g.
+/// `cryptography::cryptocurrencies` for `cryptography`.
+pub fn matches(categories: &HashSet<String>, wanted: &[String]) -> bool {
+    categories.iter().any(|slug| {
+        wanted
+            .iter()
+            .any(|w| slug == w || (slug.starts_with(w.as_str()) && slug[w.len()..].starts_with("::")))
+    })
+}
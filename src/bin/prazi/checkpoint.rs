@@ -0,0 +1,139 @@
+// Periodic checkpoints of the progress of a phase in `progress.json`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Every crate a phase finishes is counted, and every `[report]
+// checkpoint_secs` seconds or `checkpoint_crates` crates, whichever comes
+// first, the counts are written to `{storage.path}/progress.json`. The file
+// is replaced by a rename, so a monitoring script never reads half of it.
+use chrono::Utc;
+use metrics::METRICS;
+use report::{Entry, Envelope, Status};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use {config_value, run_dir, PraziCrate, PRAZI_DIR};
+
+/// What `progress.json` holds.
+#[derive(Debug, Default, Serialize)]
+struct Checkpoint {
+    phase: String,
+    /// Crates selected for the run, after `--resume`.
+    total: usize,
+    /// Crates of the phase finished so far, including the ones without an
+    /// entry, e.g. already up to date.
+    processed: usize,
+    ok: usize,
+    failed: usize,
+    skipped: usize,
+    /// `name@version` of the crate finished last.
+    last_crate: Option<String>,
+    downloads_completed: usize,
+    downloads_failed: usize,
+    bytes_downloaded: usize,
+    updated_at: String,
+    /// Whether the phase wrote its report.
+    finished: bool,
+}
+
+struct State {
+    checkpoint: Checkpoint,
+    written_at: Instant,
+    /// Crates processed when the file was last written.
+    written_processed: usize,
+}
+
+lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State {
+        checkpoint: Checkpoint::default(),
+        written_at: Instant::now(),
+        written_processed: 0,
+    });
+    static ref INTERVAL: Duration = Duration::from_secs(
+        config_value("report", "checkpoint_secs")
+            .map_or(60, |v| v.parse().expect("invalid [report] checkpoint_secs"))
+    );
+    static ref INTERVAL_CRATES: usize = config_value("report", "checkpoint_crates")
+        .map_or(100, |v| v.parse().expect("invalid [report] checkpoint_crates"));
+}
+
+pub fn progress_file() -> PathBuf {
+    run_dir(Path::new(&**PRAZI_DIR)).join("progress.json")
+}
+
+/// Set the number of crates the run processes.
+pub fn set_total(total: usize) {
+    STATE.lock().unwrap().checkpoint.total = total;
+}
+
+/// Count `krate` as finished by `phase`, with its report `entry` if any, and
+/// write a checkpoint when one is due.
+pub fn record(krate: &PraziCrate, phase: &str, entry: Option<&Entry>) {
+    let mut state = STATE.lock().unwrap();
+    if state.checkpoint.phase != phase {
+        let total = state.checkpoint.total;
+        state.checkpoint = Checkpoint {
+            phase: phase.to_string(),
+            total,
+            ..Checkpoint::default()
+        };
+        state.written_at = Instant::now();
+        state.written_processed = 0;
+    }
+    {
+        let checkpoint = &mut state.checkpoint;
+        checkpoint.processed += 1;
+        match entry.map(|e| e.status) {
+            Some(Status::Ok) => checkpoint.ok += 1,
+            Some(Status::Failed) => checkpoint.failed += 1,
+            Some(Status::Skipped) => checkpoint.skipped += 1,
+            None => {}
+        }
+        checkpoint.last_crate = Some(format!("{}@{}", krate.name, krate.version));
+    }
+    let due = state.written_at.elapsed() >= *INTERVAL
+        || state.checkpoint.processed - state.written_processed >= *INTERVAL_CRATES;
+    if due {
+        write(&mut state);
+    }
+}
+
+/// Write the final checkpoint of `phase`.
+pub fn finish(phase: &str) {
+    let mut state = STATE.lock().unwrap();
+    if state.checkpoint.phase != phase {
+        // no crate was processed
+        let total = state.checkpoint.total;
+        state.checkpoint = Checkpoint {
+            phase: phase.to_string(),
+            total,
+            ..Checkpoint::default()
+        };
+    }
+    state.checkpoint.finished = true;
+    write(&mut state);
+}
+
+fn write(state: &mut State) {
+    state.written_at = Instant::now();
+    state.written_processed = state.checkpoint.processed;
+    let checkpoint = &mut state.checkpoint;
+    checkpoint.downloads_completed = METRICS.downloads_completed.load(Ordering::SeqCst);
+    checkpoint.downloads_failed = METRICS.downloads_failed.load(Ordering::SeqCst);
+    checkpoint.bytes_downloaded = METRICS.bytes_downloaded.load(Ordering::SeqCst);
+    checkpoint.updated_at = Utc::now().to_rfc3339();
+    let file = progress_file();
+    let tmp = file.with_extension("json.tmp");
+    let res: io::Result<()> = fs::create_dir_all(file.parent().unwrap())
+        .and_then(|()| fs::write(&tmp, Envelope::new("progress", &*checkpoint).to_json()))
+        .and_then(|()| fs::rename(&tmp, &file));
+    if let Err(error) = res {
+        // the checkpoint is only informative, the run goes on
+        eprintln!("Error writing {:?}: {:?}", file, error);
+    }
+}
@@ -0,0 +1,92 @@
+// Hashes of downloaded `.crate` files
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use sha2::{Digest, Sha256, Sha512};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Bytes of a file hashed at a time by `Algo::digest_file`.
+const CHUNK_BYTES: usize = 64 * 1024;
+
+/// Pass the contents of `reader` to `f`, a chunk at a time.
+fn read_chunks<R: Read, F: FnMut(&[u8])>(mut reader: R, mut f: F) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_BYTES];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => f(&buf[..n]),
+            Err(ref error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Hash algorithms that can be recorded in the provenance of a crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algo {
+    /// The algorithm of the crates.io index `cksum`.
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Algo {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algo::Sha256 => "sha256",
+            Algo::Sha512 => "sha512",
+            Algo::Blake3 => "blake3",
+        }
+    }
+
+    /// Lowercase hex digest of `data`.
+    pub fn digest(self, data: &[u8]) -> String {
+        match self {
+            Algo::Sha256 => format!("{:x}", Sha256::digest(data)),
+            Algo::Sha512 => format!("{:x}", Sha512::digest(data)),
+            Algo::Blake3 => ::blake3::hash(data).to_hex().to_string(),
+        }
+    }
+
+    /// Lowercase hex digest of the file at `path`, read in chunks rather
+    /// than at once.
+    pub fn digest_file(self, path: &Path) -> io::Result<String> {
+        let file = File::open(path)?;
+        Ok(match self {
+            Algo::Sha256 => {
+                let mut hasher = Sha256::new();
+                read_chunks(file, |chunk| hasher.input(chunk))?;
+                format!("{:x}", hasher.result())
+            }
+            Algo::Sha512 => {
+                let mut hasher = Sha512::new();
+                read_chunks(file, |chunk| hasher.input(chunk))?;
+                format!("{:x}", hasher.result())
+            }
+            Algo::Blake3 => {
+                let mut hasher = ::blake3::Hasher::new();
+                read_chunks(file, |chunk| {
+                    hasher.update(chunk);
+                })?;
+                hasher.finalize().to_hex().to_string()
+            }
+        })
+    }
+}
+
+impl FromStr for Algo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Algo, String> {
+        match s {
+            "sha256" => Ok(Algo::Sha256),
+            "sha512" => Ok(Algo::Sha512),
+            "blake3" => Ok(Algo::Blake3),
+            _ => Err(format!("unknown checksum algorithm: {}", s)),
+        }
+    }
+}
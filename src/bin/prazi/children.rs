@@ -0,0 +1,110 @@
+// The subprocesses running for crates, to spot hung builds early
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Every `cargo`, `opt` and other LLVM tool a phase runs for a crate is
+// registered with its start time while it runs, together with the crate the
+// worker thread is processing (see `report::guard`). The `[report]
+// longest_children` longest-running ones are served by the metrics endpoint
+// and, every `longest_children_secs` seconds, written to stderr: a build that
+// has run for far longer than the others is likely stuck.
+use config_value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+struct Child {
+    program: &'static str,
+    /// `name@version` of the crate it runs for, if any.
+    krate: Option<String>,
+    started: Instant,
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    static ref RUNNING: Mutex<HashMap<usize, Child>> = Mutex::new(HashMap::new());
+    /// How many of the longest-running children are listed.
+    static ref TOP: usize = config_value("report", "longest_children")
+        .map_or(5, |v| v.parse().expect("invalid [report] longest_children"));
+    static ref LOG_INTERVAL: Option<Duration> = config_value("report", "longest_children_secs")
+        .map(|v| Duration::from_secs(v.parse().expect("invalid [report] longest_children_secs")));
+}
+
+thread_local! {
+    static CRATE: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// Attribute the children started on this thread to `krate` from now on,
+/// returning the crate they were attributed to before.
+pub fn set_crate(krate: Option<String>) -> Option<String> {
+    CRATE.with(|current| current.replace(krate))
+}
+
+/// Registers a subprocess as running for as long as it is alive.
+pub struct Running(usize);
+
+impl Running {
+    /// Register `program`, about to be started for the crate of the thread.
+    pub fn new(program: &'static str) -> Running {
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        let child = Child {
+            program,
+            krate: CRATE.with(|current| current.borrow().clone()),
+            started: Instant::now(),
+        };
+        RUNNING.lock().unwrap().insert(id, child);
+        Running(id)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        RUNNING.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// `cmd.output()`, registered as `program` while it runs.
+pub fn output(program: &'static str, cmd: &mut Command) -> io::Result<Output> {
+    let _running = Running::new(program);
+    cmd.output()
+}
+
+/// The program, crate and elapsed time of the `TOP` longest-running
+/// children, longest first.
+pub fn longest() -> Vec<(&'static str, Option<String>, Duration)> {
+    let mut children = RUNNING
+        .lock()
+        .unwrap()
+        .values()
+        .map(|child| (child.program, child.krate.clone(), child.started.elapsed()))
+        .collect::<Vec<_>>();
+    children.sort_by(|a, b| b.2.cmp(&a.2));
+    children.truncate(*TOP);
+    children
+}
+
+/// Write the longest-running children to stderr every
+/// `[report] longest_children_secs` seconds, if set, from a background thread.
+pub fn log_periodically() {
+    if let Some(interval) = *LOG_INTERVAL {
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            for (program, krate, elapsed) in longest() {
+                eprintln!(
+                    "running for {}s: {} {}",
+                    elapsed.as_secs(),
+                    program,
+                    krate.as_ref().map_or("", |k| k.as_str())
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,198 @@
+// The dependency closure of a root crate, for `--closure` and `closure`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Resolution follows cargo loosely: the normal dependencies of every version
+// are followed for all targets, optional ones only if the default features
+// enable them. Build dependencies are needed to compile the closure but are
+// not part of the runtime graph, they are only followed on request, and so
+// are the dev-dependencies of the root, which cargo needs for its tests.
+// A requirement is met by a version already in the closure if one matches,
+// otherwise by its highest non-yanked match. Cargo allows one version per
+// name and semver-compatible range (same major, or same minor below 1.0); a
+// second one is kept but recorded as a conflict, just like requirements that
+// no version meets.
+use crates_index::{Index, Version};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+/// The dependency kinds followed besides the normal dependencies.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Kinds {
+    /// Build dependencies, of all crates of the closure.
+    pub build: bool,
+    /// Dev-dependencies, of the root only.
+    pub dev: bool,
+}
+
+impl Kinds {
+    /// The names of the followed kinds, as recorded in the closure.
+    fn names(self) -> Vec<String> {
+        let mut names = vec!["normal".to_string()];
+        if self.build {
+            names.push("build".to_string());
+        }
+        if self.dev {
+            names.push("dev".to_string());
+        }
+        names
+    }
+}
+
+/// A crate of the closure.
+#[derive(Debug, Clone, Serialize)]
+pub struct Resolved {
+    pub name: String,
+    pub version: String,
+    /// Dependency edges from the root, 0 for the root itself.
+    pub depth: usize,
+    /// Kind of the dependency it was first reached by, absent for the root.
+    pub kind: Option<String>,
+    #[serde(skip)]
+    pub checksum: String,
+}
+
+/// A requirement the resolution could not meet like cargo would.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub name: String,
+    pub requirement: String,
+    /// `name@version` of the crate with the requirement.
+    pub required_by: String,
+    /// The version picked anyway, if any.
+    pub selected: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Closure {
+    pub root: String,
+    /// The dependency kinds followed.
+    pub kinds: Vec<String>,
+    pub crates: Vec<Resolved>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// The versions of a name cargo would not unify: same major, or same minor
+/// below 1.0.
+fn compatible(a: &::semver::Version, b: &::semver::Version) -> bool {
+    a.major == b.major && (a.major > 0 || a.minor == b.minor)
+}
+
+/// Names of the optional dependencies the default features of `version`
+/// enable, following features that enable other features.
+fn default_dependencies(version: &Version) -> HashSet<String> {
+    let features = version.features();
+    let mut res = HashSet::new();
+    let mut seen = HashSet::new();
+    let mut todo = vec!["default".to_string()];
+    while let Some(feature) = todo.pop() {
+        if !seen.insert(feature.clone()) {
+            continue;
+        }
+        match features.get(&feature) {
+            Some(enabled) => todo.extend(enabled.iter().map(|f| {
+                // `dep/feature` enables `dep` as well
+                f.split('/').next().unwrap().to_string()
+            })),
+            // not a feature, the name of an optional dependency
+            None => {
+                res.insert(feature);
+            }
+        }
+    }
+    res
+}
+
+/// The highest non-yanked version of `name` matching `req`.
+fn best_match<'a>(index: &'a Index, name: &str, req: &::semver::VersionReq) -> Option<(::semver::Version, &'a Version)> {
+    index
+        .crate_(name)?
+        .versions()
+        .iter()
+        .filter(|v| !v.is_yanked())
+        .filter_map(|v| ::semver::Version::parse(v.version()).ok().map(|parsed| (parsed, v)))
+        .filter(|(parsed, _)| req.matches(parsed))
+        .max_by(|a, b| a.0.cmp(&b.0))
+}
+
+/// Resolve the closure of `version` of `name` along the normal and `kinds`
+/// dependencies. Fails only if the root is not in the index.
+pub fn resolve(index: &Index, name: &str, version: &str, kinds: Kinds) -> Result<Closure, String> {
+    let root = index
+        .crate_(name)
+        .ok_or_else(|| format!("crate {} not found in index", name))?
+        .versions()
+        .iter()
+        .find(|v| v.version() == version)
+        .ok_or_else(|| format!("version {} of {} not found in index", version, name))?
+        .clone();
+    let mut closure = Closure {
+        root: format!("{}@{}", name, version),
+        kinds: kinds.names(),
+        ..Closure::default()
+    };
+    // the versions in the closure by name, parsed
+    let mut selected: BTreeMap<String, Vec<::semver::Version>> = BTreeMap::new();
+    if let Ok(parsed) = ::semver::Version::parse(version) {
+        selected.insert(name.to_string(), vec![parsed]);
+    }
+    let mut todo = VecDeque::new();
+    todo.push_back((root, 0, None));
+    while let Some((current, depth, kind)) = todo.pop_front() {
+        let required_by = format!("{}@{}", current.name(), current.version());
+        let defaults = default_dependencies(&current);
+        closure.crates.push(Resolved {
+            name: current.name().to_string(),
+            version: current.version().to_string(),
+            depth,
+            kind,
+            checksum: current.checksum().to_string(),
+        });
+        for dep in current.dependencies() {
+            let followed = match dep.kind() {
+                Some("dev") => kinds.dev && depth == 0,
+                Some("build") => kinds.build,
+                _ => true,
+            };
+            if !followed || (dep.is_optional() && !defaults.contains(dep.name())) {
+                continue;
+            }
+            let conflict = |selected: Option<String>, reason: String| Conflict {
+                name: dep.name().to_string(),
+                requirement: dep.requirement().to_string(),
+                required_by: required_by.clone(),
+                selected,
+                reason,
+            };
+            let req = match ::semver::VersionReq::parse(dep.requirement()) {
+                Ok(req) => req,
+                Err(error) => {
+                    closure.conflicts.push(conflict(None, format!("unparseable requirement: {}", error)));
+                    continue;
+                }
+            };
+            let versions = selected.entry(dep.name().to_string()).or_insert_with(Vec::new);
+            if versions.iter().any(|v| req.matches(v)) {
+                continue;
+            }
+            let (parsed, found) = match best_match(index, dep.name(), &req) {
+                Some(found) => found,
+                None => {
+                    closure.conflicts.push(conflict(None, "no matching version".to_string()));
+                    continue;
+                }
+            };
+            if let Some(other) = versions.iter().find(|v| compatible(v, &parsed)) {
+                closure.conflicts.push(conflict(
+                    Some(found.version().to_string()),
+                    format!("semver-compatible with {} already in the closure", other),
+                ));
+            }
+            versions.push(parsed);
+            todo.push_back((found.clone(), depth + 1, Some(dep.kind().unwrap_or("normal").to_string())));
+        }
+    }
+    Ok(closure)
+}
@@ -0,0 +1,134 @@
+// Reading the configuration from `conf.toml`, `conf.json` or `conf.ini`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The first of `conf.toml`, `conf.json` and `conf.ini` in the top dir is
+// read. The sections of `conf.ini` are tables in the other two; a table
+// nested in a section is a section of its own, e.g. `[build.env]` and
+// `[registry.<name>]`, which `conf.ini` spells with a dot. Settings are looked
+// up as the strings `conf.ini` would hold: booleans and numbers as written,
+// lists joined with commas, so `kinds = ["normal", "build"]` in `[deps]`
+// reads like `kinds=normal,build`. Keys outside of any table go to the
+// section `""`, like the keys before the first section of `conf.ini`.
+//
+// The settings are not deserialized into a struct of their own: `conf.ini`
+// only holds strings, every setting is read by section and key through the
+// same lookup whatever the file, and each is parsed where it is used, as the
+// settings of `conf.ini` always were. `ufi` reads its `[storage] path` through
+// this module as well.
+use ini::Ini;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A value of `conf.toml` or `conf.json`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+    List(Vec<Setting>),
+    Table(BTreeMap<String, Setting>),
+}
+
+impl Setting {
+    /// The value as `conf.ini` would hold it, `None` for tables and lists
+    /// of them.
+    fn text(&self) -> Option<String> {
+        match *self {
+            Setting::Boolean(value) => Some(value.to_string()),
+            Setting::Integer(value) => Some(value.to_string()),
+            Setting::Float(value) => Some(value.to_string()),
+            Setting::Text(ref value) => Some(value.clone()),
+            Setting::List(ref values) => {
+                let values = values.iter().map(|value| value.text()).collect::<Option<Vec<_>>>()?;
+                Some(values.join(","))
+            }
+            Setting::Table(_) => None,
+        }
+    }
+}
+
+/// The settings of the configuration file, by section and key.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The file the settings were read from.
+    pub file: PathBuf,
+    sections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Config {
+    /// Read the configuration file of `dir`.
+    pub fn load(dir: &Path) -> Result<Config, Box<Error>> {
+        let toml = dir.join("conf.toml");
+        let json = dir.join("conf.json");
+        let file = if toml.exists() {
+            toml
+        } else if json.exists() {
+            json
+        } else {
+            dir.join("conf.ini")
+        };
+        let mut config = Config {
+            file: file.clone(),
+            sections: BTreeMap::new(),
+        };
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => config.add(String::new(), ::toml::from_str(&fs::read_to_string(&file)?)?)?,
+            Some("json") => config.add(String::new(), ::serde_json::from_str(&fs::read_to_string(&file)?)?)?,
+            _ => config.add_ini(&Ini::load_from_file(&file)?),
+        }
+        Ok(config)
+    }
+
+    /// Add the `settings` of `section`, and their tables as the sections
+    /// below it.
+    fn add(&mut self, section: String, settings: BTreeMap<String, Setting>) -> Result<(), Box<Error>> {
+        for (key, setting) in settings {
+            if let Setting::Table(table) = setting {
+                let nested = if section.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", section, key)
+                };
+                self.add(nested, table)?;
+                continue;
+            }
+            let value = setting
+                .text()
+                .ok_or_else(|| format!("{:?}: [{}] {} cannot be a list of tables", self.file, section, key))?;
+            self.sections.entry(section.clone()).or_insert_with(BTreeMap::new).insert(key, value);
+        }
+        Ok(())
+    }
+
+    fn add_ini(&mut self, ini: &Ini) {
+        for (section, properties) in ini.iter() {
+            let values = self
+                .sections
+                .entry(section.as_ref().map_or(String::new(), |s| s.to_string()))
+                .or_insert_with(BTreeMap::new);
+            values.extend(properties.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        }
+    }
+
+    /// The value of `key` in `section`, if set.
+    pub fn value(&self, section: &str, key: &str) -> Option<&str> {
+        self.sections.get(section).and_then(|s| s.get(key)).map(|v| v.as_str())
+    }
+
+    /// The settings of `section`, if it has any.
+    pub fn section(&self, section: &str) -> Option<&BTreeMap<String, String>> {
+        self.sections.get(section)
+    }
+
+    /// All settings by section, `""` for the keys outside of any.
+    pub fn sections(&self) -> &BTreeMap<String, BTreeMap<String, String>> {
+        &self.sections
+    }
+}
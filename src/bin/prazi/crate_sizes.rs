@@ -0,0 +1,32 @@
+// Sizes of the `.crate` files from a crates.io database dump, for
+// `download --max-crate-size`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The index does not know how large a crate file is. `versions.csv` of the
+// database dump has the `crate_size` of every version, whose crate is named
+// by its `crate_id` in `crates.csv`. Versions published after the dump, or
+// without a size in it, can only be checked against the `Content-Length` of
+// their download.
+use categories;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// The size in bytes of the `.crate` file of every version of the dump in
+/// `dir`, by crate name and version.
+pub fn read(dir: &Path) -> io::Result<HashMap<(String, String), u64>> {
+    let names = categories::read_table(dir, "crates.csv")?
+        .into_iter()
+        .filter_map(|mut row| Some((row.remove("id")?, row.remove("name")?)))
+        .collect::<HashMap<_, _>>();
+    Ok(categories::read_table(dir, "versions.csv")?
+        .into_iter()
+        .filter_map(|mut row| {
+            let name = names.get(&row.remove("crate_id")?)?.clone();
+            let size = row.remove("crate_size")?.parse().ok()?;
+            Some(((name, row.remove("num")?), size))
+        }).collect())
+}
@@ -0,0 +1,112 @@
+// Packaging of the corpus into a single publishable archive
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use report::{self, Envelope};
+use run_record;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tar::{Builder, Header};
+use {PraziCrate, PraziResult, PRAZI_DIR};
+
+/// Describes the contents of a packaged dataset, stored as `MANIFEST.json`.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    /// Commit of the crates.io index the crate list was read from.
+    pub index_commit: Option<String>,
+    /// Number of crates with provenance in the dataset.
+    pub crate_count: usize,
+    /// Filters that selected the crates, as given on the command line.
+    pub filters: Vec<String>,
+    /// Paths of all files in the archive, except the manifest.
+    pub files: Vec<String>,
+}
+
+/// `HEAD` of the git checkout of the index at `index_dir`.
+pub fn index_commit(index_dir: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .current_dir(index_dir)
+        .output()
+        .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+/// The merged call-based dependency network built by `create_prazi_graph.sh`.
+pub fn merged_graph() -> PathBuf {
+    Path::new(&**PRAZI_DIR)
+        .join("cdn")
+        .join("graphs")
+        .join("callgraph.ufi.merged.graph")
+}
+
+/// Bundle the provenance records of `crates`, all reports and optionally the
+/// merged graph into the gzipped tarball `out`.
+pub fn package(
+    out: &Path,
+    crates: &[PraziCrate],
+    index_dir: &str,
+    filters: Vec<String>,
+    with_graph: bool,
+) -> PraziResult<Manifest> {
+    // (path in archive, path on disk)
+    let mut files: Vec<(String, PathBuf)> = Vec::new();
+    let mut crate_count = 0;
+    for krate in crates {
+        let provenance = krate.provenance_file();
+        if provenance.exists() {
+            crate_count += 1;
+            files.push((
+                format!("provenance/{}/{}.json", krate.name, krate.version),
+                provenance,
+            ));
+        }
+    }
+    if let Ok(entries) = fs::read_dir(report::reports_dir()) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_file() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                files.push((format!("reports/{}", name), path));
+            }
+        }
+    }
+    for (phase, run) in run_record::run_files() {
+        files.push((format!("run-{}.json", phase), run));
+    }
+    if with_graph {
+        let graph = merged_graph();
+        if !graph.exists() {
+            return Err(format!("merged graph {:?} does not exist", graph).into());
+        }
+        files.push(("graphs/callgraph.ufi.merged.graph".to_string(), graph));
+    }
+
+    let manifest = Manifest {
+        index_commit: index_commit(index_dir),
+        crate_count,
+        filters,
+        files: files.iter().map(|f| f.0.clone()).collect(),
+    };
+    let manifest_json = Envelope::new("dataset-manifest", &manifest).to_json();
+
+    let mut builder = Builder::new(GzEncoder::new(File::create(out)?, Compression::default()));
+    let mut header = Header::new_gnu();
+    header.set_size(manifest_json.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, "MANIFEST.json", manifest_json.as_bytes())?;
+    for &(ref name, ref path) in &files {
+        builder.append_path_with_name(path, name)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(manifest)
+}
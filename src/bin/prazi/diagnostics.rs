@@ -0,0 +1,71 @@
+// Compiler diagnostics of a build by level, for the `build` report
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// In the human-readable output of cargo, a diagnostic of rustc is a
+// `warning: ...` or `error[E0308]: ...` line followed by the `-->` line of its
+// location; cargo's own messages, such as the `generated 3 warnings` summary,
+// have no location and are not counted, and neither are the diagnostics of
+// rustc without one. With `--message-format json` cargo reports every
+// diagnostic as a `compiler-message` on stdout, which counts them exactly.
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The level of a diagnostic header like `error[E0308]: mismatched types`.
+fn header_level(line: &str) -> Option<&str> {
+    let colon = line.find(": ")?;
+    let level = line[..colon].splitn(2, '[').next().unwrap();
+    if !level.is_empty() && level.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+        Some(level)
+    } else {
+        None
+    }
+}
+
+/// The diagnostics with a location in the human-readable `stderr` of a build,
+/// by level.
+pub fn count_text(stderr: &str) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    let mut lines = stderr.lines().peekable();
+    while let Some(line) = lines.next() {
+        let level = match header_level(line) {
+            Some(level) => level,
+            None => continue,
+        };
+        if lines.peek().map_or(false, |next| next.trim_start().starts_with("--> ")) {
+            *counts.entry(level.to_string()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// The `compiler-message`s of the `--message-format json` output of a build.
+fn compiler_messages(stdout: &str) -> Vec<Value> {
+    stdout
+        .lines()
+        .filter_map(|line| ::serde_json::from_str::<Value>(line).ok())
+        .filter(|message| message["reason"].as_str() == Some("compiler-message"))
+        .collect()
+}
+
+/// The diagnostics in the `--message-format json` output of a build, by level.
+pub fn count_json(stdout: &str) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for message in compiler_messages(stdout) {
+        if let Some(level) = message["message"]["level"].as_str() {
+            *counts.entry(level.to_string()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// The diagnostics of the `--message-format json` output of a build as rustc
+/// renders them, which cargo then leaves out of stderr.
+pub fn rendered(stdout: &str) -> String {
+    compiler_messages(stdout)
+        .iter()
+        .filter_map(|message| message["message"]["rendered"].as_str().map(|r| r.to_string()))
+        .collect()
+}
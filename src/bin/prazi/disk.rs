@@ -0,0 +1,136 @@
+// Stopping the run cleanly when the storage volume fills up
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// A full volume makes every worker fail at once, each with its own write
+// error. The first one detected stops the scheduling of new crates like
+// Ctrl-C does, and the run ends with a single "out of disk" message.
+use config_value;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use stop;
+use PRAZI_DIR;
+
+/// What tools print when a write failed for lack of space, e.g. in the
+/// output of `cargo build`.
+pub const FULL_MESSAGES: &[&str] = &["No space left on device", "Disk quota exceeded"];
+
+lazy_static! {
+    /// The first write that failed for lack of space.
+    static ref FULL_AT: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Did `error` happen because the volume is full?
+pub fn is_full(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        if let Some(code) = error.raw_os_error() {
+            return code == ::libc::ENOSPC || code == ::libc::EDQUOT;
+        }
+    }
+    is_full_output(&error.to_string())
+}
+
+/// Does the output of a command report a write that failed for lack of space?
+pub fn is_full_output(output: &str) -> bool {
+    FULL_MESSAGES.iter().any(|message| output.contains(message))
+}
+
+/// Record that writing `path` failed for lack of space and stop the run;
+/// only the first call prints.
+pub fn full(path: &Path) {
+    let mut full_at = FULL_AT.lock().unwrap();
+    if full_at.is_none() {
+        eprintln!(
+            "Out of disk writing {:?}, finishing work in flight ({})",
+            path,
+            usage_text()
+        );
+        *full_at = Some(path.to_path_buf());
+    }
+    stop::out_of_disk();
+}
+
+/// `is_full` for an `error` writing `path`, recording the full volume.
+pub fn check(path: &Path, error: &io::Error) -> bool {
+    let res = is_full(error);
+    if res {
+        full(path);
+    }
+    res
+}
+
+/// The message the run ends with when the volume filled up.
+pub fn exit_message() -> Option<String> {
+    FULL_AT.lock().unwrap().as_ref().map(|path| {
+        format!(
+            "Out of disk: writing {:?} failed, {}. Free some space and continue with --resume",
+            path,
+            usage_text()
+        )
+    })
+}
+
+/// Total and available bytes of the volume holding `path`.
+#[cfg(unix)]
+pub fn usage(path: &Path) -> Option<(u64, u64)> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: ::libc::statvfs = unsafe { ::std::mem::zeroed() };
+    if unsafe { ::libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block = stat.f_frsize as u64;
+    Some((stat.f_blocks as u64 * block, stat.f_bavail as u64 * block))
+}
+
+#[cfg(not(unix))]
+pub fn usage(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+fn mib(bytes: u64) -> u64 {
+    bytes / (1024 * 1024)
+}
+
+/// The usage of the storage volume, as printed in messages.
+fn usage_text() -> String {
+    match usage(Path::new(&**PRAZI_DIR)) {
+        Some((total, available)) => format!(
+            "{} MiB of {} MiB used, {} MiB available in {}",
+            mib(total - available.min(total)),
+            mib(total),
+            mib(available),
+            &**PRAZI_DIR
+        ),
+        None => format!("usage of {} unknown", &**PRAZI_DIR),
+    }
+}
+
+/// Whether the storage volume has the `[storage] min_free` MiB available
+/// that a phase is started with; stops the run when it does not.
+pub fn has_room(phase: &str) -> bool {
+    let min_free = match config_value("storage", "min_free") {
+        Some(v) => v.parse::<u64>().expect("invalid [storage] min_free"),
+        None => return true,
+    };
+    match usage(Path::new(&**PRAZI_DIR)) {
+        Some((_, available)) if mib(available) < min_free => {
+            eprintln!(
+                "Not starting {}: {} MiB available, [storage] min_free is {} MiB ({})",
+                phase,
+                mib(available),
+                min_free,
+                usage_text()
+            );
+            stop::out_of_disk();
+            false
+        }
+        _ => true,
+    }
+}
@@ -0,0 +1,126 @@
+// Download counts of crates, for `--order downloads`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The index has no download counts. `ingest-downloads` reads them from the
+// `crates.csv` of a database dump or pages through the crates.io API, and
+// stores them with the time they were fetched in
+// `{storage.path}/downloads.json`, which the other commands read.
+use categories;
+use chrono::{DateTime, Utc};
+use fetch;
+use report::Envelope;
+use reqwest::header::USER_AGENT;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use {PraziResult, PRAZI_DIR};
+
+/// Listing of all crates of the crates.io API, sorted by name.
+const API_URL: &str = "https://crates.io/api/v1/crates";
+
+/// crates.io asks crawlers for at most one request per second.
+pub const API_DELAY: Duration = Duration::from_secs(1);
+
+/// The download counts of all crates at some point in time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Downloads {
+    /// The database dump folder or `api`.
+    pub source: String,
+    pub fetched_at: String,
+    /// All-time downloads by crate name.
+    pub crates: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiPage {
+    crates: Vec<ApiCrate>,
+    meta: ApiMeta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCrate {
+    name: String,
+    downloads: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMeta {
+    /// Query string of the next page, absent on the last one.
+    next_page: Option<String>,
+}
+
+pub fn downloads_file() -> PathBuf {
+    Path::new(&**PRAZI_DIR).join("downloads.json")
+}
+
+/// Read the counts from the `data/` folder of a database dump.
+fn from_dump(dir: &Path) -> io::Result<BTreeMap<String, u64>> {
+    Ok(categories::read_table(dir, "crates.csv")?
+        .iter()
+        .filter_map(|row| {
+            let downloads = row.get("downloads")?.parse().ok()?;
+            Some((row.get("name")?.clone(), downloads))
+        }).collect())
+}
+
+/// Page through the crates.io API, one request per `API_DELAY`.
+fn from_api() -> PraziResult<BTreeMap<String, u64>> {
+    let client = ::reqwest::Client::new();
+    let mut crates = BTreeMap::new();
+    let mut query = "?per_page=100&sort=alpha".to_string();
+    loop {
+        let page: ApiPage = client
+            .get(&format!("{}{}", API_URL, query))
+            .header(USER_AGENT, fetch::user_agent())
+            .send()?
+            .error_for_status()?
+            .json()?;
+        crates.extend(page.crates.into_iter().map(|c| (c.name, c.downloads)));
+        progress!("{} crates fetched", crates.len());
+        match page.meta.next_page {
+            Some(next_page) => query = next_page,
+            None => break,
+        }
+        thread::sleep(API_DELAY);
+    }
+    Ok(crates)
+}
+
+/// Fetch the counts from `from`, a database dump folder or `api`.
+pub fn ingest(from: &str) -> PraziResult<Downloads> {
+    let crates = if from == "api" {
+        from_api()?
+    } else {
+        from_dump(Path::new(from))?
+    };
+    Ok(Downloads {
+        source: from.to_string(),
+        fetched_at: Utc::now().to_rfc3339(),
+        crates,
+    })
+}
+
+impl Downloads {
+    /// The stored counts, if any.
+    pub fn read() -> Option<Downloads> {
+        let text = fs::read_to_string(downloads_file()).ok()?;
+        let envelope: Envelope<Downloads> = ::serde_json::from_str(&text).ok()?;
+        Some(envelope.data)
+    }
+
+    pub fn write(&self) -> io::Result<()> {
+        fs::write(downloads_file(), Envelope::new("downloads", self).to_json())
+    }
+
+    /// How long ago the counts were fetched.
+    pub fn age(&self) -> Option<Duration> {
+        let fetched_at = DateTime::parse_from_rfc3339(&self.fetched_at).ok()?;
+        Utc::now().signed_duration_since(fetched_at).to_std().ok()
+    }
+}
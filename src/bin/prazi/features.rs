@@ -0,0 +1,99 @@
+// Building crates with several feature sets for `--feature-matrix`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The default feature set keeps the regular `target/` and `graphs/` folders,
+// so the other phases and the scripts see the same crate as without the
+// matrix. The other sets are built into `target/features/{set}` and their
+// graphs written to `graphs/{set}/`.
+use report::Status;
+use std::str::FromStr;
+
+/// A feature configuration a crate is built with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureSet {
+    Default,
+    AllFeatures,
+    NoDefaultFeatures,
+}
+
+/// The feature sets of `--feature-matrix` without a list.
+pub const MATRIX: [FeatureSet; 3] = [
+    FeatureSet::Default,
+    FeatureSet::AllFeatures,
+    FeatureSet::NoDefaultFeatures,
+];
+
+impl FeatureSet {
+    pub fn name(self) -> &'static str {
+        match self {
+            FeatureSet::Default => "default",
+            FeatureSet::AllFeatures => "all-features",
+            FeatureSet::NoDefaultFeatures => "no-default-features",
+        }
+    }
+
+    /// Arguments of `cargo build` selecting the features.
+    pub fn cargo_args(self) -> &'static [&'static str] {
+        match self {
+            FeatureSet::Default => &[],
+            FeatureSet::AllFeatures => &["--all-features"],
+            FeatureSet::NoDefaultFeatures => &["--no-default-features"],
+        }
+    }
+}
+
+impl FromStr for FeatureSet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FeatureSet, String> {
+        match s {
+            "default" => Ok(FeatureSet::Default),
+            "all-features" => Ok(FeatureSet::AllFeatures),
+            "no-default-features" => Ok(FeatureSet::NoDefaultFeatures),
+            _ => Err(format!("unknown feature set: {}", s)),
+        }
+    }
+}
+
+/// Outcome of the build of a crate with one feature set, in the `build`
+/// report.
+#[derive(Debug, Serialize)]
+pub struct FeatureBuild {
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitcode_files: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stderr: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FeatureBuild {
+    pub fn new(status: Status) -> FeatureBuild {
+        FeatureBuild {
+            status,
+            bitcode_files: None,
+            stderr: None,
+            error: None,
+        }
+    }
+}
+
+/// Record `features` on the DOT text of a call graph as a graph attribute
+/// after the opening line, which the node and edge parsers ignore.
+pub fn label_graph(text: &str, features: FeatureSet) -> String {
+    let mut res = String::with_capacity(text.len() + 32);
+    let mut labeled = false;
+    for line in text.lines() {
+        res.push_str(line);
+        res.push('\n');
+        if !labeled && line.trim_start().starts_with("digraph") {
+            res.push_str(&format!("\tfeatures=\"{}\";\n", features.name()));
+            labeled = true;
+        }
+    }
+    res
+}
@@ -0,0 +1,194 @@
+// Downloading `.crate` files to disk, resuming interrupted transfers
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The body is streamed to a partial file next to the crate's sources. When the
+// transfer breaks off, the next attempt asks for the missing bytes with a
+// `Range` header; a server ignoring it answers with the whole file, which then
+// replaces the partial one. A partial file left by an aborted run is resumed
+// the same way by the next run. With a size limit, a response whose
+// `Content-Length` announces a larger file is dropped before its body is read.
+//
+// Every request identifies the tool with `[download] user_agent`, so that the
+// operators of the registry know whom to contact about the traffic.
+use config_value;
+use disk;
+use futures::future::{self, Loop};
+use futures::{Future, Stream};
+use metrics::{self, METRICS};
+use provenance::Provenance;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, ETAG, LAST_MODIFIED, RANGE, USER_AGENT};
+use reqwest::r#async::{Client, Decoder};
+use reqwest::StatusCode;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use stop;
+
+/// Attempts of a download, all but the first resuming the partial file.
+const ATTEMPTS: u32 = 3;
+
+lazy_static! {
+    /// `User-Agent` of all requests to the registry, `[download] user_agent`.
+    static ref USER_AGENT_VALUE: String = config_value("download", "user_agent")
+        .unwrap_or_else(|| format!("rustprazi/{} (+https://github.com/praezi/rust)", env!("CARGO_PKG_VERSION")));
+    /// Time between the starts of two downloads, `[download] request_delay`.
+    pub static ref REQUEST_DELAY: Option<Duration> = config_value("download", "request_delay")
+        .map(|v| stop::parse_duration(&v).expect("invalid [download] request_delay"));
+}
+
+/// The `User-Agent` the tool identifies itself with.
+pub fn user_agent() -> &'static str {
+    &USER_AGENT_VALUE
+}
+
+/// A client of the downloads sending `user_agent` with every request.
+pub fn client() -> Result<Client, ::reqwest::Error> {
+    let mut headers = HeaderMap::new();
+    let agent = HeaderValue::from_str(user_agent()).expect("invalid [download] user_agent");
+    headers.insert(USER_AGENT, agent);
+    Client::builder().default_headers(headers).build()
+}
+
+/// How a download ended.
+pub enum Fetched {
+    /// The server reports the downloaded copy as current.
+    NotModified,
+    /// The whole `.crate` file is in the partial file.
+    Complete {
+        /// The `ETag` and `Last-Modified` of the response.
+        provenance: Provenance,
+        /// Whether some of the file came from an earlier, broken off attempt.
+        resumed: bool,
+    },
+    /// The server refused the download, retrying does not help.
+    Failed(String),
+    /// The file has more bytes than the limit, nothing was kept of it.
+    TooLarge(u64),
+}
+
+/// Copy the `.crate` file `file` of a `--source-dir` to `part`, where the
+/// download would have put it.
+pub fn copy_local(file: &Path, part: &Path) -> Fetched {
+    match fs::copy(file, part) {
+        Ok(_) => Fetched::Complete {
+            provenance: Provenance::default(),
+            resumed: false,
+        },
+        Err(error) => {
+            disk::check(part, &error);
+            Fetched::Failed(format!("copying {:?}: {}", file, error))
+        }
+    }
+}
+
+type FetchFuture = Box<Future<Item = Loop<Fetched, (u32, bool)>, Error = String>>;
+
+/// Download `url` into `part`, sending the `conditional` headers when there
+/// is nothing to resume, unless the file has more than `max_bytes`.
+pub fn fetch(
+    client: &Client,
+    url: String,
+    part: PathBuf,
+    conditional: Vec<(HeaderName, String)>,
+    max_bytes: Option<u64>,
+) -> Box<Future<Item = Fetched, Error = String>> {
+    let client = client.clone();
+    Box::new(future::loop_fn((1, false), move |(attempt, resumed)| {
+        let offset = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+        let mut request = client.get(&url);
+        if offset > 0 {
+            request = request.header(RANGE, format!("bytes={}-", offset));
+        } else {
+            for &(ref name, ref value) in &conditional {
+                request = request.header(name.clone(), value.as_str());
+            }
+        }
+        let part = part.clone();
+        let url = url.clone();
+        request
+            .send()
+            .map_err(|e| e.to_string())
+            .and_then(move |mut res| -> FetchFuture {
+                let status = res.status();
+                let provenance = {
+                    let header = |name: HeaderName| {
+                        res.headers()
+                            .get(name)
+                            .and_then(|v| v.to_str().ok())
+                            .map(|v| v.to_string())
+                    };
+                    Provenance {
+                        etag: header(ETAG),
+                        last_modified: header(LAST_MODIFIED),
+                        ..Provenance::default()
+                    }
+                };
+                if status == StatusCode::NOT_MODIFIED {
+                    return Box::new(future::ok(Loop::Break(Fetched::NotModified)));
+                }
+                if status == StatusCode::RANGE_NOT_SATISFIABLE && offset > 0 {
+                    // nothing left to send, the checksum tells whether the file is whole
+                    return Box::new(future::ok(Loop::Break(Fetched::Complete {
+                        provenance,
+                        resumed: true,
+                    })));
+                }
+                if !status.is_success() {
+                    return Box::new(future::ok(Loop::Break(Fetched::Failed(format!(
+                        "HTTP {}",
+                        status
+                    )))));
+                }
+                let append = status == StatusCode::PARTIAL_CONTENT;
+                // a partial response only announces the bytes still missing
+                let bytes = res.content_length().map(|length| if append { offset + length } else { length });
+                if let (Some(bytes), Some(max)) = (bytes, max_bytes) {
+                    if bytes > max {
+                        fs::remove_file(&part).ok();
+                        return Box::new(future::ok(Loop::Break(Fetched::TooLarge(bytes))));
+                    }
+                }
+                let file = OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(append)
+                    .truncate(!append)
+                    .open(&part);
+                let mut file = match file {
+                    Ok(file) => file,
+                    Err(error) => {
+                        disk::check(&part, &error);
+                        return Box::new(future::err(error.to_string()));
+                    }
+                };
+                let body = std::mem::replace(res.body_mut(), Decoder::empty());
+                Box::new(
+                    body.map_err(|e| e.to_string())
+                        .for_each(move |chunk| {
+                            metrics::add(&METRICS.bytes_downloaded, chunk.len());
+                            file.write_all(&chunk).map_err(|error| {
+                                disk::check(&part, &error);
+                                error.to_string()
+                            })
+                        }).map(move |()| {
+                            Loop::Break(Fetched::Complete {
+                                provenance,
+                                resumed: resumed || append,
+                            })
+                        }),
+                )
+            }).or_else(move |error| {
+                // a full disk or Ctrl-C would only make the retry fail as well
+                if attempt < ATTEMPTS && !stop::should_stop() {
+                    eprintln!("Download of {} broke off, resuming: {}", url, error);
+                    Ok(Loop::Continue((attempt + 1, resumed || offset > 0)))
+                } else {
+                    Err(error)
+                }
+            })
+    }))
+}
@@ -0,0 +1,615 @@
+// Parsing of the LLVM call graphs emitted by `opt -dot-callgraph`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use checksum::Algo;
+use config_value;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use regex::{Captures, Regex};
+use rustc_demangle::demangle;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+lazy_static! {
+    /// Whether call graphs are stored gzipped, `[output] compress`.
+    static ref COMPRESS: bool = match config_value("output", "compress").as_ref().map(|v| v.as_str()) {
+        None | Some("none") => false,
+        Some("gzip") => true,
+        Some(other) => panic!("invalid [output] compress: {}, expected gzip or none", other),
+    };
+}
+
+/// `path` with `.gz` appended, where the graph of `path` is stored when it
+/// is compressed.
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// The file the graph `path` is stored in: `path`, or `path.gz` when only
+/// the compressed graph exists.
+pub fn stored(path: &Path) -> PathBuf {
+    let gz = gz_path(path);
+    if !path.exists() && gz.exists() {
+        gz
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// The text of the graph `path`, decompressed when it is stored gzipped.
+pub fn read_text(path: &Path) -> io::Result<String> {
+    let stored = stored(path);
+    if stored.extension().map_or(false, |ext| ext == "gz") {
+        let mut text = String::new();
+        GzDecoder::new(fs::File::open(&stored)?).read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        fs::read_to_string(&stored)
+    }
+}
+
+/// Write the graph `text` to `path`, or gzipped to `path.gz` with `[output]
+/// compress = gzip`, and remove the other file of the two, e.g. the output of
+/// `opt` or the graph of an earlier run. Returns the file written.
+pub fn write(path: &Path, text: &str) -> io::Result<PathBuf> {
+    let (file, other) = if *COMPRESS {
+        (gz_path(path), path.to_path_buf())
+    } else {
+        (path.to_path_buf(), gz_path(path))
+    };
+    if *COMPRESS {
+        let mut encoder = GzEncoder::new(BufWriter::new(fs::File::create(&file)?), Compression::default());
+        encoder.write_all(text.as_bytes())?;
+        encoder.finish()?.flush()?;
+    } else {
+        fs::write(&file, text)?;
+    }
+    if other.exists() {
+        fs::remove_file(&other)?;
+    }
+    Ok(file)
+}
+
+/// A call graph as emitted by `opt -dot-callgraph`.
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    /// Node id (e.g. `Node0x7f9d2a5027e0`) to function label.
+    pub nodes: HashMap<String, String>,
+    /// Edges between node ids, in file order.
+    pub edges: Vec<(String, String)>,
+}
+
+fn node_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^\s*(Node\w+)\s*\[.*label="\{(.*?)\}""#).unwrap();
+    }
+    &RE
+}
+
+fn edge_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"^\s*(Node\w+)\s*->\s*(Node\w+)").unwrap();
+    }
+    &RE
+}
+
+/// The number of edges in the DOT text of a call graph.
+pub fn edge_count(text: &str) -> usize {
+    text.lines().filter(|line| edge_regex().is_match(line)).count()
+}
+
+impl CallGraph {
+    /// Parse the DOT text of a call graph.
+    pub fn parse(text: &str) -> CallGraph {
+        let mut graph = CallGraph::default();
+        for line in text.lines() {
+            if let Some(caps) = edge_regex().captures(line) {
+                graph.edges.push((caps[1].to_string(), caps[2].to_string()));
+            } else if let Some(caps) = node_regex().captures(line) {
+                graph.nodes.insert(caps[1].to_string(), caps[2].to_string());
+            }
+        }
+        graph
+    }
+
+    /// The graph of the file `path`, which may be stored gzipped, see
+    /// `stored`.
+    pub fn load(path: &Path) -> io::Result<CallGraph> {
+        let text = read_text(path)?;
+        Ok(CallGraph::parse(&text))
+    }
+
+    /// The set of normalized function names of the graph.
+    pub fn functions(&self) -> BTreeSet<String> {
+        self.nodes.values().map(|label| normalize_symbol(label)).collect()
+    }
+
+    /// The edges by the labels of their nodes, as in the DOT text.
+    pub fn labeled_edges(&self) -> BTreeSet<(String, String)> {
+        self.edges
+            .iter()
+            .filter_map(|&(ref from, ref to)| {
+                match (self.nodes.get(from), self.nodes.get(to)) {
+                    (Some(from), Some(to)) => Some((from.clone(), to.clone())),
+                    _ => None,
+                }
+            }).collect()
+    }
+
+    /// The set of edges between normalized function names of the graph.
+    ///
+    /// Edges from or to nodes without a label (e.g. the external node) are
+    /// ignored.
+    pub fn named_edges(&self) -> BTreeSet<(String, String)> {
+        self.edges
+            .iter()
+            .filter_map(|&(ref from, ref to)| {
+                match (self.nodes.get(from), self.nodes.get(to)) {
+                    (Some(from), Some(to)) => Some((normalize_symbol(from), normalize_symbol(to))),
+                    _ => None,
+                }
+            }).collect()
+    }
+}
+
+/// SHA-256 of the canonical form of `graphs` taken together: the sorted
+/// distinct node labels, one per line, then the sorted distinct edges as the
+/// positions of their labels in that list. The node ids `opt` picks and the
+/// order of the lines do not change the hash, so identical graphs of two
+/// crates or two runs hash alike.
+pub fn content_hash(graphs: &[CallGraph]) -> String {
+    let labels = graphs
+        .iter()
+        .flat_map(|graph| graph.nodes.values())
+        .collect::<BTreeSet<_>>();
+    let ids = labels
+        .iter()
+        .enumerate()
+        .map(|(id, label)| (*label, id))
+        .collect::<HashMap<_, _>>();
+    let mut edges = BTreeSet::new();
+    for graph in graphs {
+        for &(ref from, ref to) in &graph.edges {
+            if let (Some(from), Some(to)) = (graph.nodes.get(from), graph.nodes.get(to)) {
+                edges.insert((ids[from], ids[to]));
+            }
+        }
+    }
+    let mut canonical = String::new();
+    for label in &labels {
+        canonical.push_str(label);
+        canonical.push('\n');
+    }
+    for (from, to) in edges {
+        canonical.push_str(&format!("{} -> {}\n", from, to));
+    }
+    Algo::Sha256.digest(canonical.as_bytes())
+}
+
+/// `content_hash` of the graph files `paths`.
+pub fn content_hash_files(paths: &[PathBuf]) -> io::Result<String> {
+    let graphs = paths
+        .iter()
+        .map(|path| CallGraph::load(path))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(content_hash(&graphs))
+}
+
+/// Drop repeated edges from the DOT text of a call graph, `opt` emits one
+/// edge per call site. Other lines are kept in order. Returns the new text and
+/// the number of edges dropped.
+///
+/// The duplicates are not turned into an edge attribute, as `ufi` and the
+/// scripts of the graph generator only accept plain `A -> B;` edge lines.
+pub fn dedup_edges(text: &str) -> (String, usize) {
+    let mut seen = HashSet::new();
+    let mut dropped = 0;
+    let mut out = String::with_capacity(text.len());
+    for line in text.lines() {
+        if let Some(caps) = edge_regex().captures(line) {
+            if !seen.insert((caps[1].to_string(), caps[2].to_string())) {
+                dropped += 1;
+                continue;
+            }
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    (out, dropped)
+}
+
+/// Normalize a node label so that the same function matches across crate
+/// versions: symbols are demangled, the trailing hash is dropped, and the
+/// `v_<version>` segment of unique function identifiers is removed.
+pub fn normalize_symbol(label: &str) -> String {
+    lazy_static! {
+        static ref HASH: Regex = Regex::new(r"::h[0-9a-f]{16}$").unwrap();
+        static ref UFI_VERSION: Regex =
+            Regex::new(r"(io :: crates :: \w+) :: v_\w+").unwrap();
+    }
+    let demangled = format!("{:#}", demangle(label));
+    let unhashed = HASH.replace(&demangled, "");
+    UFI_VERSION.replace_all(&unhashed, "$1").into_owned()
+}
+
+fn ufi_crate_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"io :: crates :: (\w+) :: v_(\w+)").unwrap();
+    }
+    &RE
+}
+
+/// Crate name and version segments (`name`, `v_version` without `v_`) of the
+/// first unique function identifier in `label`, i.e. the defining crate.
+pub fn ufi_crate(label: &str) -> Option<(&str, &str)> {
+    ufi_crate_regex().captures(label).map(|caps| {
+        (
+            caps.get(1).unwrap().as_str(),
+            caps.get(2).unwrap().as_str(),
+        )
+    })
+}
+
+/// Replace the crate segments (`io :: crates :: name :: v_version`) of all
+/// unique function identifiers in `label` for which `f` returns a value.
+pub fn map_ufi_crates<F: Fn(&str, &str) -> Option<String>>(label: &str, f: F) -> String {
+    ufi_crate_regex()
+        .replace_all(label, |caps: &Captures| {
+            f(&caps[1], &caps[2]).unwrap_or_else(|| caps[0].to_string())
+        }).into_owned()
+}
+
+/// The identifier `ufi` turns a crate name or version into, e.g. `1_0_80`.
+pub fn ufi_ident(text: &str) -> String {
+    text.replace('.', "_").replace('-', "_").replace('+', "_")
+}
+
+/// Normalized names of the functions of `graphs` that call `symbol`, a label
+/// or normalized name; with `transitive` also the functions calling those,
+/// and so on. Functions are matched by their normalized names across the
+/// graphs, like the graphs of the codegen units of one crate.
+pub fn callers(graphs: &[CallGraph], symbol: &str, transitive: bool) -> BTreeSet<String> {
+    let mut callers_of: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for graph in graphs {
+        for (from, to) in graph.named_edges() {
+            callers_of.entry(to).or_insert_with(BTreeSet::new).insert(from);
+        }
+    }
+    let mut res = BTreeSet::new();
+    let mut todo = vec![normalize_symbol(symbol)];
+    while let Some(callee) = todo.pop() {
+        for caller in callers_of.get(&callee).into_iter().flat_map(|callers| callers.iter()) {
+            if res.insert(caller.clone()) && transitive {
+                todo.push(caller.clone());
+            }
+        }
+    }
+    res
+}
+
+/// Functions and edges that differ between two call graphs.
+#[derive(Debug, Serialize)]
+pub struct CallGraphDiff {
+    pub added_functions: Vec<String>,
+    pub removed_functions: Vec<String>,
+    pub added_edges: Vec<(String, String)>,
+    pub removed_edges: Vec<(String, String)>,
+}
+
+impl CallGraphDiff {
+    pub fn new(from: &CallGraph, to: &CallGraph) -> CallGraphDiff {
+        let (from_fns, to_fns) = (from.functions(), to.functions());
+        let (from_edges, to_edges) = (from.named_edges(), to.named_edges());
+        CallGraphDiff {
+            added_functions: to_fns.difference(&from_fns).cloned().collect(),
+            removed_functions: from_fns.difference(&to_fns).cloned().collect(),
+            added_edges: to_edges.difference(&from_edges).cloned().collect(),
+            removed_edges: from_edges.difference(&to_edges).cloned().collect(),
+        }
+    }
+}
+
+/// Magic bytes at the start of the binary graph format.
+///
+/// The layout, all integers as unsigned LEB128 varints:
+///
+/// - `PRZG` followed by the format version byte `1`
+/// - the node count, then per node the byte length and UTF-8 bytes of its
+///   label; nodes are sorted by label and identified by their position
+/// - the edge count, then per edge the caller and callee node positions
+pub const BINARY_MAGIC: &[u8; 4] = b"PRZG";
+pub const BINARY_VERSION: u8 = 1;
+
+pub fn write_varint<W: Write>(w: &mut W, mut value: u64) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub fn read_varint<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        r.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::InvalidData, "varint too long"))
+}
+
+pub fn write_string<W: Write>(w: &mut W, text: &str) -> io::Result<()> {
+    write_varint(w, text.len() as u64)?;
+    w.write_all(text.as_bytes())
+}
+
+/// Magic, version and node count of a binary graph.
+pub fn write_binary_header<W: Write>(w: &mut W, nodes: u64) -> io::Result<()> {
+    w.write_all(BINARY_MAGIC)?;
+    w.write_all(&[BINARY_VERSION])?;
+    write_varint(w, nodes)
+}
+
+/// A graph in the binary format, see `BINARY_MAGIC`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct BinaryGraph {
+    /// Labels, indexed by node position.
+    pub nodes: Vec<String>,
+    /// Caller and callee positions.
+    pub edges: Vec<(u64, u64)>,
+}
+
+impl BinaryGraph {
+    pub fn read<R: Read>(r: &mut R) -> io::Result<BinaryGraph> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut header = [0u8; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != BINARY_MAGIC {
+            return Err(invalid("not a binary call graph"));
+        }
+        if header[4] != BINARY_VERSION {
+            return Err(invalid("unsupported binary call graph version"));
+        }
+        let mut graph = BinaryGraph::default();
+        for _ in 0..read_varint(r)? {
+            // the length is read from the file, allocate only what it has
+            let len = read_varint(r)?;
+            let mut label = Vec::new();
+            r.by_ref().take(len).read_to_end(&mut label)?;
+            if label.len() as u64 != len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "label is cut short"));
+            }
+            graph
+                .nodes
+                .push(String::from_utf8(label).map_err(|_| invalid("label is not UTF-8"))?);
+        }
+        for _ in 0..read_varint(r)? {
+            let edge = (read_varint(r)?, read_varint(r)?);
+            if edge.0 >= graph.nodes.len() as u64 || edge.1 >= graph.nodes.len() as u64 {
+                return Err(invalid("edge refers to a missing node"));
+            }
+            graph.edges.push(edge);
+        }
+        Ok(graph)
+    }
+
+    pub fn load(path: &Path) -> io::Result<BinaryGraph> {
+        BinaryGraph::read(&mut io::BufReader::new(fs::File::open(path)?))
+    }
+
+    /// Write the graph as DOT, in the layout of the text merged graph.
+    pub fn write_dot<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut w = BufWriter::new(w);
+        writeln!(w, "digraph \"Call graph\" {{")?;
+        for node in &self.nodes {
+            writeln!(w, "\t\"{}\";", node)?;
+        }
+        for &(from, to) in &self.edges {
+            writeln!(
+                w,
+                "\t\"{}\" -> \"{}\";",
+                self.nodes[from as usize], self.nodes[to as usize]
+            )?;
+        }
+        writeln!(w, "}}")?;
+        w.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{u32, u64};
+
+    /// `graph` in the binary format, written field by field; the writer of
+    /// `merge` is tested there.
+    fn binary(graph: &BinaryGraph) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_binary_header(&mut bytes, graph.nodes.len() as u64).unwrap();
+        for node in &graph.nodes {
+            write_string(&mut bytes, node).unwrap();
+        }
+        write_varint(&mut bytes, graph.edges.len() as u64).unwrap();
+        for &(from, to) in &graph.edges {
+            write_varint(&mut bytes, from).unwrap();
+            write_varint(&mut bytes, to).unwrap();
+        }
+        bytes
+    }
+
+    fn varint(value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, value).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn binary_graph_round_trips() {
+        let graph = BinaryGraph {
+            nodes: (0..200)
+                .map(|i| format!("io :: crates :: a :: v_1_0_0 :: f{}", i))
+                .chain(vec![String::new(), "µ".to_string()])
+                .collect(),
+            edges: vec![(0, 1), (1, 0), (0, 0), (199, 200), (201, 127), (128, 3)],
+        };
+        let bytes = binary(&graph);
+        assert_eq!(&bytes[..5], b"PRZG\x01");
+        assert_eq!(BinaryGraph::read(&mut &bytes[..]).unwrap(), graph);
+        let empty = BinaryGraph::default();
+        assert_eq!(BinaryGraph::read(&mut &binary(&empty)[..]).unwrap(), empty);
+    }
+
+    #[test]
+    fn varint_edge_cases() {
+        assert_eq!(varint(0), vec![0]);
+        assert_eq!(varint(127), vec![0x7f]);
+        assert_eq!(varint(128), vec![0x80, 0x01]);
+        assert_eq!(varint(300), vec![0xac, 0x02]);
+        let mut max = vec![0xff; 9];
+        max.push(0x01);
+        assert_eq!(varint(u64::MAX), max);
+        for &value in &[0, 1, 127, 128, 16_383, 16_384, u32::MAX as u64, u64::MAX - 1, u64::MAX] {
+            let bytes = varint(value);
+            let mut r = &bytes[..];
+            assert_eq!(read_varint(&mut r).unwrap(), value);
+            assert!(r.is_empty());
+        }
+    }
+
+    #[test]
+    fn varint_errors() {
+        let error = read_varint(&mut &[0x80u8; 10][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = read_varint(&mut &[0x80u8, 0x80][..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn binary_graph_bad_magic() {
+        let mut bytes = binary(&BinaryGraph::default());
+        bytes[0] = b'X';
+        let error = BinaryGraph::read(&mut &bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "not a binary call graph");
+        let error = BinaryGraph::read(&mut &b"digraph"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn binary_graph_bad_version() {
+        let mut bytes = binary(&BinaryGraph::default());
+        bytes[4] = BINARY_VERSION + 1;
+        let error = BinaryGraph::read(&mut &bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "unsupported binary call graph version");
+    }
+
+    #[test]
+    fn binary_graph_truncated_or_inconsistent() {
+        // a label claiming more bytes than the file has is not allocated
+        let mut bytes = Vec::new();
+        write_binary_header(&mut bytes, 1).unwrap();
+        write_varint(&mut bytes, u64::MAX).unwrap();
+        bytes.extend(b"abc");
+        let error = BinaryGraph::read(&mut &bytes[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let graph = BinaryGraph {
+            nodes: vec!["a".to_string(), "b".to_string()],
+            edges: vec![(0, 2)],
+        };
+        let error = BinaryGraph::read(&mut &binary(&graph)[..]).unwrap_err();
+        assert_eq!(error.to_string(), "edge refers to a missing node");
+
+        let graph = BinaryGraph {
+            nodes: vec!["a".to_string()],
+            edges: vec![(0, 0)],
+        };
+        let bytes = binary(&graph);
+        assert!(BinaryGraph::read(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn dedup_edges_keeps_the_first_of_every_edge() {
+        let text = "digraph \"Call graph\" {\n\
+                    \tNode0x1 [shape=record,label=\"{a}\"];\n\
+                    \tNode0x1 -> Node0x2;\n\
+                    \tNode0x2 [shape=record,label=\"{b}\"];\n\
+                    \tNode0x1 -> Node0x2;\n\
+                    \tNode0x2 -> Node0x1;\n\
+                    \tNode0x1 -> Node0x2;\n\
+                    }";
+        let (deduped, dropped) = dedup_edges(text);
+        assert_eq!(dropped, 2);
+        assert_eq!(
+            deduped,
+            "digraph \"Call graph\" {\n\
+             \tNode0x1 [shape=record,label=\"{a}\"];\n\
+             \tNode0x1 -> Node0x2;\n\
+             \tNode0x2 [shape=record,label=\"{b}\"];\n\
+             \tNode0x2 -> Node0x1;\n\
+             }\n"
+        );
+        assert_eq!(dedup_edges(&deduped), (deduped.clone(), 0));
+    }
+
+    /// The DOT text of a call graph with the `nodes` ids and labels and the
+    /// `edges` between ids, in the given order.
+    fn dot(nodes: &[(&str, &str)], edges: &[(&str, &str)]) -> CallGraph {
+        let mut text = "digraph \"Call graph\" {\n".to_string();
+        for &(id, label) in nodes {
+            text.push_str(&format!("\t{} [shape=record,label=\"{{{}}}\"];\n", id, label));
+        }
+        for &(from, to) in edges {
+            text.push_str(&format!("\t{} -> {};\n", from, to));
+        }
+        text.push_str("}\n");
+        CallGraph::parse(&text)
+    }
+
+    #[test]
+    fn content_hash_ignores_node_ids_and_order() {
+        let graph = dot(
+            &[("Node0x1", "main"), ("Node0x2", "helper"), ("Node0x3", "write")],
+            &[("Node0x1", "Node0x2"), ("Node0x2", "Node0x3")],
+        );
+        let reordered = dot(
+            &[("Node0xc", "write"), ("Node0xa", "main"), ("Node0xb", "helper")],
+            &[("Node0xb", "Node0xc"), ("Node0xa", "Node0xb"), ("Node0xa", "Node0xb")],
+        );
+        let hash = content_hash(&[graph]);
+        assert_eq!(hash.len(), 64);
+        assert_eq!(content_hash(&[reordered]), hash);
+
+        // the graphs of the codegen units of a crate hash as one
+        let units = [
+            dot(&[("Node0x1", "main"), ("Node0x2", "helper")], &[("Node0x1", "Node0x2")]),
+            dot(&[("Node0x1", "helper"), ("Node0x2", "write")], &[("Node0x1", "Node0x2")]),
+        ];
+        assert_eq!(content_hash(&units), hash);
+
+        let reversed = dot(
+            &[("Node0x1", "main"), ("Node0x2", "helper"), ("Node0x3", "write")],
+            &[("Node0x2", "Node0x1"), ("Node0x2", "Node0x3")],
+        );
+        assert_ne!(content_hash(&[reversed]), hash);
+        let renamed = dot(
+            &[("Node0x1", "main"), ("Node0x2", "helper2"), ("Node0x3", "write")],
+            &[("Node0x1", "Node0x2"), ("Node0x2", "Node0x3")],
+        );
+        assert_ne!(content_hash(&[renamed]), hash);
+    }
+}
@@ -0,0 +1,78 @@
+// Hashes of the bitcode files the call graphs of a crate were built from
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// `build-callgraphs` writes the state to `graphs/state.json` after building
+// the graphs of a crate and skips the crate on the next run while its state
+// is the same, so only the crates rebuilt in between are processed again.
+// Changing the options the graphs are built with changes the state as well.
+use checksum::Algo;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use PraziCrate;
+
+/// What the call graphs of a crate were built from.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphState {
+    /// Whether the graphs kept the repeated edges, see `--keep-multi-edges`.
+    pub keep_multi_edges: bool,
+    /// Whether the bitcode files were linked into one module first.
+    #[serde(default)]
+    pub linked: bool,
+    /// The `opt` passes of `--resolve-indirect`, if any.
+    #[serde(default)]
+    pub resolve_passes: Option<String>,
+    /// Whether the source locations of the functions were recorded, see
+    /// `--with-debuginfo`.
+    #[serde(default)]
+    pub locations: bool,
+    /// BLAKE3 of every bitcode file by its path relative to the crate folder.
+    pub bitcodes: BTreeMap<String, String>,
+}
+
+impl GraphState {
+    /// The state of graphs built now from `bitcodes` of `krate`.
+    pub fn new(
+        krate: &PraziCrate,
+        bitcodes: &[PathBuf],
+        keep_multi_edges: bool,
+        linked: bool,
+        resolve_passes: &Option<String>,
+        locations: bool,
+    ) -> io::Result<GraphState> {
+        let dir = krate.dir();
+        let mut hashes = BTreeMap::new();
+        for bitcode in bitcodes {
+            let name = bitcode.strip_prefix(&dir).unwrap_or(bitcode);
+            hashes.insert(
+                name.to_string_lossy().into_owned(),
+                Algo::Blake3.digest(&fs::read(bitcode)?),
+            );
+        }
+        Ok(GraphState {
+            keep_multi_edges,
+            linked,
+            resolve_passes: resolve_passes.clone(),
+            locations,
+            bitcodes: hashes,
+        })
+    }
+
+    /// The state of the graphs of the previous run, if any.
+    pub fn read(krate: &PraziCrate) -> Option<GraphState> {
+        let text = fs::read_to_string(krate.graph_state_file()).ok()?;
+        ::serde_json::from_str(&text).ok()
+    }
+
+    pub fn write(&self, krate: &PraziCrate) -> io::Result<()> {
+        fs::create_dir_all(krate.graphs_dir())?;
+        fs::write(
+            krate.graph_state_file(),
+            ::serde_json::to_string_pretty(self).unwrap(),
+        )
+    }
+}
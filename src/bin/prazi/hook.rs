@@ -0,0 +1,145 @@
+// User supplied commands run for every crate
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use wait_timeout::ChildExt;
+use report;
+use {config_value, PraziCrate};
+
+/// A command template with `{dir}`, `{name}` and `{version}` placeholders.
+#[derive(Debug, Clone)]
+pub struct Hook {
+    template: String,
+    timeout: Option<Duration>,
+}
+
+/// What a hook run printed and how it ended.
+#[derive(Debug, Serialize)]
+pub struct HookOutput {
+    pub command: String,
+    /// Exit code, `null` when the hook was killed.
+    pub status: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl HookOutput {
+    pub fn success(&self) -> bool {
+        self.status == Some(0)
+    }
+}
+
+impl Hook {
+    /// The `[hooks] post_build` command, limited by `[hooks] timeout` seconds.
+    pub fn post_build() -> Option<Hook> {
+        config_value("hooks", "post_build").map(|template| Hook {
+            template,
+            timeout: config_value("hooks", "timeout")
+                .map(|v| Duration::from_secs(v.parse().expect("invalid [hooks] timeout"))),
+        })
+    }
+
+    pub fn command(&self, krate: &PraziCrate) -> String {
+        self.template
+            .replace("{dir}", &krate.dir().to_string_lossy())
+            .replace("{name}", &krate.name)
+            .replace("{version}", &krate.version)
+    }
+
+    /// Run the hook through `sh` in the crate's folder.
+    pub fn run(&self, krate: &PraziCrate) -> io::Result<HookOutput> {
+        let command = self.command(krate);
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .current_dir(krate.dir())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        // drain the pipes while waiting, a full pipe would block the hook
+        let stdout = drain(child.stdout.take().unwrap());
+        let stderr = drain(child.stderr.take().unwrap());
+        let (status, timed_out) = match self.timeout {
+            Some(timeout) => match child.wait_timeout(timeout)? {
+                Some(status) => (status.code(), false),
+                None => {
+                    child.kill()?;
+                    child.wait()?;
+                    (None, true)
+                }
+            },
+            None => (child.wait()?.code(), false),
+        };
+        Ok(HookOutput {
+            command,
+            status,
+            timed_out,
+            stdout: report::tail(&stdout.join().unwrap_or_default()),
+            stderr: report::tail(&stderr.join().unwrap_or_default()),
+        })
+    }
+}
+
+/// A command template wrapping every `cargo build`, e.g. in `bwrap` or
+/// `docker run`, with `{dir}`, `{name}`, `{version}` and `{command}`
+/// placeholders; `{command}` is the quoted cargo command line.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    template: String,
+}
+
+impl Sandbox {
+    /// The `[build] sandbox` command.
+    pub fn from_config() -> Option<Sandbox> {
+        config_value("build", "sandbox").map(|template| Sandbox { template })
+    }
+
+    /// `program` with `args` run through `sh` inside the sandbox, in `dir`:
+    /// the folder of `krate` or the copy it is built in.
+    pub fn command(&self, krate: &PraziCrate, dir: &Path, program: &str, args: &[String]) -> Command {
+        let command = Some(program)
+            .into_iter()
+            .chain(args.iter().map(|arg| arg.as_str()))
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+        // `{command}` last, so that nothing is substituted in the command line
+        let line = self
+            .template
+            .replace("{dir}", &shell_quote(&dir.to_string_lossy()))
+            .replace("{name}", &krate.name)
+            .replace("{version}", &krate.version)
+            .replace("{command}", &command);
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(line);
+        cmd
+    }
+}
+
+/// `arg` as a single word for `sh`.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty() && arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+fn drain<R: Read + Send + 'static>(mut pipe: R) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = pipe.read_to_end(&mut buf);
+        String::from_utf8_lossy(&buf).into_owned()
+    })
+}
@@ -0,0 +1,357 @@
+// Retrieving, updating, exporting and linting the local crates.io index
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use crates_index::{Dependency, Index, Version};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use source::SOURCE;
+use PraziResult;
+
+pub static INDEX_URL: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// How the local index checkout is brought up to date.
+#[derive(Debug, Clone)]
+pub struct UpdateOptions {
+    /// Give up when the clone/update takes longer than this.
+    pub timeout: Option<Duration>,
+    /// Use a shallow `git` clone instead of a full clone through libgit2.
+    pub shallow: bool,
+    /// Use the checkout as it is, without any network access.
+    pub offline: bool,
+    /// Delete a checkout that is not a git repository or fails to update,
+    /// and clone the index again.
+    pub reclone: bool,
+}
+
+/// Whether `dir` is missing or empty, i.e. the index can be cloned into it.
+fn is_vacant(dir: &Path) -> bool {
+    fs::read_dir(dir).map_or(true, |mut entries| entries.next().is_none())
+}
+
+/// Whether `dir` looks like a git checkout.
+fn is_checkout(dir: &Path) -> bool {
+    dir.join(".git").join("HEAD").is_file()
+}
+
+/// Delete the checkout at `dir` for `--reclone`.
+fn remove_checkout(dir: &Path) -> Result<(), String> {
+    eprintln!("Deleting the index checkout at {} to clone it again", dir.display());
+    fs::remove_dir_all(dir).map_err(|e| format!("could not delete {}: {}", dir.display(), e))
+}
+
+/// Run `git args` in `dir`.
+pub fn git(args: &[&str], dir: &Path) -> Result<(), String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("failed to execute git: {}", e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ))
+    }
+}
+
+/// Clone or update the index from `url`. Only the crates.io index can be
+/// retrieved through `crates_index`, mirrors always use the `git` CLI.
+fn update(index_dir: &str, url: &str, shallow: bool) -> Result<(), String> {
+    let path = Path::new(index_dir);
+    if !shallow && url == INDEX_URL {
+        return Index::new(index_dir)
+            .retrieve_or_update()
+            .map_err(|e| format!("{}", e));
+    }
+    let depth: &[&str] = if shallow { &["--depth", "1"] } else { &[] };
+    if path.join(".git").exists() {
+        let mut args = vec!["fetch"];
+        args.extend(depth);
+        args.extend(&["origin", "master"]);
+        git(&args, path)?;
+        git(&["reset", "--hard", "FETCH_HEAD"], path)
+    } else {
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut args = vec!["clone"];
+        args.extend(depth);
+        args.extend(&[url, index_dir]);
+        git(&args, parent)
+    }
+}
+
+/// Clone or update the index at `index_dir`, respecting the timeout.
+pub fn retrieve_or_update(index_dir: &str, options: &UpdateOptions) -> PraziResult<()> {
+    let path = Path::new(index_dir);
+    if !is_vacant(path) && !is_checkout(path) {
+        if options.offline || !options.reclone {
+            return Err(format!(
+                "{} is not a git checkout of the index; point [crates] index_path elsewhere, \
+                 delete it or run with --reclone to have it deleted and cloned again",
+                index_dir
+            ).into());
+        }
+        remove_checkout(path)?;
+    }
+    if options.offline {
+        if !Path::new(index_dir).join("config.json").exists() {
+            return Err(format!(
+                "no index checkout at {} and not fetching it with --index-offline; \
+                 clone it with `git clone {} {}` where there is network and copy it here",
+                index_dir, SOURCE.index_url, index_dir
+            ).into());
+        }
+        return Ok(());
+    }
+    let (tx, rx) = mpsc::channel();
+    let dir = index_dir.to_string();
+    let shallow = options.shallow;
+    let url = SOURCE.index_url.clone();
+    let reclone = options.reclone;
+    let existed = is_checkout(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("could not create {}: {}", parent.display(), e))?;
+    }
+    thread::spawn(move || {
+        let mut res = update(&dir, &url, shallow);
+        if let Err(ref error) = res {
+            if reclone && existed {
+                // a corrupt checkout fails every update, a fresh clone does not
+                eprintln!("Error updating the index: {}", error);
+                res = remove_checkout(Path::new(&dir)).and_then(|()| update(&dir, &url, shallow));
+            }
+        }
+        let _ = tx.send(res);
+    });
+    let res = match options.timeout {
+        Some(timeout) => match rx.recv_timeout(timeout) {
+            Ok(res) => res,
+            Err(_) => {
+                return Err(format!(
+                    "updating the crates.io index at {} did not finish within {:?}; \
+                     clone it manually with `git clone --depth 1 {} {}` and run again",
+                    index_dir, timeout, SOURCE.index_url, index_dir
+                ).into())
+            }
+        },
+        None => rx.recv().map_err(|e| format!("{}", e))?,
+    };
+    res.map_err(|e| {
+        let hint = if existed && !reclone {
+            "; if the checkout is corrupt, run again with --reclone to clone it anew"
+        } else {
+            ""
+        };
+        format!("could not retrieve crates.io index: {}{}", e, hint).into()
+    })
+}
+
+/// The kinds of dependencies that count as edges of the dependency network,
+/// `[deps] kinds` or `--dep-kinds`. Dev-dependencies are only compiled for
+/// the tests of a crate, build dependencies for its build script, so by
+/// default only the normal ones count.
+#[derive(Debug, Clone, Copy)]
+pub struct DepKinds {
+    pub normal: bool,
+    pub build: bool,
+    pub dev: bool,
+}
+
+impl Default for DepKinds {
+    fn default() -> DepKinds {
+        DepKinds {
+            normal: true,
+            build: false,
+            dev: false,
+        }
+    }
+}
+
+impl DepKinds {
+    /// Does `dep` count? The index leaves the kind of normal dependencies out.
+    pub fn includes(self, dep: &Dependency) -> bool {
+        match dep.kind() {
+            Some("build") => self.build,
+            Some("dev") => self.dev,
+            _ => self.normal,
+        }
+    }
+}
+
+impl FromStr for DepKinds {
+    type Err = String;
+
+    /// A comma separated list like `normal,build`.
+    fn from_str(s: &str) -> Result<DepKinds, String> {
+        let mut kinds = DepKinds {
+            normal: false,
+            build: false,
+            dev: false,
+        };
+        for kind in s.split(',').map(|kind| kind.trim()) {
+            match kind {
+                "normal" => kinds.normal = true,
+                "build" => kinds.build = true,
+                "dev" => kinds.dev = true,
+                other => return Err(format!("unknown dependency kind: {}", other)),
+            }
+        }
+        Ok(kinds)
+    }
+}
+
+/// A dependency of an `export-index` record.
+#[derive(Debug, Serialize)]
+struct DependencyRecord<'a> {
+    name: &'a str,
+    req: &'a str,
+    features: &'a [String],
+    optional: bool,
+    default_features: bool,
+    target: Option<&'a str>,
+    kind: Option<&'a str>,
+}
+
+/// One line of `export-index`: the index metadata of a crate version.
+#[derive(Debug, Serialize)]
+struct VersionRecord<'a> {
+    name: &'a str,
+    version: &'a str,
+    deps: Vec<DependencyRecord<'a>>,
+    features: &'a HashMap<String, Vec<String>>,
+    yanked: bool,
+    cksum: &'a str,
+}
+
+impl<'a> VersionRecord<'a> {
+    fn new(version: &'a Version, kinds: DepKinds) -> VersionRecord<'a> {
+        VersionRecord {
+            name: version.name(),
+            version: version.version(),
+            deps: version
+                .dependencies()
+                .iter()
+                .filter(|dep| kinds.includes(dep))
+                .map(|dep| DependencyRecord {
+                    name: dep.name(),
+                    req: dep.requirement(),
+                    features: dep.features(),
+                    optional: dep.is_optional(),
+                    default_features: dep.has_default_features(),
+                    target: dep.target(),
+                    kind: dep.kind(),
+                }).collect(),
+            features: version.features(),
+            yanked: version.is_yanked(),
+            cksum: version.checksum(),
+        }
+    }
+}
+
+/// Write one JSON object per version of every crate of `index` to `w`, with
+/// the dependencies of `kinds`, reading the index one crate at a time.
+/// Returns the number of versions.
+pub fn export<W: Write>(index: &Index, kinds: DepKinds, mut w: W) -> io::Result<usize> {
+    let mut count = 0;
+    for krate in index.crates() {
+        for version in krate.versions() {
+            ::serde_json::to_writer(&mut w, &VersionRecord::new(version, kinds))?;
+            writeln!(w)?;
+            count += 1;
+        }
+    }
+    w.flush()?;
+    Ok(count)
+}
+
+/// A version of the index whose metadata is not valid semver, or odd.
+#[derive(Debug, Serialize)]
+pub struct LintIssue {
+    pub name: String,
+    pub version: String,
+    /// `unparseable-version`, `duplicate`, `out-of-order` or
+    /// `unparseable-requirement`.
+    pub kind: &'static str,
+    pub detail: String,
+}
+
+/// The result of `lint-index`.
+#[derive(Debug, Default, Serialize)]
+pub struct IndexLint {
+    pub crates: usize,
+    pub versions: usize,
+    pub issues: Vec<LintIssue>,
+}
+
+/// The series of `version` a later release should not be lower than: its
+/// major, or its minor below 1.0.
+fn series(version: &::semver::Version) -> (u64, u64) {
+    if version.major == 0 {
+        (0, version.minor)
+    } else {
+        (version.major, 0)
+    }
+}
+
+/// Check every version of `index` in publication order, the order of the
+/// index files: it must parse as semver, not equal an earlier version apart
+/// from build metadata, not be lower than an earlier version of its series
+/// (backports to older series are fine) and have dependency requirements
+/// that parse.
+pub fn lint(index: &Index) -> IndexLint {
+    let mut lint = IndexLint::default();
+    for krate in index.crates() {
+        lint.crates += 1;
+        let mut published: Vec<::semver::Version> = Vec::new();
+        let mut highest: BTreeMap<(u64, u64), ::semver::Version> = BTreeMap::new();
+        for version in krate.versions() {
+            lint.versions += 1;
+            let issue = |kind, detail: String| LintIssue {
+                name: krate.name().to_string(),
+                version: version.version().to_string(),
+                kind,
+                detail,
+            };
+            for dep in version.dependencies() {
+                if let Err(error) = ::semver::VersionReq::parse(dep.requirement()) {
+                    lint.issues.push(issue(
+                        "unparseable-requirement",
+                        format!("{} {}: {}", dep.name(), dep.requirement(), error),
+                    ));
+                }
+            }
+            let parsed = match ::semver::Version::parse(version.version()) {
+                Ok(parsed) => parsed,
+                Err(error) => {
+                    lint.issues.push(issue("unparseable-version", error.to_string()));
+                    continue;
+                }
+            };
+            // the comparison of semver versions ignores build metadata
+            if let Some(earlier) = published.iter().find(|earlier| **earlier == parsed) {
+                lint.issues.push(issue("duplicate", format!("same as {}", earlier)));
+            }
+            match highest.get(&series(&parsed)) {
+                Some(earlier) if parsed < *earlier => {
+                    lint.issues.push(issue("out-of-order", format!("published after {}", earlier)));
+                }
+                _ => {
+                    highest.insert(series(&parsed), parsed.clone());
+                }
+            }
+            published.push(parsed);
+        }
+    }
+    lint
+}
@@ -0,0 +1,59 @@
+// Source locations of the functions of a bitcode file, for `--with-debuginfo`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// `opt -dot-callgraph` labels the nodes with the function names only. Built
+// with `-C debuginfo=1`, every function defined in the bitcode refers to its
+// `DISubprogram` with `!dbg`, which holds the line and the `DIFile` of the
+// definition. They are read from the textual IR `llvm-dis` prints; functions
+// without debug info, e.g. the ones only declared, get no location.
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Where a function is defined.
+#[derive(Debug, Clone, Serialize)]
+pub struct Location {
+    pub file: String,
+    pub line: u64,
+}
+
+lazy_static! {
+    static ref DEFINE: Regex = Regex::new(r#"^define\b.*?@("[^"]*"|[-\w$.]+)\(.*!dbg !(\d+)\s*\{\s*$"#).unwrap();
+    static ref SUBPROGRAM: Regex = Regex::new(r"^!(\d+) = (?:distinct )?!DISubprogram\(").unwrap();
+    static ref FILE_REF: Regex = Regex::new(r"\bfile: !(\d+)").unwrap();
+    static ref LINE: Regex = Regex::new(r"\bline: (\d+)").unwrap();
+    static ref FILE: Regex = Regex::new(r#"^!(\d+) = !DIFile\(filename: "([^"]*)", directory: "([^"]*)""#).unwrap();
+}
+
+/// The locations of the functions defined in the textual IR `ir`, by their
+/// name as in the labels of the call graph.
+pub fn parse(ir: &str) -> BTreeMap<String, Location> {
+    let mut functions = Vec::new();
+    let mut subprograms = HashMap::new();
+    let mut files = HashMap::new();
+    for line in ir.lines() {
+        if let Some(caps) = DEFINE.captures(line) {
+            functions.push((caps[1].trim_matches('"').to_string(), caps[2].to_string()));
+        } else if let Some(caps) = SUBPROGRAM.captures(line) {
+            let file = FILE_REF.captures(line).map(|c| c[1].to_string());
+            let number = LINE.captures(line).and_then(|c| c[1].parse::<u64>().ok());
+            if let (Some(file), Some(number)) = (file, number) {
+                subprograms.insert(caps[1].to_string(), (file, number));
+            }
+        } else if let Some(caps) = FILE.captures(line) {
+            // relative file names are relative to the directory rustc ran in
+            let path = Path::new(&caps[3]).join(&caps[2]);
+            files.insert(caps[1].to_string(), path.to_string_lossy().into_owned());
+        }
+    }
+    functions
+        .into_iter()
+        .filter_map(|(name, dbg)| {
+            let &(ref file, line) = subprograms.get(&dbg)?;
+            let file = files.get(file)?.clone();
+            Some((name, Location { file, line }))
+        }).collect()
+}
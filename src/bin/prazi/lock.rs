@@ -0,0 +1,68 @@
+// One run at a time per `PRAZI_DIR`, with `.prazi.lock`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Two phases running against the same directory race on the folders of the
+// crates, e.g. two downloads extracting the same archive. Every subcommand
+// but the `READ_ONLY` ones therefore takes an exclusive `flock` of
+// `.prazi.lock` in `PRAZI_DIR` and holds it until the process exits, so a
+// lock left behind by a killed run does not block the next one. The file
+// holds the process id of the holder, for the message. Elsewhere than on Unix
+// nothing is locked.
+use std::path::Path;
+
+/// The subcommands that only read `PRAZI_DIR`, and run without the lock. They
+/// write to stdout, to the file they are given or to a report no other
+/// subcommand writes, and update the index at most, which git locks itself.
+pub const READ_ONLY: &[&str] = &[
+    "list-bitcode",
+    "list-no-bitcode",
+    "dump-graph",
+    "callgraph-diff",
+    "callers",
+    "merge-reports",
+    "export-index",
+    "list-versions",
+    "lint-index",
+    "analyze",
+    "unused-deps",
+];
+
+/// Take the lock of `dir` for the rest of the process, failing if another run
+/// holds it.
+#[cfg(unix)]
+pub fn acquire(dir: &Path) -> Result<(), String> {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::mem;
+    use std::os::unix::io::AsRawFd;
+
+    let path = dir.join(".prazi.lock");
+    let mut file = fs::create_dir_all(dir)
+        .and_then(|()| OpenOptions::new().read(true).write(true).create(true).open(&path))
+        .map_err(|error| format!("Error opening {:?}: {}", path, error))?;
+    if unsafe { ::libc::flock(file.as_raw_fd(), ::libc::LOCK_EX | ::libc::LOCK_NB) } != 0 {
+        let holder = fs::read_to_string(&path).unwrap_or_default();
+        return Err(format!(
+            "another run (pid {}) is using {}; wait for it to finish, or pass --no-lock if it works on other crates",
+            holder.trim(),
+            dir.display()
+        ));
+    }
+    let res = file
+        .set_len(0)
+        .and_then(|()| write!(file, "{}", ::std::process::id()));
+    if let Err(error) = res {
+        eprintln!("Error writing {:?}: {:?}", path, error);
+    }
+    // the lock goes with the file descriptor, which the process keeps open
+    mem::forget(file);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn acquire(_dir: &Path) -> Result<(), String> {
+    Ok(())
+}
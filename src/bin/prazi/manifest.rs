@@ -0,0 +1,312 @@
+// Reading information from the `Cargo.toml` of downloaded crates
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use checksum::Algo;
+use flate2::read::GzDecoder;
+use provenance::Provenance;
+use report::Envelope;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tar::Archive;
+use toml::Value;
+use {PraziCrate, PraziResult};
+
+/// The parsed `Cargo.toml` in `dir`.
+pub fn read(dir: &Path) -> PraziResult<Value> {
+    let text = fs::read_to_string(dir.join("Cargo.toml"))?;
+    Ok(text.parse::<Value>()?)
+}
+
+/// The crate of a `.crate` file, named by the `[package]` of the
+/// `Cargo.toml` at the top of the archive.
+pub fn crate_file_package(path: &Path) -> PraziResult<PraziCrate> {
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.components().count() != 2 || !entry.path()?.ends_with("Cargo.toml") {
+            continue;
+        }
+        let mut text = String::new();
+        entry.read_to_string(&mut text)?;
+        let manifest = text.parse::<Value>()?;
+        let field = |key: &str| {
+            manifest
+                .get("package")
+                .and_then(|p| p.get(key))
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+                .ok_or_else(|| format!("{:?}: Cargo.toml has no package.{}", path, key))
+        };
+        return Ok(PraziCrate {
+            name: field("name")?,
+            version: field("version")?,
+        });
+    }
+    Err(format!("{:?}: no Cargo.toml at the top of the archive", path).into())
+}
+
+/// The declared edition of a manifest, `2015` when unspecified as per cargo.
+pub fn edition(manifest: &Value) -> String {
+    manifest
+        .get("package")
+        .and_then(|p| p.get("edition"))
+        .and_then(|e| e.as_str())
+        .unwrap_or("2015")
+        .to_string()
+}
+
+/// Is the crate a procedural macro (`[lib] proc-macro = true`)? Such crates
+/// are compiled into a dylib loaded by the compiler, so their functions are
+/// called by rustc, not by the crates using them.
+pub fn is_proc_macro(manifest: &Value) -> bool {
+    manifest.get("lib").map_or(false, |lib| {
+        // `proc_macro` is an alias accepted by cargo
+        ["proc-macro", "proc_macro"]
+            .iter()
+            .any(|key| lib.get(key).and_then(|v| v.as_bool()) == Some(true))
+    })
+}
+
+/// The minimum supported Rust version a manifest declares in
+/// `package.rust-version`, if any.
+pub fn rust_version(manifest: &Value) -> Option<String> {
+    manifest
+        .get("package")
+        .and_then(|p| p.get("rust-version"))
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+/// The unstable cargo features a manifest enables with `cargo-features`.
+pub fn cargo_features(manifest: &Value) -> Vec<String> {
+    manifest
+        .get("cargo-features")
+        .and_then(|v| v.as_array())
+        .map_or(Vec::new(), |features| {
+            features.iter().filter_map(|f| f.as_str()).map(|f| f.to_string()).collect()
+        })
+}
+
+/// Does `cargo read-manifest` fail with `stderr` because the manifest uses
+/// something newer than the running cargo, e.g. a later edition, a `resolver`
+/// it does not know or a feature still behind `cargo-features`, rather than
+/// because the manifest is broken?
+pub fn is_too_new(stderr: &str) -> bool {
+    // e.g. "feature `edition2021` is required"
+    let feature_required = stderr.lines().any(|line| line.contains("feature `") && line.contains("` is required"));
+    feature_required || [
+        "consider adding `cargo-features",
+        "unknown cargo feature",
+        "requires a nightly version of Cargo",
+        "this version of Cargo is older than",
+        "supported edition values are",
+        "unknown variant `2",
+        "`resolver` setting",
+    ].iter()
+    .any(|pattern| stderr.contains(pattern))
+}
+
+/// Does the crate in `dir` with `manifest` have a build script? Cargo runs
+/// `package.build` or, when that is not set, a `build.rs` at the top of the
+/// crate; `build = false` disables it.
+pub fn has_build_script(dir: &Path, manifest: &Value) -> bool {
+    build_script(dir, manifest).is_some()
+}
+
+/// The build script of the crate in `dir` with `manifest`, relative to `dir`.
+pub fn build_script(dir: &Path, manifest: &Value) -> Option<PathBuf> {
+    let default = || Some(PathBuf::from("build.rs")).filter(|script| dir.join(script).exists());
+    match manifest.get("package").and_then(|p| p.get("build")) {
+        Some(&Value::Boolean(false)) => None,
+        Some(&Value::String(ref script)) => Some(PathBuf::from(script)),
+        _ => default(),
+    }
+}
+
+/// Whether the crate in `dir` is a procedural macro, `false` when its
+/// manifest cannot be read.
+pub fn dir_is_proc_macro(dir: &Path) -> bool {
+    read(dir).map(|m| is_proc_macro(&m)).unwrap_or(false)
+}
+
+/// The kinds of targets, `lib` and `bin`, of the crate in `dir` with
+/// `manifest`, declared or found where cargo looks for them.
+pub fn target_kinds(dir: &Path, manifest: &Value) -> Vec<&'static str> {
+    let mut kinds = Vec::new();
+    if manifest.get("lib").is_some() || dir.join("src").join("lib.rs").exists() {
+        kinds.push("lib");
+    }
+    let declared = manifest
+        .get("bin")
+        .and_then(|bins| bins.as_array())
+        .map_or(false, |bins| !bins.is_empty());
+    let autobins = manifest
+        .get("package")
+        .and_then(|p| p.get("autobins"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let found = dir.join("src").join("main.rs").exists() || dir.join("src").join("bin").is_dir();
+    if declared || (autobins && found) {
+        kinds.push("bin");
+    }
+    kinds
+}
+
+/// The entries of the dependency `tables` of the manifest, including the
+/// target specific ones.
+fn dependencies<'a>(manifest: &'a Value, tables: &[&str]) -> Vec<(&'a String, &'a Value)> {
+    let targets = manifest
+        .get("target")
+        .and_then(|targets| targets.as_table())
+        .into_iter()
+        .flat_map(|targets| targets.values());
+    let mut res = Vec::new();
+    for parent in Some(manifest).into_iter().chain(targets) {
+        for table in tables {
+            if let Some(deps) = parent.get(table).and_then(|deps| deps.as_table()) {
+                res.extend(deps.iter());
+            }
+        }
+    }
+    res
+}
+
+/// Names of the `path` dependencies the manifest declares, in any dependency
+/// table including the target specific ones.
+pub fn path_dependencies(manifest: &Value) -> BTreeSet<String> {
+    dependencies(manifest, &["dependencies", "dev-dependencies", "build-dependencies"])
+        .into_iter()
+        .filter(|&(_, dep)| dep.get("path").is_some())
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Names of the packages of the normal and build dependencies the manifest
+/// declares, i.e. the ones a build compiles first; renamed dependencies by
+/// their `package`.
+pub fn build_dependencies(manifest: &Value) -> BTreeSet<String> {
+    dependencies(manifest, &["dependencies", "build-dependencies"])
+        .into_iter()
+        .map(|(name, dep)| {
+            dep.get("package")
+                .and_then(|package| package.as_str())
+                .map_or(name.clone(), |package| package.to_string())
+        }).collect()
+}
+
+/// The features a manifest declares, for `export-features`.
+#[derive(Debug, Default, Serialize)]
+pub struct Features {
+    /// What every feature of `[features]` enables: features, optional
+    /// dependencies (`dep:name` or just `name`) and features of dependencies
+    /// (`name/feature`, `name?/feature`).
+    pub features: BTreeMap<String, Vec<String>>,
+    /// What `default` enables, empty if the manifest has no `default`.
+    pub default: Vec<String>,
+    /// Optional normal and build dependencies, by the name the manifest uses.
+    pub optional_dependencies: BTreeSet<String>,
+    /// Features cargo adds for optional dependencies, those of the same name
+    /// unless `[features]` refers to the dependency as `dep:name`.
+    pub implicit: BTreeSet<String>,
+}
+
+/// The features of a manifest, including the implicit ones.
+pub fn features(manifest: &Value) -> Features {
+    let features = manifest
+        .get("features")
+        .and_then(|features| features.as_table())
+        .map_or(BTreeMap::new(), |features| {
+            features
+                .iter()
+                .map(|(name, enables)| {
+                    let enables = enables.as_array().map_or(Vec::new(), |enables| {
+                        enables.iter().filter_map(|v| v.as_str()).map(|v| v.to_string()).collect()
+                    });
+                    (name.clone(), enables)
+                }).collect::<BTreeMap<_, _>>()
+        });
+    let optional_dependencies = dependencies(manifest, &["dependencies", "build-dependencies"])
+        .into_iter()
+        .filter(|&(_, dep)| dep.get("optional").and_then(|v| v.as_bool()) == Some(true))
+        .map(|(name, _)| name.clone())
+        .collect::<BTreeSet<_>>();
+    let explicit = features
+        .values()
+        .flat_map(|enables| enables.iter())
+        .filter(|enabled| enabled.starts_with("dep:"))
+        .map(|enabled| enabled["dep:".len()..].to_string())
+        .collect::<BTreeSet<_>>();
+    let implicit = optional_dependencies
+        .iter()
+        .filter(|name| !explicit.contains(*name) && !features.contains_key(*name))
+        .cloned()
+        .collect();
+    Features {
+        default: features.get("default").cloned().unwrap_or_default(),
+        features,
+        optional_dependencies,
+        implicit,
+    }
+}
+
+/// Where `rewrite` keeps the diff of the `Cargo.toml` it rewrote.
+pub fn rewrite_diff_file(dir: &Path) -> PathBuf {
+    dir.join("Cargo.toml.rewrite.diff")
+}
+
+/// Unified diff of the `Cargo.toml` of a crate before and after `rewrite`.
+pub fn rewrite_diff(original: &str, rewritten: &str) -> String {
+    ::similar::TextDiff::from_lines(original, rewritten)
+        .unified_diff()
+        .header("a/Cargo.toml", "b/Cargo.toml")
+        .to_string()
+}
+
+/// Output of `cargo read-manifest` cached in `{dir}/prazi-manifest.json`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CachedManifest {
+    /// SHA-256 of the `.crate` file from the provenance, if recorded.
+    pub source_checksum: Option<String>,
+    /// SHA-256 of `Cargo.toml`, which changes when the manifest is rewritten.
+    pub manifest_checksum: String,
+    pub manifest: ::serde_json::Value,
+}
+
+pub fn cache_file(krate: &PraziCrate) -> PathBuf {
+    krate.dir().join("prazi-manifest.json")
+}
+
+fn checksums(krate: &PraziCrate) -> PraziResult<(Option<String>, String)> {
+    let source = Provenance::read(krate).and_then(|p| p.checksums.get("sha256").cloned());
+    let manifest = Algo::Sha256.digest(&fs::read(krate.dir().join("Cargo.toml"))?);
+    Ok((source, manifest))
+}
+
+/// The cached `read-manifest` output of `krate`, unless the sources changed
+/// since it was stored.
+pub fn cached(krate: &PraziCrate) -> Option<::serde_json::Value> {
+    let text = fs::read_to_string(cache_file(krate)).ok()?;
+    let cache: Envelope<CachedManifest> = ::serde_json::from_str(&text).ok()?;
+    let (source, manifest) = checksums(krate).ok()?;
+    if cache.data.source_checksum == source && cache.data.manifest_checksum == manifest {
+        Some(cache.data.manifest)
+    } else {
+        None
+    }
+}
+
+/// Cache the `read-manifest` output `json` of `krate`.
+pub fn store(krate: &PraziCrate, json: &[u8]) -> PraziResult<()> {
+    let (source_checksum, manifest_checksum) = checksums(krate)?;
+    let cache = CachedManifest {
+        source_checksum,
+        manifest_checksum,
+        manifest: ::serde_json::from_slice(json)?,
+    };
+    fs::write(cache_file(krate), Envelope::new("manifest", cache).to_json())?;
+    Ok(())
+}
@@ -0,0 +1,782 @@
+// Streaming merge of per-crate call graphs into one call-based dependency network
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Peak memory of the merge is bounded independently of the corpus size: every
+// crate's graph is read on its own, its nodes and edges go to an external sort
+// that spills sorted, deduplicated runs of at most `chunk_lines` lines to disk,
+// and the output is produced by a k-way merge over those runs. Memory is thus
+// O(chunk_lines + largest crate graph), at the cost of writing every node and
+// edge to disk once more and reading it back during the final merge.
+use graph::{self, CallGraph};
+use provenance::Provenance;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use {stdio, swhid, PraziCrate};
+
+/// Sorts and deduplicates an unbounded number of lines using sorted runs on disk.
+pub struct ExternalSorter {
+    chunk_lines: usize,
+    dir: PathBuf,
+    buffer: Vec<String>,
+    runs: Vec<PathBuf>,
+}
+
+impl ExternalSorter {
+    pub fn new(dir: &Path, chunk_lines: usize) -> io::Result<ExternalSorter> {
+        fs::create_dir_all(dir)?;
+        Ok(ExternalSorter {
+            chunk_lines,
+            dir: dir.to_path_buf(),
+            buffer: Vec::new(),
+            runs: Vec::new(),
+        })
+    }
+
+    pub fn push(&mut self, line: String) -> io::Result<()> {
+        self.buffer.push(line);
+        if self.buffer.len() >= self.chunk_lines {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        self.buffer.sort();
+        self.buffer.dedup();
+        let path = self.dir.join(format!("run-{}", self.runs.len()));
+        let mut out = BufWriter::new(File::create(&path)?);
+        for line in self.buffer.drain(..) {
+            writeln!(out, "{}", line)?;
+        }
+        out.flush()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Sorted, deduplicated lines of all runs.
+    pub fn finish(mut self) -> io::Result<Merged> {
+        if !self.buffer.is_empty() {
+            self.spill()?;
+        }
+        let mut readers = Vec::new();
+        let mut heap = BinaryHeap::new();
+        for (i, run) in self.runs.iter().enumerate() {
+            let mut lines = BufReader::new(File::open(run)?).lines();
+            if let Some(line) = lines.next() {
+                heap.push(Reverse((line?, i)));
+            }
+            readers.push(lines);
+        }
+        Ok(Merged {
+            readers,
+            heap,
+            last: None,
+            runs: self.runs,
+        })
+    }
+}
+
+/// Iterator over the k-way merge of the sorted runs of an `ExternalSorter`.
+pub struct Merged {
+    readers: Vec<Lines<BufReader<File>>>,
+    heap: BinaryHeap<Reverse<(String, usize)>>,
+    last: Option<String>,
+    runs: Vec<PathBuf>,
+}
+
+impl Iterator for Merged {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<io::Result<String>> {
+        while let Some(Reverse((line, i))) = self.heap.pop() {
+            match self.readers[i].next() {
+                Some(Ok(next)) => self.heap.push(Reverse((next, i))),
+                Some(Err(error)) => return Some(Err(error)),
+                None => {}
+            }
+            if self.last.as_ref() != Some(&line) {
+                self.last = Some(line.clone());
+                return Some(Ok(line));
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Merged {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = fs::remove_file(run);
+        }
+    }
+}
+
+/// Counts of a finished merge.
+#[derive(Debug, Default, Serialize)]
+pub struct MergeStats {
+    pub crates: usize,
+    pub missing: usize,
+    pub nodes: usize,
+    /// Nodes of functions defined outside of the crates of the merge.
+    pub unresolved: usize,
+    pub edges: usize,
+}
+
+/// The unified call graph written by `ufi` for a crate.
+pub fn ufi_graph(krate: &PraziCrate) -> PathBuf {
+    krate.dir().join("callgraph.ufi.graph")
+}
+
+fn quote(label: &str) -> String {
+    format!("\"{}\"", label.trim())
+}
+
+/// How the crates of the merge are identified in the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeId {
+    /// `io :: crates :: name :: v_version` in labels, `name@version` in edge
+    /// attributes.
+    NameVersion,
+    /// The Software Heritage identifier of the crate's source tree.
+    ContentHash,
+}
+
+impl FromStr for NodeId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<NodeId, String> {
+        match s {
+            "name-version" => Ok(NodeId::NameVersion),
+            "content-hash" => Ok(NodeId::ContentHash),
+            _ => Err(format!("unknown node id: {}", s)),
+        }
+    }
+}
+
+/// The identifier of `krate` in the merged graph.
+fn crate_id(krate: &PraziCrate, node_id: NodeId) -> String {
+    if node_id == NodeId::NameVersion {
+        return format!("{}@{}", krate.name, krate.version);
+    }
+    match Provenance::read(krate).and_then(|p| p.swhid) {
+        Some(id) => id,
+        // downloaded before identifiers were recorded
+        None => swhid::source_tree(&krate.dir(), true).unwrap_or_else(|error| {
+            eprintln!("Error hashing sources: {:?} {:?}", krate, error);
+            format!("{}@{}", krate.name, krate.version)
+        }),
+    }
+}
+
+/// Attributes a unique function identifier to its crate.
+struct Attribution {
+    /// `ufi` identifiers of name and version to the id of the crate.
+    crates: HashMap<(String, String), String>,
+    /// Replace the crate segments of labels by the crate ids.
+    relabel: bool,
+}
+
+impl Attribution {
+    fn new(crates: &[PraziCrate], node_id: NodeId) -> Attribution {
+        Attribution {
+            crates: crates
+                .iter()
+                .map(|krate| {
+                    let key = (graph::ufi_ident(&krate.name), graph::ufi_ident(&krate.version));
+                    (key, crate_id(krate, node_id))
+                }).collect(),
+            relabel: node_id == NodeId::ContentHash,
+        }
+    }
+
+    /// `label` with the crates of the merge identified as requested.
+    fn label(&self, label: &str) -> String {
+        if !self.relabel {
+            return label.to_string();
+        }
+        graph::map_ufi_crates(label, |name, version| {
+            self.crates
+                .get(&(name.to_string(), version.to_string()))
+                .cloned()
+        })
+    }
+
+    /// Whether `label` is a function of a crate of the merge. The others, of
+    /// crates outside of the merge or not attributable at all, are the
+    /// unresolved boundary of the graph.
+    fn resolves(&self, label: &str) -> bool {
+        graph::ufi_crate(label).map_or(false, |(name, version)| {
+            self.crates.contains_key(&(name.to_string(), version.to_string()))
+        })
+    }
+
+    /// Crates outside of the merged set keep their identifiers, e.g. `libc@0_2_43`.
+    fn crate_of(&self, label: &str) -> Option<String> {
+        graph::ufi_crate(label).map(|(name, version)| {
+            self.crates
+                .get(&(name.to_string(), version.to_string()))
+                .cloned()
+                .unwrap_or_else(|| format!("{}@{}", name, version))
+        })
+    }
+}
+
+/// Layout of the merged graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// GraphViz DOT text, as written by the graph generator script.
+    Dot,
+    /// The compact node dictionary plus varint edge list of `graph::BINARY_MAGIC`.
+    Binary,
+    /// GraphML XML, with the node and edge attributes of the DOT output as
+    /// data.
+    GraphMl,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "dot" => Ok(Format::Dot),
+            "binary" => Ok(Format::Binary),
+            "graphml" => Ok(Format::GraphMl),
+            _ => Err(format!("unknown graph format: {}", s)),
+        }
+    }
+}
+
+/// How `merge` runs and what it writes.
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Lines sorted in memory before a run is spilled to disk.
+    pub chunk_lines: usize,
+    /// Only keep the edges between functions of different crates.
+    pub boundary_only: bool,
+    pub format: Format,
+    pub node_id: NodeId,
+    /// Split the output into this many graphs by crate name, see
+    /// `partition_file`.
+    pub partitions: Option<usize>,
+}
+
+/// The file of partition `i` of the merged graph `out`, `{out}.{i}`.
+pub fn partition_file(out: &Path, i: usize) -> PathBuf {
+    let mut name = out.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", i));
+    out.with_file_name(name)
+}
+
+/// 64-bit FNV-1a, stable across runs and platforms unlike the std hashers.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The partition of the node `label`: by the name of its crate, so all
+/// versions of a crate share a partition, or by the label itself for the
+/// functions that cannot be attributed to a crate.
+fn partition_of(label: &str, partitions: usize) -> usize {
+    let key = graph::ufi_crate(label).map_or(label.trim(), |(name, _)| name);
+    (fnv1a(key.as_bytes()) % partitions as u64) as usize
+}
+
+/// The attribute list of the nodes of functions outside of the merge.
+const UNRESOLVED: &str = "[unresolved=true]";
+
+/// `line` with the DOT `attributes`, if any.
+fn with_attributes(line: String, attributes: &[String]) -> String {
+    if attributes.is_empty() {
+        line
+    } else {
+        format!("{} [{}]", line, attributes.join(", "))
+    }
+}
+
+/// Merge the unified call graphs of `crates` into `out`.
+///
+/// Nodes with the same unique function identifier are unified, which connects
+/// the call graphs of crates to those of their dependencies. Scratch files are
+/// written below `tmp_dir`.
+///
+/// With `boundary_only`, only edges whose caller and callee belong to
+/// different crates are written, annotated with both crates; edges with an
+/// endpoint that cannot be attributed to a crate are dropped. It is not
+/// supported by the binary format, which has no edge attributes.
+///
+/// The nodes of functions outside of the crates of the merge, e.g. of the
+/// standard library or of dependencies left out of the merge, are the
+/// unresolved boundary of the network and marked `unresolved=true`.
+///
+/// With `partitions`, the nodes are written to the partition of their crate
+/// and every edge to the partition of its caller. An edge between partitions
+/// is written to both, marked `boundary="outgoing"` in the caller's and
+/// `boundary="incoming"` in the callee's, with the `partition` of the other
+/// side, and its endpoint of the other partition is listed in each as a node
+/// with a `partition` attribute. Every partition is thus a graph of its own;
+/// leaving out the incoming edges and the nodes with attributes, the
+/// partitions together are the merged graph.
+pub fn merge(
+    crates: &[PraziCrate],
+    out: &Path,
+    tmp_dir: &Path,
+    options: Options,
+) -> io::Result<MergeStats> {
+    let Options {
+        chunk_lines,
+        boundary_only,
+        format,
+        node_id,
+        partitions,
+    } = options;
+    let binary = format == Format::Binary;
+    let graphml = format == Format::GraphMl;
+    if binary && boundary_only {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--boundary-only is not supported by the binary format",
+        ));
+    }
+    if binary && partitions.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--partitions is not supported by the binary format",
+        ));
+    }
+    if graphml && partitions.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--partitions is not supported by the GraphML format",
+        ));
+    }
+    if stdio::is_std(out) && (binary || partitions.is_some()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--out - is only supported for a single DOT or GraphML graph",
+        ));
+    }
+    if partitions == Some(0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "--partitions must be at least 1"));
+    }
+    let attribution = Attribution::new(crates, node_id);
+    let mut stats = MergeStats::default();
+    let mut nodes = ExternalSorter::new(&tmp_dir.join("nodes"), chunk_lines)?;
+    let mut edges = ExternalSorter::new(&tmp_dir.join("edges"), chunk_lines)?;
+    for krate in crates {
+        let graph = match CallGraph::load(&ufi_graph(krate)) {
+            Ok(graph) => graph,
+            Err(_) => {
+                stats.missing += 1;
+                continue;
+            }
+        };
+        stats.crates += 1;
+        if !boundary_only {
+            for original in graph.nodes.values() {
+                let label = attribution.label(original);
+                let resolved = attribution.resolves(original);
+                let attributes = if resolved { vec![] } else { vec!["unresolved=true".to_string()] };
+                nodes.push(match partitions {
+                    _ if binary => label.trim().to_string(),
+                    _ if graphml => format!("{}\t{}", label.trim(), !resolved),
+                    Some(n) => format!(
+                        "{}\t{}",
+                        partition_of(original, n),
+                        with_attributes(quote(&label), &attributes)
+                    ),
+                    None => with_attributes(quote(&label), &attributes),
+                })?;
+            }
+        }
+        for &(ref from, ref to) in &graph.edges {
+            let (from, to) = match (graph.nodes.get(from), graph.nodes.get(to)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+            // attributed by the original labels, which name the crates
+            let (label_from, label_to) = (attribution.label(from), attribution.label(to));
+            if binary {
+                edges.push(format!("{}\t{}", label_from.trim(), label_to.trim()))?;
+                continue;
+            }
+            let mut attributes = Vec::new();
+            let mut crates = (String::new(), String::new());
+            if boundary_only {
+                match (attribution.crate_of(from), attribution.crate_of(to)) {
+                    (Some(ref caller), Some(ref callee)) if caller != callee => {
+                        attributes.push(format!("caller={}", quote(caller)));
+                        attributes.push(format!("callee={}", quote(callee)));
+                        crates = (caller.clone(), callee.clone());
+                    }
+                    _ => continue,
+                }
+            }
+            if graphml {
+                let (label_from, label_to) = (label_from.trim(), label_to.trim());
+                edges.push(format!("{}\t{}\t{}\t{}", label_from, label_to, crates.0, crates.1))?;
+                if boundary_only {
+                    // GraphML declares the endpoints of every edge as nodes
+                    nodes.push(format!("{}\t{}", label_from, !attribution.resolves(from)))?;
+                    nodes.push(format!("{}\t{}", label_to, !attribution.resolves(to)))?;
+                }
+                continue;
+            }
+            let line = format!("{} -> {}", quote(&label_from), quote(&label_to));
+            let n = match partitions {
+                Some(n) => n,
+                None => {
+                    edges.push(with_attributes(line, &attributes))?;
+                    continue;
+                }
+            };
+            let (caller, callee) = (partition_of(from, n), partition_of(to, n));
+            if caller == callee {
+                edges.push(format!("{}\t{}", caller, with_attributes(line, &attributes)))?;
+                continue;
+            }
+            let mut outgoing = attributes.clone();
+            outgoing.push("boundary=\"outgoing\"".to_string());
+            outgoing.push(format!("partition={}", callee));
+            edges.push(format!("{}\t{}", caller, with_attributes(line.clone(), &outgoing)))?;
+            let mut incoming = attributes;
+            incoming.push("boundary=\"incoming\"".to_string());
+            incoming.push(format!("partition={}", caller));
+            edges.push(format!("{}\t{}", callee, with_attributes(line, &incoming)))?;
+            if !boundary_only {
+                nodes.push(format!("{}\t{} [partition={}]", caller, quote(&label_to), callee))?;
+                nodes.push(format!("{}\t{} [partition={}]", callee, quote(&label_from), caller))?;
+            }
+        }
+    }
+
+    if let Some(parent) = out.parent().filter(|_| !stdio::is_std(out)) {
+        fs::create_dir_all(parent)?;
+    }
+    if binary {
+        return write_binary(nodes, edges, out, tmp_dir, chunk_lines, stats);
+    }
+    if graphml {
+        return write_graphml(nodes, edges, out, stats);
+    }
+    if let Some(n) = partitions {
+        return write_partitions(nodes, edges, out, n, stats);
+    }
+    let mut writer = stdio::create(out)?;
+    writeln!(writer, "digraph \"Call graph\" {{")?;
+    for node in nodes.finish()? {
+        let node = node?;
+        writeln!(writer, "\t{};", node)?;
+        stats.nodes += 1;
+        if node.ends_with(UNRESOLVED) {
+            stats.unresolved += 1;
+        }
+    }
+    for edge in edges.finish()? {
+        writeln!(writer, "\t{};", edge?)?;
+        stats.edges += 1;
+    }
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Escape `text` for XML attributes and character data.
+fn xml_escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Write the sorted `label\tunresolved` nodes and `caller\tcallee\tcaller
+/// crate\tcallee crate` edges of a GraphML merge, the node ids being the
+/// labels.
+fn write_graphml(
+    nodes: ExternalSorter,
+    edges: ExternalSorter,
+    out: &Path,
+    mut stats: MergeStats,
+) -> io::Result<MergeStats> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid GraphML line: {}", line));
+    let mut writer = stdio::create(out)?;
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">")?;
+    writeln!(writer, "  <key id=\"unresolved\" for=\"node\" attr.name=\"unresolved\" attr.type=\"boolean\"/>")?;
+    writeln!(writer, "  <key id=\"caller\" for=\"edge\" attr.name=\"caller\" attr.type=\"string\"/>")?;
+    writeln!(writer, "  <key id=\"callee\" for=\"edge\" attr.name=\"callee\" attr.type=\"string\"/>")?;
+    writeln!(writer, "  <graph id=\"Call graph\" edgedefault=\"directed\">")?;
+    for node in nodes.finish()? {
+        let node = node?;
+        let mut parts = node.splitn(2, '\t');
+        let (label, unresolved) = match (parts.next(), parts.next()) {
+            (Some(label), Some(unresolved)) => (label, unresolved == "true"),
+            _ => return Err(invalid(&node)),
+        };
+        if unresolved {
+            writeln!(
+                writer,
+                "    <node id=\"{}\"><data key=\"unresolved\">true</data></node>",
+                xml_escape(label)
+            )?;
+            stats.unresolved += 1;
+        } else {
+            writeln!(writer, "    <node id=\"{}\"/>", xml_escape(label))?;
+        }
+        stats.nodes += 1;
+    }
+    for edge in edges.finish()? {
+        let edge = edge?;
+        let parts = edge.split('\t').collect::<Vec<_>>();
+        if parts.len() != 4 {
+            return Err(invalid(&edge));
+        }
+        let (source, target) = (xml_escape(parts[0]), xml_escape(parts[1]));
+        if parts[2].is_empty() {
+            writeln!(writer, "    <edge source=\"{}\" target=\"{}\"/>", source, target)?;
+        } else {
+            writeln!(
+                writer,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"caller\">{}</data><data key=\"callee\">{}</data></edge>",
+                source,
+                target,
+                xml_escape(parts[2]),
+                xml_escape(parts[3])
+            )?;
+        }
+        stats.edges += 1;
+    }
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")?;
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Split a line of a partitioned merge into its partition and DOT statement.
+fn partitioned(line: &str) -> io::Result<(usize, &str)> {
+    let mut parts = line.splitn(2, '\t');
+    match (parts.next().and_then(|p| p.parse().ok()), parts.next()) {
+        (Some(partition), Some(statement)) => Ok((partition, statement)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unpartitioned line: {}", line))),
+    }
+}
+
+/// Write the nodes and edges of a partitioned merge to the `partitions`
+/// files of `out`, counting each node and edge of the merged graph once.
+fn write_partitions(
+    nodes: ExternalSorter,
+    edges: ExternalSorter,
+    out: &Path,
+    partitions: usize,
+    mut stats: MergeStats,
+) -> io::Result<MergeStats> {
+    let mut writers = (0..partitions)
+        .map(|i| File::create(partition_file(out, i)).map(BufWriter::new))
+        .collect::<io::Result<Vec<_>>>()?;
+    for writer in &mut writers {
+        writeln!(writer, "digraph \"Call graph\" {{")?;
+    }
+    for node in nodes.finish()? {
+        let node = node?;
+        let (partition, statement) = partitioned(&node)?;
+        writeln!(writers[partition], "\t{};", statement)?;
+        if !statement.contains(" [partition=") {
+            stats.nodes += 1;
+            if statement.ends_with(UNRESOLVED) {
+                stats.unresolved += 1;
+            }
+        }
+    }
+    for edge in edges.finish()? {
+        let edge = edge?;
+        let (partition, statement) = partitioned(&edge)?;
+        writeln!(writers[partition], "\t{};", statement)?;
+        if !statement.contains("boundary=\"incoming\"") {
+            stats.edges += 1;
+        }
+    }
+    for writer in &mut writers {
+        writeln!(writer, "}}")?;
+        writer.flush()?;
+    }
+    Ok(stats)
+}
+
+/// Sorted node labels, read again for every join with the edges.
+struct NodeFile {
+    path: PathBuf,
+}
+
+impl NodeFile {
+    /// Labels with their index in the dictionary.
+    fn indexed(&self) -> io::Result<impl Iterator<Item = io::Result<(u64, String)>>> {
+        let lines = BufReader::new(File::open(&self.path)?).lines();
+        Ok(lines.enumerate().map(|(i, line)| line.map(|line| (i as u64, line))))
+    }
+}
+
+impl Drop for NodeFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Look up the label before the tab of every line of `sorted`, which must be
+/// ordered like the node labels, and pass the rest of the line with the
+/// label's dictionary index to `emit`.
+fn join<I, F>(sorted: I, nodes: &NodeFile, mut emit: F) -> io::Result<()>
+where
+    I: Iterator<Item = io::Result<String>>,
+    F: FnMut(&str, u64) -> io::Result<()>,
+{
+    let mut indexed = nodes.indexed()?;
+    let mut current: Option<(u64, String)> = None;
+    for line in sorted {
+        let line = line?;
+        let mut parts = line.splitn(2, '\t');
+        let (label, rest) = (parts.next().unwrap(), parts.next().unwrap_or(""));
+        while current.as_ref().map_or(true, |&(_, ref node)| node.as_str() < label) {
+            current = match indexed.next() {
+                Some(node) => Some(node?),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("edge endpoint without node: {}", label),
+                    ))
+                }
+            };
+        }
+        emit(rest, current.as_ref().unwrap().0)?;
+    }
+    Ok(())
+}
+
+/// Write the dictionary and edge list of the binary format of
+/// `graph::BINARY_MAGIC` with bounded memory: the edges, sorted by caller, are joined with the sorted labels to
+/// replace the caller by its index, sorted again by callee and joined a second
+/// time to replace the callee.
+fn write_binary(
+    nodes: ExternalSorter,
+    edges: ExternalSorter,
+    out: &Path,
+    tmp_dir: &Path,
+    chunk_lines: usize,
+    mut stats: MergeStats,
+) -> io::Result<MergeStats> {
+    let nodes_file = NodeFile {
+        path: tmp_dir.join("nodes.sorted"),
+    };
+    {
+        let mut writer = BufWriter::new(File::create(&nodes_file.path)?);
+        for node in nodes.finish()? {
+            writeln!(writer, "{}", node?)?;
+            stats.nodes += 1;
+        }
+        writer.flush()?;
+    }
+
+    let mut by_callee = ExternalSorter::new(&tmp_dir.join("edges-by-callee"), chunk_lines)?;
+    join(edges.finish()?, &nodes_file, |callee, caller| {
+        stats.edges += 1;
+        by_callee.push(format!("{}\t{}", callee, caller))
+    })?;
+
+    let mut writer = BufWriter::new(File::create(out)?);
+    graph::write_binary_header(&mut writer, stats.nodes as u64)?;
+    for node in nodes_file.indexed()? {
+        graph::write_string(&mut writer, &node?.1)?;
+    }
+    graph::write_varint(&mut writer, stats.edges as u64)?;
+    join(by_callee.finish()?, &nodes_file, |caller, callee| {
+        let caller = caller
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid caller index"))?;
+        graph::write_varint(&mut writer, caller)?;
+        graph::write_varint(&mut writer, callee)
+    })?;
+    writer.flush()?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graph::BinaryGraph;
+    use test_dir;
+
+    /// Store the `ufi` graph of `krate`, in the layout of `opt
+    /// -dot-callgraph`, with `edges` between the positions of `labels`.
+    fn write_ufi_graph(krate: &PraziCrate, labels: &[&str], edges: &[(usize, usize)]) {
+        let mut text = "digraph \"Call graph\" {\n".to_string();
+        for (i, label) in labels.iter().enumerate() {
+            text.push_str(&format!("\tNode{} [shape=record,label=\"{{{}}}\"];\n", i, label));
+        }
+        for &(from, to) in edges {
+            text.push_str(&format!("\tNode{} -> Node{};\n", from, to));
+        }
+        text.push_str("}\n");
+        fs::create_dir_all(krate.dir()).unwrap();
+        fs::write(ufi_graph(krate), text).unwrap();
+    }
+
+    fn krate(name: &str, version: &str) -> PraziCrate {
+        PraziCrate {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn binary_merge_reads_back() {
+        let main = "io :: crates :: merge_a :: v_1_0_0 :: main";
+        let helper = "io :: crates :: merge_a :: v_1_0_0 :: helper";
+        let parse = "io :: crates :: merge_b :: v_2_0_0 :: parse";
+        let lex = "io :: crates :: merge_b :: v_2_0_0 :: lex";
+        let write = "std :: fmt :: write";
+        let crates = vec![krate("merge-a", "1.0.0"), krate("merge-b", "2.0.0"), krate("merge-c", "0.1.0")];
+        // the edge to `parse` of the dependency unifies the two graphs, the
+        // repeated call is written once
+        write_ufi_graph(&crates[0], &[main, helper, parse], &[(0, 1), (0, 2), (1, 2), (0, 1)]);
+        write_ufi_graph(&crates[1], &[parse, lex, write], &[(0, 1), (1, 2)]);
+
+        let dir = test_dir("merge-binary");
+        let out = dir.join("merged.bin");
+        let options = Options {
+            // spill every other line, so the joins read several runs
+            chunk_lines: 2,
+            boundary_only: false,
+            format: Format::Binary,
+            node_id: NodeId::NameVersion,
+            partitions: None,
+        };
+        let stats = merge(&crates, &out, &dir.join("tmp"), options).unwrap();
+        assert_eq!((stats.crates, stats.missing, stats.nodes, stats.edges), (2, 1, 5, 5));
+
+        let merged = BinaryGraph::load(&out).unwrap();
+        assert_eq!(merged.nodes, vec![helper, main, lex, parse, write]);
+        let mut edges = merged
+            .edges
+            .iter()
+            .map(|&(from, to)| (merged.nodes[from as usize].as_str(), merged.nodes[to as usize].as_str()))
+            .collect::<Vec<_>>();
+        edges.sort();
+        let mut expected = vec![(main, helper), (main, parse), (helper, parse), (parse, lex), (lex, write)];
+        expected.sort();
+        assert_eq!(edges, expected);
+    }
+}
@@ -0,0 +1,133 @@
+// Prometheus-style counters for monitoring long runs
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use children;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use tiny_http::{Header, Response, Server};
+
+pub struct Metrics {
+    pub downloads_completed: AtomicUsize,
+    pub downloads_failed: AtomicUsize,
+    pub bytes_downloaded: AtomicUsize,
+    pub builds_succeeded: AtomicUsize,
+    pub builds_failed: AtomicUsize,
+    pub concurrency: AtomicUsize,
+}
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics {
+        downloads_completed: AtomicUsize::new(0),
+        downloads_failed: AtomicUsize::new(0),
+        bytes_downloaded: AtomicUsize::new(0),
+        builds_succeeded: AtomicUsize::new(0),
+        builds_failed: AtomicUsize::new(0),
+        concurrency: AtomicUsize::new(0),
+    };
+}
+
+/// Increment `counter` by one.
+pub fn inc(counter: &AtomicUsize) {
+    counter.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Increment `counter` by `n`.
+pub fn add(counter: &AtomicUsize, n: usize) {
+    counter.fetch_add(n, Ordering::SeqCst);
+}
+
+/// Marks a unit of work as in flight for as long as it is alive.
+pub struct InFlight;
+
+impl InFlight {
+    pub fn new() -> InFlight {
+        inc(&METRICS.concurrency);
+        InFlight
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        METRICS.concurrency.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Metrics {
+    /// Render the counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "prazi_downloads_completed_total",
+                "counter",
+                "Crates downloaded and extracted",
+                &self.downloads_completed,
+            ),
+            (
+                "prazi_downloads_failed_total",
+                "counter",
+                "Crates that failed to download or extract",
+                &self.downloads_failed,
+            ),
+            (
+                "prazi_bytes_downloaded_total",
+                "counter",
+                "Compressed bytes downloaded",
+                &self.bytes_downloaded,
+            ),
+            (
+                "prazi_builds_succeeded_total",
+                "counter",
+                "Crates built successfully",
+                &self.builds_succeeded,
+            ),
+            (
+                "prazi_builds_failed_total",
+                "counter",
+                "Crates that failed to build",
+                &self.builds_failed,
+            ),
+            (
+                "prazi_concurrency",
+                "gauge",
+                "Units of work currently in flight",
+                &self.concurrency,
+            ),
+        ];
+        for &(name, kind, help, value) in counters.iter() {
+            out.push_str(&format!("# HELP {} {}\n", name, help));
+            out.push_str(&format!("# TYPE {} {}\n", name, kind));
+            out.push_str(&format!("{} {}\n", name, value.load(Ordering::SeqCst)));
+        }
+        out.push_str("# HELP prazi_child_running_seconds Seconds the longest-running subprocesses have run\n");
+        out.push_str("# TYPE prazi_child_running_seconds gauge\n");
+        for (program, krate, elapsed) in children::longest() {
+            out.push_str(&format!(
+                "prazi_child_running_seconds{{program=\"{}\",crate=\"{}\"}} {}\n",
+                program,
+                krate.unwrap_or_default(),
+                elapsed.as_secs()
+            ));
+        }
+        out
+    }
+}
+
+/// Serve the metrics on `addr` (e.g. `127.0.0.1:9100`) from a background thread.
+pub fn serve(addr: &str) -> Result<(), String> {
+    let server = Server::http(addr).map_err(|e| format!("{}", e))?;
+    thread::spawn(move || {
+        let content_type =
+            Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..]).unwrap();
+        for request in server.incoming_requests() {
+            let response =
+                Response::from_string(METRICS.render()).with_header(content_type.clone());
+            if let Err(error) = request.respond(response) {
+                eprintln!("Error serving metrics: {:?}", error);
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,164 @@
+// A registry served from this machine, for the download tests
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Two generated crates are published in a git index of their own and their
+// `.crate` files served over HTTP by a background thread, in the
+// `{prefix}/{crate}/{crate}-{version}.crate` layout of a bucket, which the
+// `dl` of the index points to. `prazi-mock` is listed with the checksum of
+// the file served, `prazi-mock-corrupt` with a wrong one, so its download has
+// to be refused. The index is cloned and the crates are downloaded through
+// the regular code paths, without network access, a toolchain or LLVM.
+use checksum::Algo;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use index;
+use paths;
+use provenance::Provenance;
+use report::{self, Entry, Status};
+use source;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use tar::{Builder, Header};
+use tiny_http::{Response, Server};
+use {test_dir, PraziResult, Registry, VersionSelection};
+
+/// The crates of the mock registry, in their only version `VERSION`.
+const CRATES: &[&str] = &["prazi-mock", "prazi-mock-corrupt"];
+
+const VERSION: &str = "0.1.0";
+
+/// The crate whose index entry has a wrong checksum.
+const CORRUPT: &str = "prazi-mock-corrupt";
+
+/// The tar of the crate `name` in `version`: a `Cargo.toml` and an empty
+/// library below `{name}-{version}`.
+pub fn tarball(name: &str, version: &str) -> Vec<u8> {
+    let root = paths::archive_root(name, version);
+    let manifest = format!("[package]\nname = \"{}\"\nversion = \"{}\"\n", name, version);
+    let mut builder = Builder::new(Vec::new());
+    for &(path, content) in &[("Cargo.toml", manifest.as_str()), ("src/lib.rs", "")] {
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, format!("{}/{}", root, path), content.as_bytes())
+            .unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+/// `bytes` gzipped, as in a `.crate` file.
+pub fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Publish the crates in a fresh index below `dir`, serve them and make the
+/// mock registry the source of the tests. Has to be called before `SOURCE` is
+/// first used. Returns the served `.crate` files by crate name.
+fn start(dir: &Path) -> PraziResult<HashMap<String, Vec<u8>>> {
+    let server = Server::http("127.0.0.1:0").map_err(|e| format!("{}", e))?;
+    let origin = dir.join("origin");
+    let mut archives = HashMap::new();
+    let mut files = HashMap::new();
+    for &name in CRATES {
+        let archive = gzip(&tarball(name, VERSION));
+        let cksum = if name == CORRUPT {
+            Algo::Sha256.digest(b"")
+        } else {
+            Algo::Sha256.digest(&archive)
+        };
+        let entry = format!(
+            "{{\"name\":\"{}\",\"vers\":\"{}\",\"deps\":[],\"cksum\":\"{}\",\"features\":{{}},\"yanked\":false}}\n",
+            name, VERSION, cksum
+        );
+        let prefix = paths::index_prefix(name);
+        fs::create_dir_all(origin.join(&prefix))?;
+        fs::write(origin.join(&prefix).join(name), entry)?;
+        files.insert(format!("/{}/{}/{}-{}.crate", prefix, name, name, VERSION), archive.clone());
+        archives.insert(name.to_string(), archive);
+    }
+    let dl = format!("http://{}/{{prefix}}/{{crate}}/{{crate}}-{{version}}.crate", server.server_addr());
+    fs::write(origin.join("config.json"), format!("{{\"dl\":\"{}\"}}\n", dl))?;
+    index::git(&["init", "--quiet"], &origin)?;
+    index::git(&["add", "."], &origin)?;
+    index::git(
+        &["-c", "user.name=prazi", "-c", "user.email=prazi@localhost", "commit", "--quiet", "-m", "mock index"],
+        &origin,
+    )?;
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let res = match files.get(request.url()) {
+                Some(archive) => request.respond(Response::from_data(archive.clone())),
+                None => request.respond(Response::from_string("not found").with_status_code(404)),
+            };
+            if let Err(error) = res {
+                eprintln!("Error serving the mock registry: {:?}", error);
+            }
+        }
+    });
+    source::select_local(&origin.display().to_string(), &dir.join("index").display().to_string());
+    Ok(archives)
+}
+
+/// The entries of the failures file for `phase`.
+fn failures(phase: &str) -> Vec<Entry> {
+    fs::read_to_string(report::failures_file())
+        .unwrap_or_default()
+        .lines()
+        .map(|line| ::serde_json::from_str::<Entry>(line).unwrap())
+        .filter(|entry| entry.phase == phase)
+        .collect()
+}
+
+#[test]
+fn downloads_the_intact_crate_and_refuses_the_corrupt_one() {
+    let archives = start(&test_dir("mock")).unwrap();
+    let mut reg = Registry::new();
+    reg.version_selection = VersionSelection::Latest;
+    reg.read();
+    let mut selected = reg
+        .list
+        .iter()
+        .map(|krate| (krate.name.as_str(), krate.version.as_str()))
+        .collect::<Vec<_>>();
+    selected.sort();
+    assert_eq!(selected, vec![("prazi-mock", VERSION), (CORRUPT, VERSION)]);
+
+    reg.download_src().unwrap();
+
+    let intact = reg.list.iter().find(|krate| krate.name == "prazi-mock").unwrap();
+    assert!(intact.success_file().exists());
+    assert_eq!(
+        fs::read_to_string(intact.dir().join("Cargo.toml")).unwrap(),
+        "[package]\nname = \"prazi-mock\"\nversion = \"0.1.0\"\n"
+    );
+    assert_eq!(fs::read(intact.dir().join("src").join("lib.rs")).unwrap(), b"");
+    assert!(!intact.partial_file().exists());
+    let provenance = Provenance::read(intact).unwrap();
+    assert!(provenance.url.ends_with("/pr/az/prazi-mock/prazi-mock-0.1.0.crate"));
+    assert_eq!(
+        provenance.checksums.get("sha256"),
+        Some(&Algo::Sha256.digest(&archives["prazi-mock"]))
+    );
+
+    let corrupt = reg.list.iter().find(|krate| krate.name == CORRUPT).unwrap();
+    assert!(!corrupt.dir().exists());
+    assert!(!corrupt.success_file().exists());
+    assert!(!corrupt.partial_file().exists());
+
+    let failures = failures("download");
+    assert_eq!(failures.len(), 1);
+    assert_eq!((failures[0].name.as_str(), failures[0].version.as_str()), (CORRUPT, VERSION));
+    assert_eq!(failures[0].status, Status::Failed);
+    let error = failures[0].details["error"].as_str().unwrap();
+    assert!(error.starts_with("checksum mismatch"), "{}", error);
+}
@@ -0,0 +1,144 @@
+// Declared minimum supported Rust versions against the builds of the crates
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// `validate` records the `rust-version` of every manifest, the
+// `build-{toolchain}.json` reports record which toolchains built a crate.
+// Joining both tells whether the declared MSRV holds: the crate should build
+// with every toolchain at least as recent, and it may build with older ones.
+use report::{self, Entry, Status};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `major.minor.patch` of a Rust release, pre-release tags ignored.
+type RustVersion = (u64, u64, u64);
+
+/// Parse a `rust-version` like `1.56` or `1.56.1`.
+fn parse_version(text: &str) -> Option<RustVersion> {
+    let release = text.trim().split(|c| c == '-' || c == '+').next()?;
+    let mut parts = release.split('.').map(|part| part.parse::<u64>());
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+/// The release of the output of `rustc --version`, e.g. `rustc 1.31.0
+/// (abe02cefd 2018-12-17)` or `rustc 1.33.0-nightly (...)`.
+fn toolchain_version(toolchain: &str) -> Option<RustVersion> {
+    toolchain.split_whitespace().nth(1).and_then(parse_version)
+}
+
+/// How the declared MSRV of a crate compares to its builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    /// Every toolchain at least as recent as the MSRV built the crate, none
+    /// older did.
+    Holds,
+    /// A toolchain at least as recent as the MSRV failed to build the crate.
+    Violated,
+    /// A toolchain older than the MSRV built the crate, the MSRV could be
+    /// lower.
+    Conservative,
+    /// No toolchain at least as recent as the MSRV was attempted.
+    Untested,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MsrvCheck {
+    pub name: String,
+    pub version: String,
+    /// The `rust-version` of the manifest.
+    pub rust_version: String,
+    /// Build status by toolchain.
+    pub builds: BTreeMap<String, Status>,
+    /// The oldest toolchain that built the crate, if any.
+    pub oldest_success: Option<String>,
+    pub verdict: Verdict,
+}
+
+/// The declared `rust-version` of the crates of a `validate` report.
+fn declared(validate: &Path) -> io::Result<BTreeMap<(String, String), String>> {
+    Ok(report::read(validate)?
+        .into_iter()
+        .filter_map(|entry| {
+            let rust_version = entry.details.get("rust_version")?.as_str()?.to_string();
+            Some(((entry.name, entry.version), rust_version))
+        }).collect())
+}
+
+fn toolchain(entry: &Entry) -> Option<String> {
+    entry
+        .details
+        .get("toolchain")
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+}
+
+fn verdict(msrv: RustVersion, builds: &BTreeMap<String, Status>) -> Verdict {
+    let mut tested = false;
+    let mut conservative = false;
+    for (toolchain, &status) in builds {
+        let version = match toolchain_version(toolchain) {
+            Some(version) => version,
+            None => continue,
+        };
+        if version >= msrv {
+            tested = true;
+            if status == Status::Failed {
+                return Verdict::Violated;
+            }
+        } else if status == Status::Ok {
+            conservative = true;
+        }
+    }
+    if conservative {
+        Verdict::Conservative
+    } else if tested {
+        Verdict::Holds
+    } else {
+        Verdict::Untested
+    }
+}
+
+/// Join the `rust-version` recorded in the `validate` report with the
+/// build `reports`, for every crate declaring an MSRV that was built.
+pub fn compare(validate: &Path, reports: &[PathBuf]) -> io::Result<Vec<MsrvCheck>> {
+    let declared = declared(validate)?;
+    let mut builds: BTreeMap<(String, String), BTreeMap<String, Status>> = BTreeMap::new();
+    for path in reports {
+        for entry in report::read(path)? {
+            let key = (entry.name.clone(), entry.version.clone());
+            if entry.status == Status::Skipped || !declared.contains_key(&key) {
+                continue;
+            }
+            if let Some(toolchain) = toolchain(&entry) {
+                builds.entry(key).or_insert_with(BTreeMap::new).insert(toolchain, entry.status);
+            }
+        }
+    }
+    Ok(builds
+        .into_iter()
+        .filter_map(|((name, version), builds)| {
+            let rust_version = declared[&(name.clone(), version.clone())].clone();
+            let msrv = parse_version(&rust_version)?;
+            let oldest_success = builds
+                .iter()
+                .filter(|&(_, &status)| status == Status::Ok)
+                .filter_map(|(toolchain, _)| Some((toolchain_version(toolchain)?, toolchain)))
+                .min()
+                .map(|(_, toolchain)| toolchain.clone());
+            Some(MsrvCheck {
+                verdict: verdict(msrv, &builds),
+                name,
+                version,
+                rust_version,
+                builds,
+                oldest_success,
+            })
+        }).collect())
+}
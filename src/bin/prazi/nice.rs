@@ -0,0 +1,42 @@
+// Leaving resources to the rest of the machine on `--nice`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+
+/// Concurrent downloads in nice mode.
+pub const DOWNLOAD_CONCURRENCY: usize = 2;
+
+/// Scheduling priority (`nice` value) of the process in nice mode.
+#[cfg(unix)]
+const NICENESS: i32 = 10;
+
+/// Leave one core free and lower the scheduling priority of the tool, which
+/// all `cargo`, `opt` and hook subprocesses inherit. The priority is only
+/// lowered on Unix.
+pub fn enable() {
+    let threads = ::num_cpus::get().saturating_sub(1).max(1);
+    if let Err(error) = ::rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+    {
+        eprintln!("Error limiting worker threads: {:?}", error);
+    }
+    lower_priority();
+    message!("Nice mode: {} worker threads, {} concurrent downloads", threads, DOWNLOAD_CONCURRENCY);
+}
+
+#[cfg(unix)]
+fn lower_priority() {
+    // nice values are relative to the current one, as in nice(1)
+    let res = unsafe { ::libc::nice(NICENESS) };
+    if res == -1 {
+        eprintln!(
+            "Error lowering the scheduling priority: {}",
+            ::std::io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn lower_priority() {}
@@ -0,0 +1,188 @@
+// Crates of a crates.io user or team, for `--owner`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The index does not record who owns a crate. A database dump relates the
+// owners to their crates in `crate_owners.csv`; the crates.io API lists the
+// crates of a user or team by its numeric id, which it looks up by login
+// first. The API is paced like in `ingest-downloads` and its answers are
+// cached in `{storage.path}/owners/`, so repeated runs over the same owner
+// send no requests.
+use categories;
+use chrono::{DateTime, Utc};
+use downloads;
+use fetch;
+use report::Envelope;
+use reqwest::header::USER_AGENT;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use {PraziResult, PRAZI_DIR};
+
+const API_URL: &str = "https://crates.io/api/v1";
+
+/// The crates of an owner at some point in time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Owned {
+    /// The GitHub login of a user, or `github:{org}:{team}` of a team.
+    pub login: String,
+    /// The database dump folder or `api`.
+    pub source: String,
+    pub fetched_at: String,
+    pub crates: BTreeSet<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiOwner {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiUser {
+    user: ApiOwner,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiTeam {
+    team: ApiOwner,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiPage {
+    crates: Vec<ApiCrate>,
+    meta: ApiMeta,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCrate {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMeta {
+    /// Query string of the next page, absent on the last one.
+    next_page: Option<String>,
+}
+
+/// Team logins are `github:{org}:{team}`, user logins have no colon.
+fn is_team(login: &str) -> bool {
+    login.contains(':')
+}
+
+fn cache_file(login: &str) -> PathBuf {
+    Path::new(&**PRAZI_DIR)
+        .join("owners")
+        .join(format!("{}.json", login.to_lowercase().replace(':', "_")))
+}
+
+/// Read the crates of `login` from the `data/` folder of a database dump.
+fn from_dump(dir: &Path, login: &str) -> io::Result<BTreeSet<String>> {
+    // `owner_kind` is 0 for users and 1 for teams
+    let (table, column, kind) = if is_team(login) {
+        ("teams.csv", "login", "1")
+    } else {
+        ("users.csv", "gh_login", "0")
+    };
+    let ids = categories::read_table(dir, table)?
+        .iter()
+        .filter(|row| row.get(column).map_or(false, |l| l.eq_ignore_ascii_case(login)))
+        .filter_map(|row| row.get("id").cloned())
+        .collect::<HashSet<_>>();
+    if ids.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no owner {} in {}", login, table),
+        ));
+    }
+    let names = categories::read_table(dir, "crates.csv")?
+        .into_iter()
+        .filter_map(|mut row| Some((row.remove("id")?, row.remove("name")?)))
+        .collect::<HashMap<_, _>>();
+    Ok(categories::read_table(dir, "crate_owners.csv")?
+        .iter()
+        .filter(|row| row.get("owner_kind").map(String::as_str) == Some(kind))
+        .filter(|row| row.get("owner_id").map_or(false, |id| ids.contains(id)))
+        .filter_map(|row| names.get(row.get("crate_id")?).cloned())
+        .collect())
+}
+
+/// Page through the crates of `login` in the crates.io API, one request per
+/// `API_DELAY`.
+fn from_api(login: &str) -> PraziResult<BTreeSet<String>> {
+    let client = ::reqwest::Client::new();
+    let get = |url: String| -> ::reqwest::Result<::reqwest::Response> {
+        client.get(&url).header(USER_AGENT, fetch::user_agent()).send()?.error_for_status()
+    };
+    let mut query = if is_team(login) {
+        let team: ApiTeam = get(format!("{}/teams/{}", API_URL, login))?.json()?;
+        format!("?per_page=100&team_id={}", team.team.id)
+    } else {
+        let user: ApiUser = get(format!("{}/users/{}", API_URL, login))?.json()?;
+        format!("?per_page=100&user_id={}", user.user.id)
+    };
+    let mut crates = BTreeSet::new();
+    loop {
+        thread::sleep(downloads::API_DELAY);
+        let page: ApiPage = get(format!("{}/crates{}", API_URL, query))?.json()?;
+        crates.extend(page.crates.into_iter().map(|c| c.name));
+        match page.meta.next_page {
+            Some(next_page) => query = next_page,
+            None => break,
+        }
+    }
+    Ok(crates)
+}
+
+impl Owned {
+    fn read_cache(login: &str) -> Option<Owned> {
+        let text = fs::read_to_string(cache_file(login)).ok()?;
+        let envelope: Envelope<Owned> = ::serde_json::from_str(&text).ok()?;
+        Some(envelope.data)
+    }
+
+    fn write_cache(&self) -> io::Result<()> {
+        let file = cache_file(&self.login);
+        fs::create_dir_all(file.parent().unwrap())?;
+        fs::write(file, Envelope::new("owner", self).to_json())
+    }
+
+    /// How long ago the crates were fetched.
+    fn age(&self) -> Option<Duration> {
+        let fetched_at = DateTime::parse_from_rfc3339(&self.fetched_at).ok()?;
+        Utc::now().signed_duration_since(fetched_at).to_std().ok()
+    }
+}
+
+/// The crates of the user or team `login` from `from`, a database dump
+/// folder or `api`. Answers of the API younger than `max_age` are taken from
+/// the cache.
+pub fn crates_of(login: &str, from: &str, max_age: Duration) -> PraziResult<Owned> {
+    if from != "api" {
+        return Ok(Owned {
+            login: login.to_string(),
+            source: from.to_string(),
+            fetched_at: Utc::now().to_rfc3339(),
+            crates: from_dump(Path::new(from), login)?,
+        });
+    }
+    if let Some(cached) = Owned::read_cache(login) {
+        if cached.age().map_or(false, |age| age < max_age) {
+            return Ok(cached);
+        }
+    }
+    let owned = Owned {
+        login: login.to_string(),
+        source: from.to_string(),
+        fetched_at: Utc::now().to_rfc3339(),
+        crates: from_api(login)?,
+    };
+    if let Err(error) = owned.write_cache() {
+        eprintln!("Error writing {:?}: {:?}", cache_file(login), error);
+    }
+    Ok(owned)
+}
@@ -0,0 +1,309 @@
+// Download URLs and on-disk locations of crates
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Pure functions of the crate name and version and of the configured roots,
+// `Source` and `PraziCrate` pass the configuration in. Crates are stored as
+// `{registry}/{name}/{version}`, next to the marker files of the version.
+//
+// Versions are used as published, e.g. `1.0.0-alpha.1+build.5`: the `.crate`
+// file is stored under this exact string. Only in URLs the `+` of the build
+// metadata is percent-encoded, storage backends like S3 decode a plain `+`
+// in a path as a space and answer 404.
+use std::path::{Path, PathBuf};
+use std::str;
+
+/// `version` as it appears in download URLs.
+pub fn url_version(version: &str) -> String {
+    version.replace('+', "%2B")
+}
+
+/// `version` as it appears in file names: unchanged for semver versions,
+/// which only have ASCII alphanumerics and `.-+`; other characters (and `%`)
+/// are percent-encoded, so the name still decodes to the version.
+pub fn path_version(version: &str) -> String {
+    let mut res = String::with_capacity(version.len());
+    for byte in version.bytes() {
+        match byte {
+            b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' | b'.' | b'-' | b'+' | b'_' => res.push(byte as char),
+            _ => res.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    res
+}
+
+/// The version a crate folder named `name` by `path_version` is of, `None`
+/// if the percent-encoding of the name is malformed.
+pub fn version_of_path(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2)?;
+            if !hex.iter().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            bytes.push(u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// `{root}/{name}/{name}-{version}.crate`, the layout of the crates.io
+/// storage and of `[crates] mirror_url`.
+pub fn crate_url(root: &str, name: &str, version: &str) -> String {
+    format!(
+        "{0}/{1}/{1}-{2}.crate",
+        root.trim_end_matches('/'),
+        name,
+        url_version(version)
+    )
+}
+
+/// The folder of the index file of `name`, as in the `{prefix}` of a `dl`
+/// template: `1`, `2`, `3/{first letter}` or `{ab}/{cd}`.
+pub fn index_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Expand the `dl` template of an index `config.json` like cargo does, a
+/// template without markers is the root of `{dl}/{name}/{version}/download`.
+pub fn dl_url(dl: &str, name: &str, version: &str) -> String {
+    if ["{crate}", "{version}", "{prefix}", "{lowerprefix}"]
+        .iter()
+        .any(|marker| dl.contains(marker))
+    {
+        let prefix = index_prefix(name);
+        dl.replace("{crate}", name)
+            .replace("{version}", &url_version(version))
+            .replace("{lowerprefix}", &prefix.to_lowercase())
+            .replace("{prefix}", &prefix)
+    } else {
+        format!("{}/{}/{}/download", dl.trim_end_matches('/'), name, url_version(version))
+    }
+}
+
+/// The top-level directory of a crate tarball, `{name}-{version}`.
+pub fn archive_root(name: &str, version: &str) -> String {
+    format!("{0}-{1}", name, version)
+}
+
+/// Folder of all versions of the crate `name` below `registry`.
+pub fn crate_dir_src(registry: &Path, name: &str) -> PathBuf {
+    registry.join(name)
+}
+
+/// Folder the sources of `version` of `name` are extracted into.
+pub fn crate_dir(registry: &Path, name: &str, version: &str) -> PathBuf {
+    crate_dir_src(registry, name).join(path_version(version))
+}
+
+/// Name of the file next to the crate folder marking a complete download.
+pub fn success_file_name(version: &str) -> String {
+    format!("{}_success", path_version(version))
+}
+
+/// Name of the `.crate` file next to the crate folder while downloading.
+pub fn partial_file_name(version: &str) -> String {
+    format!("{}.crate.part", path_version(version))
+}
+
+/// Name of the `.crate` file kept next to the crate folder with
+/// `--keep-archive`.
+pub fn archive_file_name(version: &str) -> String {
+    format!("{}.crate", path_version(version))
+}
+
+/// Name of the provenance record next to the crate folder.
+pub fn provenance_file_name(version: &str) -> String {
+    format!("{}_provenance.json", path_version(version))
+}
+
+/// Name of the folder next to the crate folder it is extracted into when
+/// there is no `[storage] tmp_path`.
+pub fn unpack_dir_name(version: &str) -> String {
+    format!(".{}.tmp", path_version(version))
+}
+
+/// Name of the folder next to the crate folder `--out-of-tree-build` copies
+/// the sources into when there is no `[storage] tmp_path`.
+pub fn build_dir_name(version: &str) -> String {
+    format!(".{}.build", path_version(version))
+}
+
+/// Names of the files and folders next to the crate folder of `version`
+/// that belong to it, as the functions above name them.
+pub fn sibling_names(version: &str) -> Vec<String> {
+    vec![
+        success_file_name(version),
+        partial_file_name(version),
+        archive_file_name(version),
+        provenance_file_name(version),
+        unpack_dir_name(version),
+        build_dir_name(version),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_prefix_by_name_length() {
+        assert_eq!(index_prefix("a"), "1");
+        assert_eq!(index_prefix("ab"), "2");
+        assert_eq!(index_prefix("abc"), "3/a");
+        assert_eq!(index_prefix("abcd"), "ab/cd");
+        assert_eq!(index_prefix("serde"), "se/rd");
+    }
+
+    #[test]
+    fn index_prefix_keeps_case_and_underscores() {
+        assert_eq!(index_prefix("A"), "1");
+        assert_eq!(index_prefix("Abc"), "3/A");
+        assert_eq!(index_prefix("Serde_json"), "Se/rd");
+        assert_eq!(index_prefix("a_b"), "3/a");
+        assert_eq!(index_prefix("_abc"), "_a/bc");
+    }
+
+    #[test]
+    fn url_version_encodes_build_metadata() {
+        assert_eq!(url_version("1.0.0-alpha.1+build.5"), "1.0.0-alpha.1%2Bbuild.5");
+        assert_eq!(url_version("1.0.0+a+b"), "1.0.0%2Ba%2Bb");
+        for &version in &["1.0.0", "0.1.0-alpha.1", "2.0.0-rc.1_x"] {
+            assert_eq!(url_version(version), version);
+        }
+        assert_eq!(
+            crate_url("https://static.crates.io/crates", "a", "1.0.0-alpha.1+build.5"),
+            "https://static.crates.io/crates/a/a-1.0.0-alpha.1%2Bbuild.5.crate"
+        );
+        assert_eq!(
+            dl_url("https://crates.io/api/v1/crates", "a", "1.0.0+build.5"),
+            "https://crates.io/api/v1/crates/a/1.0.0%2Bbuild.5/download"
+        );
+    }
+
+    #[test]
+    fn path_version_keeps_semver_versions() {
+        for &version in &["1.0.0", "1.0.0-alpha.1+build.5", "0.0.1-Beta-2", "1.2.3+20181101"] {
+            assert_eq!(path_version(version), version);
+        }
+    }
+
+    #[test]
+    fn path_version_round_trips() {
+        for &version in &["1.0.0", "1.0.0-alpha.1+build.5", "1.0 beta", "100%", "1.0/../x", "1.0.0-ü", "%2B"] {
+            let path = path_version(version);
+            assert!(!path.contains('/') && !path.contains(' '), "{}", path);
+            assert_eq!(version_of_path(&path), Some(version.to_string()));
+        }
+        assert_eq!(path_version("1.0 beta"), "1.0%20beta");
+        assert_eq!(path_version("100%"), "100%25");
+    }
+
+    #[test]
+    fn version_of_path_refuses_malformed_names() {
+        assert_eq!(version_of_path("1.0%2"), None);
+        assert_eq!(version_of_path("1.0%zz"), None);
+        assert_eq!(version_of_path("1.0%+1"), None);
+        assert_eq!(version_of_path("%FF"), None);
+    }
+
+    #[test]
+    fn crate_url_in_bucket_layout() {
+        assert_eq!(
+            crate_url("https://static.crates.io/crates", "serde", "1.0.80"),
+            "https://static.crates.io/crates/serde/serde-1.0.80.crate"
+        );
+        assert_eq!(
+            crate_url("https://static.crates.io/crates/", "serde_json", "1.0.0-rc.1"),
+            "https://static.crates.io/crates/serde_json/serde_json-1.0.0-rc.1.crate"
+        );
+    }
+
+    #[test]
+    fn dl_url_without_markers() {
+        assert_eq!(
+            dl_url("https://crates.io/api/v1/crates", "serde", "1.0.80"),
+            "https://crates.io/api/v1/crates/serde/1.0.80/download"
+        );
+        assert_eq!(
+            dl_url("https://crates.io/api/v1/crates/", "a", "0.1.0-beta"),
+            "https://crates.io/api/v1/crates/a/0.1.0-beta/download"
+        );
+    }
+
+    #[test]
+    fn dl_url_expands_markers() {
+        let dl = "https://example.com/{prefix}/{crate}/{crate}-{version}.crate";
+        assert_eq!(dl_url(dl, "serde", "1.0.80"), "https://example.com/se/rd/serde/serde-1.0.80.crate");
+        assert_eq!(dl_url(dl, "ab", "1.0.0"), "https://example.com/2/ab/ab-1.0.0.crate");
+        assert_eq!(
+            dl_url("https://example.com/{lowerprefix}/{crate}", "Serde_json", "1.0.0"),
+            "https://example.com/se/rd/Serde_json"
+        );
+        assert_eq!(
+            dl_url("https://example.com/{prefix}/{crate}", "Serde_json", "1.0.0"),
+            "https://example.com/Se/rd/Serde_json"
+        );
+        assert_eq!(dl_url("https://example.com/{version}", "a", "2.0.0-rc.1"), "https://example.com/2.0.0-rc.1");
+    }
+
+    #[test]
+    fn crate_dirs_below_the_registry() {
+        let registry = Path::new("data").join("crates").join("reg");
+        assert_eq!(crate_dir_src(&registry, "serde_json"), registry.join("serde_json"));
+        assert_eq!(
+            crate_dir(&registry, "serde_json", "1.0.80"),
+            registry.join("serde_json").join("1.0.80")
+        );
+        assert_eq!(
+            crate_dir(&registry, "a", "1.0.0-alpha.1+build.5"),
+            registry.join("a").join("1.0.0-alpha.1+build.5")
+        );
+    }
+
+    #[test]
+    fn archive_root_joins_name_and_version() {
+        assert_eq!(archive_root("serde_json", "1.0.0-rc.1"), "serde_json-1.0.0-rc.1");
+        assert_eq!(archive_root("a", "1.0.0+build.5"), "a-1.0.0+build.5");
+    }
+
+    #[test]
+    fn file_names_next_to_the_crate_folder() {
+        assert_eq!(success_file_name("1.0.0"), "1.0.0_success");
+        assert_eq!(partial_file_name("1.0.0"), "1.0.0.crate.part");
+        assert_eq!(archive_file_name("1.0.0"), "1.0.0.crate");
+        assert_eq!(provenance_file_name("1.0.0"), "1.0.0_provenance.json");
+        assert_eq!(unpack_dir_name("1.0.0"), ".1.0.0.tmp");
+        assert_eq!(build_dir_name("1.0.0"), ".1.0.0.build");
+        assert_eq!(success_file_name("0.1.0-alpha.1+build.5"), "0.1.0-alpha.1+build.5_success");
+        assert_eq!(archive_file_name("0.1.0-alpha.1+build.5"), "0.1.0-alpha.1+build.5.crate");
+    }
+
+    #[test]
+    fn sibling_names_are_the_file_names() {
+        assert_eq!(
+            sibling_names("1.0.0-rc.1"),
+            vec![
+                "1.0.0-rc.1_success",
+                "1.0.0-rc.1.crate.part",
+                "1.0.0-rc.1.crate",
+                "1.0.0-rc.1_provenance.json",
+                ".1.0.0-rc.1.tmp",
+                ".1.0.0-rc.1.build",
+            ]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// Crate selection rules of a `--policy` file
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// A policy is a TOML file that can be versioned and shared with a dataset:
+//
+//     include = ["serde*", "tokio-*"]
+//     exclude = ["*-sys"]
+//     max_versions_per_crate = 3
+//
+//     [versions]
+//     "serde*" = ">=1.0"
+//
+// A crate is selected if its name matches an `include` glob (any name when
+// there are none) and no `exclude` glob. Its versions have to meet the
+// requirements of all `versions` globs matching the name.
+use glob::Pattern;
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The policy file as written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    include: Vec<String>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    versions: BTreeMap<String, String>,
+    max_versions_per_crate: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Policy {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    versions: Vec<(Pattern, VersionReq)>,
+    /// Keep at most this many versions of every crate, unless the command
+    /// line caps them.
+    pub max_versions_per_crate: Option<usize>,
+}
+
+fn patterns(globs: &[String]) -> Result<Vec<Pattern>, String> {
+    globs
+        .iter()
+        .map(|glob| Pattern::new(glob).map_err(|error| format!("invalid glob {:?}: {}", glob, error)))
+        .collect()
+}
+
+impl Policy {
+    pub fn read(path: &Path) -> Result<Policy, String> {
+        let text = fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let file: PolicyFile = ::toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut versions = Vec::new();
+        for (glob, req) in &file.versions {
+            let pattern = Pattern::new(glob).map_err(|error| format!("invalid glob {:?}: {}", glob, error))?;
+            let req = VersionReq::parse(req).map_err(|error| format!("invalid requirement {:?}: {}", req, error))?;
+            versions.push((pattern, req));
+        }
+        Ok(Policy {
+            include: patterns(&file.include)?,
+            exclude: patterns(&file.exclude)?,
+            versions,
+            max_versions_per_crate: file.max_versions_per_crate,
+        })
+    }
+
+    /// Is the crate `name` selected?
+    pub fn admits(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| p.matches(name)))
+            && !self.exclude.iter().any(|p| p.matches(name))
+    }
+
+    /// Does `version` of `name` meet the requirements for it? Versions that do
+    /// not parse only pass when no requirement applies.
+    pub fn admits_version(&self, name: &str, version: &str) -> bool {
+        let mut reqs = self.versions.iter().filter(|(p, _)| p.matches(name)).peekable();
+        if reqs.peek().is_none() {
+            return true;
+        }
+        match Version::parse(version) {
+            Ok(parsed) => reqs.all(|(_, req)| req.matches(&parsed)),
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,70 @@
+// Per-crate record of where and how a crate's sources were obtained
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use PraziCrate;
+
+/// Stored as `{name}/{version}_provenance.json` next to the sources.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Where the `.crate` file was downloaded from.
+    pub url: String,
+    pub downloaded_at: String,
+    /// `ETag` the server returned for the `.crate` file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` the server returned for the `.crate` file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// Top-level directory of the archive when it is not `{name}-{version}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archive_root: Option<String>,
+    /// Hex digests of the `.crate` file by algorithm name, e.g. `sha256`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksums: BTreeMap<String, String>,
+    /// Software Heritage identifier of the extracted source tree.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub swhid: Option<String>,
+    /// Symlinks of the archive that stay within the tree, relative to the
+    /// crate folder.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub symlinks: Vec<String>,
+    /// Symlinks of the archive pointing outside the tree, not extracted.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped_symlinks: Vec<String>,
+    /// The regular files of the archive, in archive order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<ArchiveFile>,
+}
+
+/// A regular file of a crate archive, as recorded in its tar header. The
+/// extraction preserves the mode and mtime.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveFile {
+    /// Path relative to the crate folder.
+    pub path: String,
+    pub size: u64,
+    /// Permission bits, e.g. `0o644`.
+    pub mode: u32,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+}
+
+impl Provenance {
+    /// The provenance of `krate`, if it was recorded.
+    pub fn read(krate: &PraziCrate) -> Option<Provenance> {
+        let text = fs::read_to_string(krate.provenance_file()).ok()?;
+        ::serde_json::from_str(&text).ok()
+    }
+
+    pub fn write(&self, krate: &PraziCrate) -> io::Result<()> {
+        fs::write(
+            krate.provenance_file(),
+            ::serde_json::to_string_pretty(self).unwrap(),
+        )
+    }
+}
@@ -0,0 +1,369 @@
+// Versioned envelope shared by all JSON outputs
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::any::Any;
+use std::io::{self, BufRead, BufReader};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use checkpoint;
+use children;
+use disk;
+use sink::AppendSink;
+use stop;
+use summary;
+use {config_value, run_dir, PraziCrate, PRAZI_DIR};
+
+/// Version of the JSON output schemas, documented in `doc/schemas.md`.
+///
+/// Bump it whenever a field of any output is added, removed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Top-level object of every JSON document the tool emits.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub schema_version: u32,
+    /// What `data` contains, e.g. `callgraph-diff`.
+    pub kind: String,
+    pub tool_version: String,
+    pub generated_at: String,
+    pub data: T,
+}
+
+impl<T: Serialize> Envelope<T> {
+    pub fn new(kind: &str, data: T) -> Envelope<T> {
+        Envelope {
+            schema_version: SCHEMA_VERSION,
+            kind: kind.to_string(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            generated_at: Utc::now().to_rfc3339(),
+            data,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        ::serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Outcome of a phase for a single crate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// The result of running a phase on one crate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub version: String,
+    pub phase: String,
+    pub status: Status,
+    /// Phase specific information, e.g. the environment of a build.
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub details: Map<String, Value>,
+}
+
+impl Entry {
+    pub fn new(krate: &PraziCrate, phase: &str, status: Status) -> Entry {
+        Entry {
+            name: krate.name.clone(),
+            version: krate.version.clone(),
+            phase: phase.to_string(),
+            status,
+            details: Map::new(),
+        }
+    }
+
+    /// Attach a detail to the entry.
+    pub fn with<V: Serialize>(mut self, key: &str, value: V) -> Entry {
+        self.details
+            .insert(key.to_string(), ::serde_json::to_value(value).unwrap());
+        self
+    }
+}
+
+lazy_static! {
+    /// Lines and bytes of the tail of command output kept in reports, from
+    /// `[report] output_lines` and `output_bytes`.
+    static ref TAIL_LIMITS: (usize, usize) = (
+        config_value("report", "output_lines").map_or(50, |v| v.parse().expect("invalid [report] output_lines")),
+        config_value("report", "output_bytes").map_or(8192, |v| v.parse().expect("invalid [report] output_bytes")),
+    );
+}
+
+/// The end of the output of a command as kept in reports: its last
+/// `[report] output_lines` lines, of at most `output_bytes` bytes. Truncated
+/// output starts with `[truncated]`.
+pub fn tail(output: &str) -> String {
+    let (max_lines, max_bytes) = *TAIL_LIMITS;
+    let output = output.trim_end();
+    let mut start = output
+        .rmatch_indices('\n')
+        .nth(max_lines.saturating_sub(1))
+        .map_or(0, |(i, _)| i + 1);
+    if output.len() - start > max_bytes {
+        start = output.len() - max_bytes;
+        while !output.is_char_boundary(start) {
+            start += 1;
+        }
+    }
+    if start == 0 {
+        output.to_string()
+    } else {
+        format!("[truncated]\n{}", &output[start..])
+    }
+}
+
+/// Folder all phase reports are written to.
+pub fn reports_dir() -> PathBuf {
+    run_dir(Path::new(&**PRAZI_DIR)).join("reports")
+}
+
+/// Write the report of `phase` to `reports/{phase}.json`.
+pub fn write(phase: &str, entries: &[Entry]) -> io::Result<PathBuf> {
+    write_named(phase, phase, entries)
+}
+
+/// Write the report of `phase` to `reports/{name}.json`.
+pub fn write_named(name: &str, phase: &str, entries: &[Entry]) -> io::Result<PathBuf> {
+    let dir = reports_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.json", name));
+    fs::write(&path, Envelope::new(phase, entries).to_json())?;
+    Ok(path)
+}
+
+/// The entries of a report written by `write`.
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let text = fs::read_to_string(path)?;
+    let envelope: Envelope<Vec<Entry>> = ::serde_json::from_str(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(envelope.data)
+}
+
+fn invalid<E: ToString>(path: &Path, error: E) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), error.to_string()),
+    )
+}
+
+/// The entries of a report or of a failures file (`.jsonl`) with the time
+/// they were produced: the report's `generated_at`, or the modification time
+/// of the failures file. Reports of other schema versions are refused.
+fn read_input(path: &Path) -> io::Result<(DateTime<Utc>, Vec<Entry>)> {
+    let text = fs::read_to_string(path)?;
+    if path.extension().map_or(false, |ext| ext == "jsonl") {
+        let modified = DateTime::<Utc>::from(fs::metadata(path)?.modified()?);
+        let entries = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| ::serde_json::from_str(line).map_err(|e| invalid(path, e)))
+            .collect::<io::Result<Vec<Entry>>>()?;
+        return Ok((modified, entries));
+    }
+    let envelope: Envelope<Value> = ::serde_json::from_str(&text).map_err(|e| invalid(path, e))?;
+    if envelope.schema_version != SCHEMA_VERSION {
+        return Err(invalid(
+            path,
+            format!(
+                "schema version {}, expected {}",
+                envelope.schema_version, SCHEMA_VERSION
+            ),
+        ));
+    }
+    let generated_at = DateTime::parse_from_rfc3339(&envelope.generated_at)
+        .map_err(|e| invalid(path, e))?
+        .with_timezone(&Utc);
+    let entries = ::serde_json::from_value(envelope.data)
+        .map_err(|e| invalid(path, format!("{} is not a phase report: {}", envelope.kind, e)))?;
+    Ok((generated_at, entries))
+}
+
+/// Combine the reports of several runs, e.g. of shards of the registry built
+/// on different machines, into one entry per crate and phase. Of conflicting
+/// entries a successful one wins, then the most recent; later lines of a
+/// failures file are more recent. Returns the phase of the entries as kind,
+/// `reports` when they are of different phases.
+pub fn merge(inputs: &[PathBuf]) -> io::Result<(String, Vec<Entry>)> {
+    let mut merged: BTreeMap<(String, String, String), ((bool, DateTime<Utc>, usize), Entry)> =
+        BTreeMap::new();
+    let mut seq = 0;
+    for path in inputs {
+        let (generated_at, entries) = read_input(path)?;
+        for entry in entries {
+            seq += 1;
+            let rank = (entry.status == Status::Ok, generated_at, seq);
+            let key = (entry.name.clone(), entry.version.clone(), entry.phase.clone());
+            if merged.get(&key).map_or(true, |&(ref best, _)| rank > *best) {
+                merged.insert(key, (rank, entry));
+            }
+        }
+    }
+    let entries = merged.into_iter().map(|(_, (_, entry))| entry).collect::<Vec<_>>();
+    let phases = entries.iter().map(|e| e.phase.as_str()).collect::<HashSet<_>>();
+    let kind = match phases.len() {
+        1 => phases.into_iter().next().unwrap().to_string(),
+        _ => "reports".to_string(),
+    };
+    Ok((kind, entries))
+}
+
+/// File every failure of any phase is appended to as it happens, one JSON
+/// entry per line.
+pub fn failures_file() -> PathBuf {
+    run_dir(Path::new(&**PRAZI_DIR)).join("failures.jsonl")
+}
+
+lazy_static! {
+    static ref FAILURES: AppendSink = AppendSink::new(failures_file());
+}
+
+/// Append a failed entry to the failures file.
+pub fn record_failure(entry: &Entry) {
+    stop::failure();
+    if let Err(error) = FAILURES.append(&::serde_json::to_string(entry).unwrap()) {
+        disk::check(FAILURES.path(), &error);
+        eprintln!("Error recording failure: {:?} {:?}", entry, error);
+    }
+}
+
+/// Write the failures recorded so far to the failures file.
+pub fn flush_failures() {
+    if let Err(error) = FAILURES.flush() {
+        disk::check(FAILURES.path(), &error);
+        eprintln!("Error writing {:?}: {:?}", FAILURES.path(), error);
+    }
+}
+
+/// Run `phase` on `krate`, turning a panic into a failed (and recorded) entry
+/// so that one crate cannot abort the batch and lose the results of the others.
+pub fn guard<F>(krate: &PraziCrate, phase: &str, f: F) -> Option<Entry>
+where
+    F: FnOnce() -> Option<Entry>,
+{
+    let outer = children::set_crate(Some(format!("{}@{}", krate.name, krate.version)));
+    let res = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let entry = panic_entry(krate, phase, &*payload);
+        record_failure(&entry);
+        Some(entry)
+    });
+    children::set_crate(outer);
+    if let Some(ref entry) = res {
+        summary::result(krate, phase, entry.status);
+    }
+    checkpoint::record(krate, phase, res.as_ref());
+    res
+}
+
+/// The failed entry of `krate` in `phase` for the panic with `payload`.
+pub fn panic_entry(krate: &PraziCrate, phase: &str, payload: &(Any + Send)) -> Entry {
+    Entry::new(krate, phase, Status::Failed).with("panic", panic_message(payload))
+}
+
+fn panic_message(payload: &(Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Rewrite the failures file with `failures` in place of the failures of the
+/// crates and phases of `outcomes`; the other lines are kept as they are.
+pub fn replace_failures(outcomes: &[Entry], failures: &[Entry]) -> io::Result<()> {
+    let replaced = outcomes
+        .iter()
+        .map(|e| (e.name.as_str(), e.version.as_str(), e.phase.as_str()))
+        .collect::<HashSet<_>>();
+    FAILURES.close()?;
+    let file = failures_file();
+    let mut text = String::new();
+    if let Ok(old) = fs::File::open(&file) {
+        for line in BufReader::new(old).lines() {
+            let line = line?;
+            let keep = match ::serde_json::from_str::<Entry>(&line) {
+                Ok(entry) => !replaced.contains(&(&*entry.name, &*entry.version, &*entry.phase)),
+                Err(_) => true,
+            };
+            if keep {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+    }
+    for entry in failures {
+        text.push_str(&::serde_json::to_string(entry).unwrap());
+        text.push('\n');
+    }
+    fs::create_dir_all(file.parent().unwrap())?;
+    let tmp = file.with_extension("jsonl.tmp");
+    fs::write(&tmp, text)?;
+    fs::rename(&tmp, &file)
+}
+
+/// `(name, version)` of all crates that failed `phase` in earlier runs.
+pub fn failed(phase: &str) -> HashSet<(String, String)> {
+    let file = match fs::File::open(failures_file()) {
+        Ok(file) => file,
+        Err(_) => return HashSet::new(),
+    };
+    BufReader::new(file)
+        .lines()
+        .filter_map(|line| line.ok())
+        .filter_map(|line| ::serde_json::from_str::<Entry>(&line).ok())
+        .filter(|entry| entry.phase == phase)
+        .map(|entry| (entry.name, entry.version))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayon::prelude::*;
+
+    #[test]
+    fn guard_turns_a_panic_into_a_failed_entry() {
+        let crates = (0..16)
+            .map(|i| PraziCrate {
+                name: format!("guard-{}", i),
+                version: "1.0.0".to_string(),
+            }).collect::<Vec<_>>();
+        let entries = crates
+            .par_iter()
+            .filter_map(|krate| guard(krate, "guard-test", || {
+                if krate.name == "guard-3" {
+                    panic!("no {}", krate.name);
+                }
+                Some(Entry::new(krate, "guard-test", Status::Ok))
+            })).collect::<Vec<_>>();
+        assert_eq!(entries.len(), crates.len());
+        let (panicked, ok): (Vec<_>, Vec<_>) = entries.iter().partition(|e| e.status == Status::Failed);
+        assert_eq!(panicked.len(), 1);
+        assert_eq!(panicked[0].name, "guard-3");
+        assert_eq!(panicked[0].details["panic"], "no guard-3");
+        assert!(ok.iter().all(|e| e.status == Status::Ok));
+        let mut ok = ok.iter().map(|e| e.name.clone()).collect::<Vec<_>>();
+        ok.sort();
+        let mut others = crates.iter().map(|k| k.name.clone()).filter(|name| name != "guard-3").collect::<Vec<_>>();
+        others.sort();
+        assert_eq!(ok, others);
+        flush_failures();
+        assert_eq!(
+            failed("guard-test").into_iter().collect::<Vec<_>>(),
+            vec![("guard-3".to_string(), "1.0.0".to_string())]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+// The dependency graph cargo resolves for a crate, for `resolve`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The index only declares version requirements; `cargo metadata` resolves
+// them to the exact versions a build of the crate uses, for all targets and
+// with the dev-dependencies of the crate itself. Packages are identified by
+// `name@version`, as in the other outputs.
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The resolved packages of a crate and the dependencies of each.
+#[derive(Debug, Default, Serialize)]
+pub struct ResolvedGraph {
+    /// `name@version` of the crate itself.
+    pub root: Option<String>,
+    pub packages: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl ResolvedGraph {
+    /// The graph of the `resolve` section of `cargo metadata --format-version 1`
+    /// output, `None` when it has none.
+    pub fn from_metadata(metadata: &Value) -> Option<ResolvedGraph> {
+        let ids = metadata["packages"]
+            .as_array()?
+            .iter()
+            .filter_map(|package| {
+                let id = package["id"].as_str()?;
+                let key = format!("{}@{}", package["name"].as_str()?, package["version"].as_str()?);
+                Some((id, key))
+            }).collect::<BTreeMap<_, _>>();
+        let resolve = &metadata["resolve"];
+        let mut graph = ResolvedGraph {
+            root: resolve["root"].as_str().and_then(|id| ids.get(id)).cloned(),
+            ..ResolvedGraph::default()
+        };
+        for node in resolve["nodes"].as_array()? {
+            let key = match node["id"].as_str().and_then(|id| ids.get(id)) {
+                Some(key) => key.clone(),
+                None => continue,
+            };
+            let deps = node["dependencies"]
+                .as_array()
+                .map_or(BTreeSet::new(), |deps| {
+                    deps.iter()
+                        .filter_map(|id| id.as_str().and_then(|id| ids.get(id)).cloned())
+                        .collect()
+                });
+            graph.packages.insert(key, deps);
+        }
+        Some(graph)
+    }
+
+    /// The resolved direct dependencies of the crate.
+    pub fn direct(&self) -> BTreeSet<String> {
+        self.root
+            .as_ref()
+            .and_then(|root| self.packages.get(root))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Names resolved to more than one version.
+    pub fn duplicates(&self) -> BTreeSet<String> {
+        let mut versions: BTreeMap<&str, usize> = BTreeMap::new();
+        for key in self.packages.keys() {
+            let name = key.splitn(2, '@').next().unwrap();
+            *versions.entry(name).or_insert(0) += 1;
+        }
+        versions
+            .into_iter()
+            .filter(|&(_, count)| count > 1)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
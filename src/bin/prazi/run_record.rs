@@ -0,0 +1,162 @@
+// The configuration a phase ran with, in `run-{phase}.json`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Written when a phase has read the index and rewritten with its end time
+// when the command finishes, next to the reports of the run. Every phase has
+// a file of its own, so a later phase keeps the record of the earlier ones,
+// and a run of the same phase replaces it. Together with the provenance of
+// the crates, these are what a dataset release needs to be reproduced.
+use chrono::Utc;
+use dataset;
+use report::Envelope;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use {config_all, config_index_dir, config_value, opt_binary, run_dir, rustc_verbose, rustc_version, PRAZI_DIR};
+
+/// The subcommands that process crates or produce the artifacts of a dataset
+/// and get a `run-{phase}.json`.
+pub const PHASES: &[&str] = &[
+    "download",
+    "validate",
+    "resolve",
+    "rewrite",
+    "build-crates",
+    "build-callgraphs",
+    "export-api",
+    "export-features",
+    "collect-mir",
+    "verify-graphs",
+    "audit-checksums",
+    "verify-archives",
+    "analyze-sources",
+    "count-loc",
+    "merge-callgraphs",
+    "package-dataset",
+    "analyze",
+    "unused-deps",
+];
+
+/// What `run-{phase}.json` holds.
+#[derive(Debug, Clone, Serialize)]
+struct RunRecord {
+    phase: String,
+    /// The command line, without the program.
+    args: Vec<String>,
+    /// Crates selected by the filters of the command line, 0 for the phases
+    /// that read a merged graph instead.
+    crates: usize,
+    index_commit: Option<String>,
+    /// All values of the configuration by section, `""` for the keys outside
+    /// of any.
+    config: BTreeMap<String, BTreeMap<String, String>>,
+    /// `rustc --version` of the builds.
+    rustc: Option<String>,
+    /// The fields of `rustc --version --verbose`, e.g. `commit-hash`.
+    rustc_verbose: Option<BTreeMap<String, String>>,
+    /// `cargo --version` of the builds.
+    cargo: Option<String>,
+    /// `rustc -V` of `[compiler] nightly`, for `export-api`.
+    nightly: Option<String>,
+    /// The version line of `opt --version`.
+    llvm: Option<String>,
+    os: String,
+    arch: String,
+    started_at: String,
+    /// Absent while the phase is running.
+    finished_at: Option<String>,
+}
+
+lazy_static! {
+    static ref PHASE: Mutex<Option<String>> = Mutex::new(None);
+    static ref RECORD: Mutex<Option<RunRecord>> = Mutex::new(None);
+}
+
+/// The record of the last run of `phase`.
+pub fn run_file(phase: &str) -> PathBuf {
+    run_dir(Path::new(&**PRAZI_DIR)).join(format!("run-{}.json", phase))
+}
+
+/// The records of all phases run so far, with their phases.
+pub fn run_files() -> Vec<(&'static str, PathBuf)> {
+    PHASES
+        .iter()
+        .map(|&phase| (phase, run_file(phase)))
+        .filter(|&(_, ref file)| file.exists())
+        .collect()
+}
+
+/// Have `start` record a run of the subcommand `name` if it is a phase.
+pub fn set_phase(name: &str) {
+    if PHASES.contains(&name) {
+        *PHASE.lock().unwrap() = Some(name.to_string());
+    }
+}
+
+/// The first line of the standard output of `program args` that contains
+/// `needle`, if it runs successfully.
+fn version_line(program: &mut Command, needle: &str) -> Option<String> {
+    let output = program.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|line| line.contains(needle))
+        .map(|line| line.trim().to_string())
+}
+
+fn write(record: &RunRecord) {
+    let file = run_file(&record.phase);
+    let res = fs::create_dir_all(file.parent().unwrap())
+        .and_then(|()| fs::write(&file, Envelope::new("run", record).to_json()));
+    if let Err(error) = res {
+        eprintln!("Error writing {:?}: {:?}", file, error);
+    }
+}
+
+/// Write `run-{phase}.json` for the phase set with `set_phase`, which selected
+/// `crates`.
+pub fn start(crates: usize) {
+    let phase = match *PHASE.lock().unwrap() {
+        Some(ref phase) => phase.clone(),
+        None => return,
+    };
+    let nightly = config_value("compiler", "nightly").and_then(|nightly| {
+        version_line(Command::new("rustup").args(&["run", &nightly, "rustc", "-V"]), "rustc")
+    });
+    let llvm = config_value("llvm", "path")
+        .and_then(|path| version_line(Command::new(opt_binary(&path)).arg("--version"), "version"));
+    let record = RunRecord {
+        phase,
+        args: env::args().skip(1).collect(),
+        crates,
+        index_commit: dataset::index_commit(&config_index_dir()),
+        config: config_all(),
+        rustc: rustc_version(),
+        rustc_verbose: rustc_verbose(),
+        cargo: version_line(Command::new("cargo").arg("--version"), "cargo"),
+        nightly,
+        llvm,
+        os: env::consts::OS.to_string(),
+        arch: env::consts::ARCH.to_string(),
+        started_at: Utc::now().to_rfc3339(),
+        finished_at: None,
+    };
+    write(&record);
+    *RECORD.lock().unwrap() = Some(record);
+}
+
+/// Record the end of the phase in its file, if `start` wrote it.
+pub fn finish() {
+    if let Some(ref mut record) = *RECORD.lock().unwrap() {
+        record.finished_at = Some(Utc::now().to_rfc3339());
+        write(record);
+    }
+}
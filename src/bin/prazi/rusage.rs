@@ -0,0 +1,91 @@
+// Peak memory and CPU time of build subprocesses
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// On Unix the child is reaped with `wait4`, whose resource usage covers the
+// child and the descendants it waited for, i.e. the `rustc` processes of a
+// `cargo build`. Elsewhere only the wall-clock time is known.
+use std::io;
+use std::process::{Command, Output};
+use std::time::Instant;
+
+/// What a subprocess used until it exited.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Usage {
+    pub wall_time_ms: u64,
+    /// Peak resident set size of the process and its descendants.
+    pub max_rss_bytes: Option<u64>,
+    pub user_time_ms: Option<u64>,
+    pub system_time_ms: Option<u64>,
+}
+
+fn millis(duration: ::std::time::Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+/// Run `cmd` like `Command::output` and measure what it used.
+#[cfg(unix)]
+pub fn output(cmd: &mut Command) -> io::Result<(Output, Usage)> {
+    use std::io::Read;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::Stdio;
+    use std::thread;
+
+    let started = Instant::now();
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // both pipes are drained at once, a child blocked on a full one never exits
+    let mut stdout = child.stdout.take().unwrap();
+    let stdout = thread::spawn(move || {
+        let mut buf = Vec::new();
+        stdout.read_to_end(&mut buf).map(|_| buf)
+    });
+    let mut stderr = Vec::new();
+    child.stderr.take().unwrap().read_to_end(&mut stderr)?;
+    let mut status = 0;
+    let mut rusage: ::libc::rusage = unsafe { ::std::mem::zeroed() };
+    loop {
+        let res = unsafe { ::libc::wait4(child.id() as ::libc::pid_t, &mut status, 0, &mut rusage) };
+        if res >= 0 {
+            break;
+        }
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    let stdout = stdout
+        .join()
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "reading stdout panicked"))??;
+    // Linux reports kilobytes, macOS bytes
+    let rss_unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    let cpu_ms = |time: ::libc::timeval| time.tv_sec as u64 * 1000 + time.tv_usec as u64 / 1000;
+    let usage = Usage {
+        wall_time_ms: millis(started.elapsed()),
+        max_rss_bytes: Some(rusage.ru_maxrss as u64 * rss_unit),
+        user_time_ms: Some(cpu_ms(rusage.ru_utime)),
+        system_time_ms: Some(cpu_ms(rusage.ru_stime)),
+    };
+    let output = Output {
+        status: ExitStatusExt::from_raw(status),
+        stdout,
+        stderr,
+    };
+    Ok((output, usage))
+}
+
+#[cfg(not(unix))]
+pub fn output(cmd: &mut Command) -> io::Result<(Output, Usage)> {
+    let started = Instant::now();
+    let output = cmd.output()?;
+    let usage = Usage {
+        wall_time_ms: millis(started.elapsed()),
+        ..Usage::default()
+    };
+    Ok((output, usage))
+}
@@ -0,0 +1,141 @@
+// Explicit crate lists and their expansion along the dependency graph
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Crates named in a `--from-list` file.
+#[derive(Debug, Default)]
+pub struct CrateList {
+    pub names: HashSet<String>,
+    /// Versions pinned with `name@version`, a name may be pinned several times.
+    pub versions: HashMap<String, HashSet<String>>,
+}
+
+impl CrateList {
+    /// Read a file with one `name` or `name@version` per line. Empty lines and
+    /// lines starting with `#` are ignored.
+    pub fn read(path: &Path) -> io::Result<CrateList> {
+        let mut list = CrateList::default();
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '@');
+            let name = parts.next().unwrap().to_string();
+            if let Some(version) = parts.next() {
+                list.versions
+                    .entry(name.clone())
+                    .or_insert_with(HashSet::new)
+                    .insert(version.to_string());
+            }
+            list.names.insert(name);
+        }
+        Ok(list)
+    }
+
+    /// Is `version` of `name` selected? Names without pinned versions select
+    /// whatever the version selection picked.
+    pub fn contains(&self, name: &str, version: &str) -> bool {
+        self.names.contains(name) && self
+            .versions
+            .get(name)
+            .map_or(true, |versions| versions.contains(version))
+    }
+}
+
+/// Relation followed by `--expand`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Dependents,
+    Dependencies,
+    Both,
+}
+
+/// `--expand {dependents,dependencies,both}[:depth]`, the depth defaults to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Expand {
+    pub relation: Relation,
+    pub depth: usize,
+}
+
+impl FromStr for Expand {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Expand, String> {
+        let mut parts = s.splitn(2, ':');
+        let relation = match parts.next().unwrap() {
+            "dependents" => Relation::Dependents,
+            "dependencies" => Relation::Dependencies,
+            "both" => Relation::Both,
+            other => return Err(format!("unknown relation: {}", other)),
+        };
+        let depth = match parts.next() {
+            Some(depth) => depth
+                .parse()
+                .map_err(|_| format!("invalid expansion depth: {}", depth))?,
+            None => 1,
+        };
+        Ok(Expand { relation, depth })
+    }
+}
+
+/// Grow `names` by the crates reachable through `expand` in `dependencies`
+/// (crate name to the names it depends on). Stops with a warning once the
+/// set holds `limit` crates.
+pub fn expand(
+    names: &HashSet<String>,
+    dependencies: &HashMap<String, Vec<String>>,
+    expand: Expand,
+    limit: usize,
+) -> HashSet<String> {
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    if expand.relation != Relation::Dependencies {
+        for (name, deps) in dependencies {
+            for dep in deps {
+                dependents
+                    .entry(dep.as_str())
+                    .or_insert_with(Vec::new)
+                    .push(name.as_str());
+            }
+        }
+    }
+    let mut selected = names.clone();
+    let mut frontier = names.iter().cloned().collect::<Vec<_>>();
+    for _ in 0..expand.depth {
+        let mut next = Vec::new();
+        for name in &frontier {
+            let mut neighbours = Vec::new();
+            if expand.relation != Relation::Dependents {
+                if let Some(deps) = dependencies.get(name) {
+                    neighbours.extend(deps.iter().map(|d| d.as_str()));
+                }
+            }
+            if let Some(deps) = dependents.get(name.as_str()) {
+                neighbours.extend(deps.iter().cloned());
+            }
+            for neighbour in neighbours {
+                if selected.contains(neighbour) {
+                    continue;
+                }
+                if selected.len() >= limit {
+                    eprintln!(
+                        "Warning: --expand stopped at {} crates, raise --expand-limit to select more",
+                        limit
+                    );
+                    return selected;
+                }
+                selected.insert(neighbour.to_string());
+                next.push(neighbour.to_string());
+            }
+        }
+        frontier = next;
+    }
+    selected
+}
@@ -0,0 +1,59 @@
+// End-to-end smoke test of the pipeline over a few well-known crates
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The stages run through the same code paths as the regular subcommands, so a
+// passing self-test means the deployment (index access, downloads, toolchain,
+// LLVM) is able to process real crates. The crates are looked up in the index
+// by name, it is not enumerated.
+use {PraziCrate, Registry};
+
+/// Crates processed by `prazi selftest`, in their latest version.
+pub const CRATES: &[&str] = &["libc", "bitflags", "serde"];
+
+/// Run download, build and callgraph over `reg`'s crates and check the
+/// artifacts of every stage. Crates failing a stage are reported and left out
+/// of the later stages. Returns whether all crates passed all stages.
+pub fn run(reg: &mut Registry) -> bool {
+    reg.list.retain(|krate| CRATES.contains(&krate.name.as_str()));
+    if reg.list.len() != CRATES.len() {
+        println!("selftest: FAILED index, found {:?}", reg.list);
+        return false;
+    }
+    let mut passed = true;
+
+    if let Err(error) = reg.download_src() {
+        eprintln!("Error downloading: {:?}", error);
+    }
+    passed &= check(reg, "download", |krate| krate.success_file().exists());
+
+    reg.compile();
+    let profile = reg.profile;
+    passed &= check(reg, "build", |krate| krate.has_bitcode(profile));
+
+    reg.build_callgraph();
+    let link = reg.link_bitcode;
+    passed &= check(reg, "callgraph", |krate| krate.has_callgraphs(profile, link));
+
+    println!("selftest: {}", if passed { "passed" } else { "FAILED" });
+    passed
+}
+
+/// Report the crates without the artifact of `stage` and drop them.
+fn check<F: Fn(&PraziCrate) -> bool>(reg: &mut Registry, stage: &str, artifact: F) -> bool {
+    let before = reg.list.len();
+    reg.list.retain(|krate| {
+        let ok = artifact(krate);
+        println!(
+            "selftest: {} {} {} {}",
+            if ok { "ok    " } else { "FAILED" },
+            stage,
+            krate.name,
+            krate.version
+        );
+        ok
+    });
+    reg.list.len() == before
+}
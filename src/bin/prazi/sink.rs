@@ -0,0 +1,163 @@
+// Line-oriented output files appended to by parallel workers
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// A line is only written while holding the lock of its sink, into a buffer
+// shared by all workers, so the lines of different workers never interleave.
+// The buffer is flushed and the file synced to disk when a line is appended
+// `FLUSH_INTERVAL` after the last flush and by `flush` when a phase ends, so
+// a crash loses at most the lines of that interval. With `[storage]
+// sync_manifest=true` every line is synced before `append` returns instead,
+// at the cost of one `fsync` per line.
+use config_value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+lazy_static! {
+    /// Sync every line, `[storage] sync_manifest`.
+    static ref SYNC_EVERY_LINE: bool = config_value("storage", "sync_manifest").map_or(false, |v| v == "true");
+}
+
+/// Write the buffer of `writer` to the file and the file to disk.
+fn sync(writer: &mut BufWriter<File>) -> io::Result<()> {
+    writer.flush()?;
+    writer.get_ref().sync_data()
+}
+
+struct State {
+    /// Opened on the first line, and again after `close` or an error.
+    writer: Option<BufWriter<File>>,
+    flushed_at: Instant,
+}
+
+/// A file lines are appended to from any thread.
+pub struct AppendSink {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl AppendSink {
+    /// Append to `path`, which is only created with the first line.
+    pub fn new(path: PathBuf) -> AppendSink {
+        AppendSink {
+            path,
+            state: Mutex::new(State {
+                writer: None,
+                flushed_at: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn lock(&self) -> MutexGuard<State> {
+        // a panic of one worker must not stop the others from writing
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Append `line` and a newline.
+    pub fn append(&self, line: &str) -> io::Result<()> {
+        let mut state = self.lock();
+        let due = *SYNC_EVERY_LINE || state.flushed_at.elapsed() >= FLUSH_INTERVAL;
+        let res: io::Result<()> = (|| {
+            if state.writer.is_none() {
+                if let Some(parent) = self.path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                state.writer = Some(BufWriter::new(file));
+            }
+            let writer = state.writer.as_mut().unwrap();
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+            if due {
+                sync(writer)?;
+            }
+            Ok(())
+        })();
+        if due || res.is_err() {
+            state.flushed_at = Instant::now();
+        }
+        if res.is_err() {
+            // what the buffer holds is unknown, start over with a new one
+            state.writer = None;
+        }
+        res
+    }
+
+    /// Write the buffered lines to the file and sync it.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut state = self.lock();
+        state.flushed_at = Instant::now();
+        match state.writer {
+            Some(ref mut writer) => sync(writer),
+            None => Ok(()),
+        }
+    }
+
+    /// Flush and close the file, e.g. before replacing it; the next line
+    /// opens it again.
+    pub fn close(&self) -> io::Result<()> {
+        let mut state = self.lock();
+        match state.writer.take() {
+            Some(mut writer) => sync(&mut writer),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use test_dir;
+
+    #[test]
+    fn lines_of_parallel_workers_do_not_interleave() {
+        const THREADS: usize = 8;
+        const LINES: usize = 2_000;
+        // the folder of the file is only created with the first line
+        let path = test_dir("sink").join("lines").join("out.jsonl");
+        let sink = Arc::new(AppendSink::new(path.clone()));
+        let workers = (0..THREADS)
+            .map(|id| {
+                let sink = sink.clone();
+                thread::spawn(move || {
+                    for line in 0..LINES {
+                        let padding = "x".repeat(line % 300);
+                        let text = format!("{{\"thread\":{},\"line\":{},\"padding\":\"{}\"}}", id, line, padding);
+                        sink.append(&text).unwrap();
+                        if line % 500 == 0 {
+                            sink.flush().unwrap();
+                        }
+                    }
+                })
+            }).collect::<Vec<_>>();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        sink.close().unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let mut seen = vec![vec![false; LINES]; THREADS];
+        for line in text.lines() {
+            let value: ::serde_json::Value = ::serde_json::from_str(line).unwrap();
+            let thread = value["thread"].as_u64().unwrap() as usize;
+            let line = value["line"].as_u64().unwrap() as usize;
+            assert_eq!(value["padding"].as_str().unwrap().len(), line % 300);
+            assert!(!seen[thread][line], "line {} of thread {} twice", line, thread);
+            seen[thread][line] = true;
+        }
+        assert_eq!(text.lines().count(), THREADS * LINES);
+        assert!(text.ends_with('\n'));
+    }
+}
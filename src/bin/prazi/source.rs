@@ -0,0 +1,209 @@
+// Where the index and the `.crate` files are fetched from
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// A source replacement of crates.io in the user's cargo configuration
+// (`[source.crates-io] replace-with = "..."`) takes precedence, so the tool
+// fetches from the same mirror as cargo itself. Otherwise the `[crates]
+// index_url` and `mirror_url` keys of `conf.ini` apply, and finally crates.io.
+//
+// `--registry <name>` selects another registry instead, defined like in the
+// cargo configuration by the git URL of its `index` in a `[registry.<name>]`
+// section. Its `dl` is a download URL template as in the `config.json` of an
+// index, and defaults to the one of the index checkout as it does for cargo.
+//
+// The tests replace all of these with a registry they serve themselves,
+// whose index is cloned from a local git repository into a checkout of its
+// own, so neither the index path nor the download root are the configured
+// ones.
+use index::INDEX_URL;
+use paths;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use toml::Value;
+use {config_cargo_home, config_index_dir, config_value};
+
+static CRATES_ROOT: &str = "https://crates-io.s3-us-west-1.amazonaws.com/crates";
+
+/// Index and download locations of the registry the crates come from.
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// Git URL of the index.
+    pub index_url: String,
+    /// Download URL template, of `[registry.<name>] dl`.
+    dl: Option<String>,
+    /// Whether the source is a replacement from the cargo configuration or a
+    /// `--registry`, whose downloads are located through the `dl` key of the
+    /// index `config.json` unless `dl` is given.
+    dl_from_index: bool,
+    /// Root of the `{root}/{name}/{name}-{version}.crate` download layout.
+    mirror_url: Option<String>,
+}
+
+lazy_static! {
+    /// The name of the `--registry`, `None` for crates.io.
+    static ref REGISTRY: RwLock<Option<String>> = RwLock::new(None);
+    /// The git URL and the checkout of the index of a local registry.
+    static ref LOCAL: RwLock<Option<(String, String)>> = RwLock::new(None);
+    pub static ref SOURCE: Source = Source::resolve();
+}
+
+fn registry_section(name: &str) -> String {
+    format!("registry.{}", name)
+}
+
+/// Fetch the crates from the registry `[registry.<name>]` instead of
+/// crates.io. Has to be called before `SOURCE` is first used.
+pub fn select_registry(name: &str) {
+    let index = config_value(&registry_section(name), "index")
+        .unwrap_or_else(|| panic!("--registry {0} needs the index URL in [registry.{0}] index", name));
+    // the index is read from a git checkout
+    assert!(
+        !index.starts_with("sparse+"),
+        "[registry.{}] index: only git indexes are supported, got {}",
+        name,
+        index
+    );
+    *REGISTRY.write().unwrap() = Some(name.to_string());
+}
+
+/// Fetch the crates from a registry of this machine, whose index is cloned
+/// from `index_url` into `index_dir` and whose downloads are located through
+/// the `dl` of the index. Has to be called before `SOURCE` is first used.
+#[cfg(test)]
+pub fn select_local(index_url: &str, index_dir: &str) {
+    *LOCAL.write().unwrap() = Some((index_url.to_string(), index_dir.to_string()));
+}
+
+/// The index checkout of the local registry, if one is selected.
+pub fn local_index_dir() -> Option<String> {
+    LOCAL.read().unwrap().as_ref().map(|&(_, ref index_dir)| index_dir.clone())
+}
+
+/// The name of the registry selected with `--registry`, if any.
+pub fn registry() -> Option<String> {
+    REGISTRY.read().unwrap().clone()
+}
+
+/// The `[registry.<name>] index_path` checkout of the index of the
+/// `--registry`, by default next to `index_path` as `{index_path}-{name}`.
+pub fn registry_index_dir(name: &str, index_path: &str) -> String {
+    config_value(&registry_section(name), "index_path").unwrap_or_else(|| format!("{}-{}", index_path, name))
+}
+
+impl Source {
+    fn resolve() -> Source {
+        if let Some((index_url, _)) = LOCAL.read().unwrap().clone() {
+            return Source {
+                index_url,
+                dl: None,
+                dl_from_index: true,
+                mirror_url: None,
+            };
+        }
+        if let Some(name) = registry() {
+            let section = registry_section(&name);
+            message!("Using registry `{}`", name);
+            return Source {
+                index_url: config_value(&section, "index").unwrap(),
+                dl: config_value(&section, "dl"),
+                dl_from_index: true,
+                mirror_url: None,
+            };
+        }
+        if let Some((name, index_url)) = cargo_replacement() {
+            message!("Using crates.io replacement `{}` from the cargo configuration: {}", name, index_url);
+            return Source {
+                index_url,
+                dl: None,
+                dl_from_index: true,
+                mirror_url: None,
+            };
+        }
+        Source {
+            index_url: config_value("crates", "index_url").unwrap_or_else(|| INDEX_URL.to_string()),
+            dl: None,
+            dl_from_index: false,
+            mirror_url: config_value("crates", "mirror_url"),
+        }
+    }
+
+    /// Download URL of a `.crate` file.
+    pub fn crate_url(&self, name: &str, version: &str) -> String {
+        if let Some(ref dl) = self.dl {
+            return paths::dl_url(dl, name, version);
+        }
+        if self.dl_from_index {
+            if let Some(dl) = index_dl() {
+                return paths::dl_url(&dl, name, version);
+            }
+        }
+        let root = self.mirror_url.as_ref().map_or(CRATES_ROOT, |url| url.as_str());
+        paths::crate_url(root, name, version)
+    }
+}
+
+/// The `dl` key of the `config.json` of the local index checkout.
+fn index_dl() -> Option<String> {
+    let text = fs::read_to_string(Path::new(&config_index_dir()).join("config.json")).ok()?;
+    let config: ::serde_json::Value = ::serde_json::from_str(&text).ok()?;
+    config.get("dl")?.as_str().map(|dl| dl.to_string())
+}
+
+/// Cargo configuration files in order of precedence: the current directory
+/// and its ancestors, then `CARGO_HOME`.
+fn cargo_config_files() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Ok(cwd) = env::current_dir() {
+        dirs.extend(cwd.ancestors().map(|dir| dir.join(".cargo")));
+    }
+    let cargo_home = config_cargo_home()
+        .or_else(|| env::var_os("CARGO_HOME").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    dirs.extend(cargo_home);
+    dirs.iter()
+        .flat_map(|dir| vec![dir.join("config.toml"), dir.join("config")])
+        .filter(|file| file.is_file())
+        .collect()
+}
+
+/// Name and index URL of the source replacing crates.io, if any.
+fn cargo_replacement() -> Option<(String, String)> {
+    let configs = cargo_config_files()
+        .iter()
+        .filter_map(|file| {
+            let text = fs::read_to_string(file).ok()?;
+            match text.parse::<Value>() {
+                Ok(config) => Some(config),
+                Err(error) => {
+                    eprintln!("Ignoring unparseable cargo configuration {:?}: {}", file, error);
+                    None
+                }
+            }
+        }).collect::<Vec<_>>();
+    let source = |name: &str, key: &str| {
+        configs.iter().filter_map(|config| {
+            config
+                .get("source")?
+                .get(name)?
+                .get(key)?
+                .as_str()
+                .map(|v| v.to_string())
+        }).next()
+    };
+    let name = source("crates-io", "replace-with")?;
+    match source(&name, "registry") {
+        Some(url) => Some((name, url)),
+        None => {
+            eprintln!(
+                "Ignoring crates.io replacement `{}`: only `registry` sources are supported",
+                name
+            );
+            None
+        }
+    }
+}
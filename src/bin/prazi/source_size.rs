@@ -0,0 +1,93 @@
+// Size and files of the extracted sources of a crate, for `--max-source-bytes`
+// and `analyze-sources`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// A few crates ship megabytes of generated or vendored sources and take
+// hours to build. Their size is known right after the download, so they can
+// be left out before cargo ever runs.
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use swhid;
+
+/// Parse a size like `5000000`, `512K`, `50M` or `2G` (binary multiples).
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, shift) = match text.char_indices().last() {
+        Some((i, c)) if c.is_alphabetic() => {
+            let shift = match c.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                _ => return Err(format!("invalid size unit: {}", text)),
+            };
+            (&text[..i], shift)
+        }
+        _ => (text, 0),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("invalid size: {}", text))?;
+    Ok(number << shift)
+}
+
+/// Bytes of the file or, for a folder, of the files below it. Subfolders
+/// are walked in parallel and symlinks are not followed.
+fn entry_bytes(entry: &fs::DirEntry) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(entry.path())?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let entries = fs::read_dir(entry.path())?.collect::<io::Result<Vec<_>>>()?;
+    entries.par_iter().map(entry_bytes).sum()
+}
+
+/// The files of `entry`, itself or the ones below the folder, as paths
+/// below `prefix`. Symlinks are not followed and left out.
+fn entry_files(entry: &fs::DirEntry, prefix: &Path) -> io::Result<Vec<PathBuf>> {
+    let path = prefix.join(entry.file_name());
+    let metadata = fs::symlink_metadata(entry.path())?;
+    if metadata.file_type().is_symlink() {
+        return Ok(Vec::new());
+    }
+    if !metadata.is_dir() {
+        return Ok(vec![path]);
+    }
+    let entries = fs::read_dir(entry.path())?.collect::<io::Result<Vec<_>>>()?;
+    let files = entries
+        .par_iter()
+        .map(|entry| entry_files(entry, &path))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(files.into_iter().flat_map(|files| files).collect())
+}
+
+/// The files of the sources extracted into `dir`, relative to it, leaving
+/// out the same files as `source_bytes`.
+pub fn source_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    let files = entries
+        .par_iter()
+        .filter(|entry| !swhid::is_artifact(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry_files(entry, Path::new("")))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(files.into_iter().flat_map(|files| files).collect())
+}
+
+/// Bytes of the sources extracted into `dir`, leaving out the build outputs
+/// and the files the tool writes next to them.
+pub fn source_bytes(dir: &Path) -> io::Result<u64> {
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries
+        .par_iter()
+        .filter(|entry| !swhid::is_artifact(&entry.file_name().to_string_lossy()))
+        .map(entry_bytes)
+        .sum()
+}
+
+/// Bytes of all files below `dir`, build outputs included.
+pub fn tree_bytes(dir: &Path) -> io::Result<u64> {
+    let entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.par_iter().map(entry_bytes).sum()
+}
@@ -0,0 +1,185 @@
+// Lines and kinds of the files of the extracted sources, for `analyze-sources`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Every file is counted once under its extension and once under its kind,
+// the first that applies of: `vendored` (below a `vendor` or `third_party`
+// folder), `build_script`, `generated` (marked as such in its first lines),
+// `rust`, `c` (C, C++ and assembly), `binary` (not UTF-8) and `other`.
+use manifest;
+use rayon::prelude::*;
+use source_size;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Folders of sources copied from other projects.
+const VENDOR_DIRS: &[&str] = &["vendor", "vendored", "third_party", "third-party"];
+
+const C_EXTENSIONS: &[&str] = &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx", "s", "asm"];
+
+/// Markers of generated files, looked for in lower case.
+const GENERATED_MARKERS: &[&str] = &["@generated", "do not edit", "automatically generated", "auto-generated"];
+
+/// Lines of a file searched for `GENERATED_MARKERS`.
+const GENERATED_HEADER_LINES: usize = 10;
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Count {
+    pub files: usize,
+    pub lines: usize,
+    /// Lines that are empty or only whitespace.
+    pub blank: usize,
+    pub bytes: u64,
+}
+
+impl Count {
+    fn add(&mut self, other: &Count) {
+        self.files += other.files;
+        self.lines += other.lines;
+        self.blank += other.blank;
+        self.bytes += other.bytes;
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct SourceStats {
+    pub total: Count,
+    pub by_kind: BTreeMap<String, Count>,
+    /// By lower-case file extension, `""` for files without one.
+    pub by_extension: BTreeMap<String, Count>,
+}
+
+impl SourceStats {
+    pub fn add(&mut self, other: &SourceStats) {
+        self.total.add(&other.total);
+        for (kind, count) in &other.by_kind {
+            self.by_kind.entry(kind.clone()).or_insert_with(Count::default).add(count);
+        }
+        for (extension, count) in &other.by_extension {
+            self.by_extension.entry(extension.clone()).or_insert_with(Count::default).add(count);
+        }
+    }
+}
+
+fn is_generated(text: &str) -> bool {
+    text.lines().take(GENERATED_HEADER_LINES).any(|line| {
+        let line = line.to_lowercase();
+        GENERATED_MARKERS.iter().any(|marker| line.contains(marker))
+    })
+}
+
+/// The kind and count of the file `path` of the sources in `dir`.
+fn file_count(
+    dir: &Path,
+    path: &Path,
+    build_script: Option<&Path>,
+    extension: &str,
+) -> io::Result<(&'static str, Count)> {
+    let content = fs::read(dir.join(path))?;
+    let mut count = Count {
+        files: 1,
+        bytes: content.len() as u64,
+        ..Count::default()
+    };
+    let text = if content.contains(&0) {
+        None
+    } else {
+        String::from_utf8(content).ok()
+    };
+    if let Some(ref text) = text {
+        for line in text.lines() {
+            count.lines += 1;
+            if line.trim().is_empty() {
+                count.blank += 1;
+            }
+        }
+    }
+    let vendored = path
+        .parent()
+        .map_or(false, |parent| parent.iter().any(|dir| VENDOR_DIRS.contains(&&*dir.to_string_lossy())));
+    let kind = match text {
+        _ if vendored => "vendored",
+        _ if build_script.map_or(false, |script| script == path) => "build_script",
+        Some(ref text) if is_generated(text) => "generated",
+        Some(_) if extension == "rs" => "rust",
+        Some(_) if C_EXTENSIONS.contains(&extension) => "c",
+        None => "binary",
+        Some(_) => "other",
+    };
+    Ok((kind, count))
+}
+
+/// Count the lines of the sources extracted into `dir` by file kind and
+/// extension.
+pub fn stats(dir: &Path) -> io::Result<SourceStats> {
+    let build_script = manifest::read(dir)
+        .ok()
+        .and_then(|manifest| manifest::build_script(dir, &manifest));
+    let counts = source_size::source_files(dir)?
+        .par_iter()
+        .map(|path| {
+            let extension = path
+                .extension()
+                .map_or(String::new(), |extension| extension.to_string_lossy().to_lowercase());
+            let (kind, count) = file_count(dir, path, build_script.as_ref().map(PathBuf::as_path), &extension)?;
+            Ok((kind, extension, count))
+        }).collect::<io::Result<Vec<_>>>()?;
+    let mut res = SourceStats::default();
+    for (kind, extension, count) in counts {
+        res.total.add(&count);
+        res.by_kind.entry(kind.to_string()).or_insert_with(Count::default).add(&count);
+        res.by_extension.entry(extension).or_insert_with(Count::default).add(&count);
+    }
+    Ok(res)
+}
+
+/// Lines of `text` with Rust code besides comments, following nested block
+/// comments across lines. Comment markers inside string literals are taken for
+/// comments.
+fn code_lines(text: &str) -> usize {
+    let mut depth = 0;
+    let mut res = 0;
+    for line in text.lines() {
+        let bytes = line.as_bytes();
+        let mut code = false;
+        let mut i = 0;
+        while i < bytes.len() {
+            let pair = &bytes[i..bytes.len().min(i + 2)];
+            if pair == b"/*" {
+                depth += 1;
+                i += 2;
+            } else if depth > 0 && pair == b"*/" {
+                depth -= 1;
+                i += 2;
+            } else if depth == 0 && pair == b"//" {
+                break;
+            } else {
+                code = code || (depth == 0 && !bytes[i].is_ascii_whitespace());
+                i += 1;
+            }
+        }
+        if code {
+            res += 1;
+        }
+    }
+    res
+}
+
+/// Non-blank, non-comment lines of the `.rs` files below `src` of the sources
+/// extracted into `dir`; files that are not UTF-8 are left out.
+pub fn rust_loc(dir: &Path) -> io::Result<usize> {
+    let files = source_size::source_files(dir)?
+        .into_iter()
+        .filter(|path| path.starts_with("src") && path.extension().map_or(false, |extension| extension == "rs"))
+        .collect::<Vec<_>>();
+    files
+        .par_iter()
+        .map(|path| {
+            let content = fs::read(dir.join(path))?;
+            Ok(String::from_utf8(content).map_or(0, |text| code_lines(&text)))
+        }).sum()
+}
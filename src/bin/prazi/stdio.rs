@@ -0,0 +1,36 @@
+// Files outputs are written to and inputs read from, `-` for stdout/stdin
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Line-oriented outputs are written as they are produced, so piping them
+// into the next stage needs no local copy, e.g.
+// `prazi export-index --out - | prazi unused-deps --deps - --graph ...`.
+// Commands writing to stdout print their messages on stderr.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Does `path` stand for stdin or stdout?
+pub fn is_std(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// A buffered writer to the file `path`, or to stdout for `-`.
+pub fn create(path: &Path) -> io::Result<Box<Write>> {
+    if is_std(path) {
+        Ok(Box::new(BufWriter::new(io::stdout())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// A buffered reader of the file `path`, or of stdin for `-`.
+pub fn open(path: &Path) -> io::Result<Box<BufRead>> {
+    if is_std(path) {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
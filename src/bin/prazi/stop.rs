@@ -0,0 +1,157 @@
+// Graceful stopping on Ctrl-C, when the runtime limit is reached, the disk is
+// full or, with `--fail-fast`, a crate fails
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// All paths only stop the scheduling of new crates: work in flight finishes,
+// and the phases then write their reports as usual, so the run ends in a
+// consistent state that `--resume` continues from.
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Exit code of a run stopped by `--max-runtime`.
+pub const EXIT_TIME_LIMITED: i32 = 3;
+/// Exit code of a run stopped because the storage volume is full.
+pub const EXIT_OUT_OF_DISK: i32 = 4;
+/// Exit code of a run stopped by a failure with `--fail-fast`.
+pub const EXIT_FAILED: i32 = 5;
+/// Exit code of a run stopped by Ctrl-C.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+const RUNNING: usize = 0;
+const TIME_LIMITED: usize = 1;
+const INTERRUPTED: usize = 2;
+const OUT_OF_DISK: usize = 3;
+const FAILED: usize = 4;
+
+static STATE: AtomicUsize = AtomicUsize::new(RUNNING);
+static HANDLER_INSTALLED: AtomicBool = AtomicBool::new(false);
+static FAIL_FAST: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref DEADLINE: Mutex<Option<Instant>> = Mutex::new(None);
+}
+
+/// Parse a duration like `90`, `250ms`, `30s`, `15m`, `6h` or `2d`.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if text.ends_with("ms") {
+        let millis: u64 = text[..text.len() - 2]
+            .parse()
+            .map_err(|_| format!("invalid duration: {}", text))?;
+        return Ok(Duration::from_millis(millis));
+    }
+    let (number, unit) = match text.char_indices().last() {
+        Some((i, c)) if c.is_alphabetic() => (&text[..i], c),
+        _ => (text, 's'),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration: {}", text))?;
+    let secs = match unit {
+        's' => number,
+        'm' => number * 60,
+        'h' => number * 60 * 60,
+        'd' => number * 60 * 60 * 24,
+        _ => return Err(format!("invalid duration unit: {}", text)),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+/// Install the Ctrl-C handler and start the runtime limit, if any.
+///
+/// A second Ctrl-C exits immediately.
+pub fn install(max_runtime: Option<Duration>) {
+    if let Some(max_runtime) = max_runtime {
+        *DEADLINE.lock().unwrap() = Some(Instant::now() + max_runtime);
+    }
+    if !HANDLER_INSTALLED.swap(true, Ordering::SeqCst) {
+        let res = ::ctrlc::set_handler(|| {
+            if STATE.swap(INTERRUPTED, Ordering::SeqCst) == INTERRUPTED {
+                process::exit(EXIT_INTERRUPTED);
+            }
+            eprintln!("Interrupted, finishing work in flight (Ctrl-C again to abort)");
+        });
+        if let Err(error) = res {
+            eprintln!("Error installing Ctrl-C handler: {:?}", error);
+        }
+    }
+}
+
+/// Should no new crate be started?
+pub fn should_stop() -> bool {
+    if STATE.load(Ordering::SeqCst) != RUNNING {
+        return true;
+    }
+    let expired = DEADLINE
+        .lock()
+        .unwrap()
+        .map_or(false, |deadline| Instant::now() >= deadline);
+    if expired && STATE
+        .compare_exchange(RUNNING, TIME_LIMITED, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        eprintln!("Runtime limit reached, finishing work in flight");
+    }
+    expired
+}
+
+/// Stop the run because the storage volume is full.
+pub fn out_of_disk() {
+    STATE.store(OUT_OF_DISK, Ordering::SeqCst);
+}
+
+/// Stop the run at the first failure from now on.
+pub fn fail_fast() {
+    FAIL_FAST.store(true, Ordering::SeqCst);
+}
+
+/// A crate failed: stops the run with `--fail-fast`.
+pub fn failure() {
+    if FAIL_FAST.load(Ordering::SeqCst)
+        && STATE
+            .compare_exchange(RUNNING, FAILED, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    {
+        eprintln!("Failure with --fail-fast, finishing work in flight");
+    }
+}
+
+/// Exit code for the run if it was stopped early.
+pub fn exit_code() -> Option<i32> {
+    match STATE.load(Ordering::SeqCst) {
+        TIME_LIMITED => Some(EXIT_TIME_LIMITED),
+        INTERRUPTED => Some(EXIT_INTERRUPTED),
+        OUT_OF_DISK => Some(EXIT_OUT_OF_DISK),
+        FAILED => Some(EXIT_FAILED),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("6h"), Ok(Duration::from_secs(6 * 60 * 60)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(2 * 24 * 60 * 60)));
+        assert_eq!(parse_duration(" 0m\n"), Ok(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn refuses_malformed_durations() {
+        for text in &["", "m", "ms", "1.5h", "-1s", "10 m", "5w", "5µ", "h6"] {
+            assert!(parse_duration(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(parse_duration("5w"), Err("invalid duration unit: 5w".to_string()));
+    }
+}
@@ -0,0 +1,140 @@
+// Quiet mode and the machine-readable summary line of a phase
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Every phase ends with exactly one line of the form
+//
+//     PRAZI_SUMMARY phase=download total=N ok=M failed=K bytes=B seconds=S
+//
+// on stdout. For `callgraph`, `empty=E` follows `ok`: the call graphs among
+// the `ok` ones that have barely any edges. The summary line is printed even
+// with `--quiet`, which silences the per-crate messages printed through
+// `progress!`. Errors always go to stderr.
+//
+// With `--json-lines`, stdout only carries JSON objects, one per line: the
+// outcome of every crate as soon as it is known, and the summary in the same
+// form at the end. The per-crate messages are silenced as with `--quiet`, and
+// the other messages printed through `message!` go to stderr.
+//
+// A run in which a summary counted failed crates, or whose subcommand failed
+// as a whole, exits with `[report] failed_exit_code`, 1 by default, unless it
+// was stopped early.
+use report::Status;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use {config_value, PraziCrate};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static JSON_LINES: AtomicBool = AtomicBool::new(false);
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref STARTED: Instant = Instant::now();
+    /// Exit code of a run with failed crates, or that failed as a whole, 0 to
+    /// exit successfully anyway.
+    static ref FAILED_EXIT_CODE: i32 = config_value("report", "failed_exit_code")
+        .map_or(1, |v| v.parse().expect("invalid [report] failed_exit_code"));
+}
+
+/// Silence the per-crate messages, or switch stdout to JSON lines, and start
+/// the clock of the phase.
+pub fn start(quiet: bool, json_lines: bool) {
+    QUIET.store(quiet || json_lines, Ordering::SeqCst);
+    JSON_LINES.store(json_lines, Ordering::SeqCst);
+    ::lazy_static::initialize(&STARTED);
+}
+
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::SeqCst)
+}
+
+pub fn json_lines() -> bool {
+    JSON_LINES.load(Ordering::SeqCst)
+}
+
+/// The JSON line of the outcome of a crate.
+#[derive(Serialize)]
+struct Outcome<'a> {
+    #[serde(rename = "crate")]
+    krate: &'a str,
+    version: &'a str,
+    phase: &'a str,
+    status: Status,
+}
+
+/// Print the outcome `status` of `krate` in `phase` as a JSON line, with
+/// `--json-lines`.
+pub fn result(krate: &PraziCrate, phase: &str, status: Status) {
+    if json_lines() {
+        let line = Outcome {
+            krate: &krate.name,
+            version: &krate.version,
+            phase,
+            status,
+        };
+        println!("{}", ::serde_json::to_string(&line).unwrap());
+    }
+}
+
+/// The JSON line of the summary of a phase.
+#[derive(Serialize)]
+struct Summary<'a> {
+    phase: &'a str,
+    summary: bool,
+    total: usize,
+    ok: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    empty: Option<usize>,
+    failed: usize,
+    bytes: usize,
+    seconds: u64,
+}
+
+/// Print the summary line of `phase`, with the number of `empty` results
+/// among the `ok` ones if the phase tells them apart.
+pub fn print(phase: &str, total: usize, ok: usize, empty: Option<usize>, failed: usize, bytes: usize) {
+    if failed > 0 {
+        FAILED.store(true, Ordering::SeqCst);
+    }
+    if json_lines() {
+        let line = Summary {
+            phase,
+            summary: true,
+            total,
+            ok,
+            empty,
+            failed,
+            bytes,
+            seconds: STARTED.elapsed().as_secs(),
+        };
+        println!("{}", ::serde_json::to_string(&line).unwrap());
+        return;
+    }
+    println!(
+        "PRAZI_SUMMARY phase={} total={} ok={}{} failed={} bytes={} seconds={}",
+        phase,
+        total,
+        ok,
+        empty.map_or(String::new(), |empty| format!(" empty={}", empty)),
+        failed,
+        bytes,
+        STARTED.elapsed().as_secs()
+    );
+}
+
+/// Fail the run as a whole, for a subcommand that could not finish.
+pub fn fail() {
+    FAILED.store(true, Ordering::SeqCst);
+}
+
+/// Exit code for the run if a summary counted failed crates or the run
+/// failed.
+pub fn exit_code() -> Option<i32> {
+    if FAILED.load(Ordering::SeqCst) && *FAILED_EXIT_CODE != 0 {
+        Some(*FAILED_EXIT_CODE)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,86 @@
+// Content-addressed identifiers of crate source trees
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The identifiers are Software Heritage directory identifiers
+// (`swh:1:dir:<hex>`), i.e. the SHA-1 of the git tree object of the directory,
+// so they can be joined with the Software Heritage archive and with git.
+use sha1::Sha1;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Entries of a crate folder that are not part of its published sources.
+const ARTIFACTS: &[&str] = &["target", "graphs", "runs", "mir", "build.log", "Cargo.lock", "Cargo.toml.rewrite.diff"];
+
+/// Whether a top-level entry of a crate folder was written by a build or by
+/// the tool rather than extracted from the `.crate` file.
+pub fn is_artifact(name: &str) -> bool {
+    ARTIFACTS.contains(&name) || name.starts_with("prazi-") || name.starts_with("callgraph")
+}
+
+fn object_id(kind: &str, content: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", kind, content.len()).as_bytes());
+    hasher.update(content);
+    hasher.digest().bytes()
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Git tree id of `dir`, skipping the `filter`ed top-level entries.
+fn tree_id(dir: &Path, filter: &Fn(&str) -> bool) -> io::Result<[u8; 20]> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if filter(&name) {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(entry.path())?;
+        let (mode, id) = if metadata.file_type().is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            ("120000", object_id("blob", target.to_string_lossy().as_bytes()))
+        } else if metadata.is_dir() {
+            ("40000", tree_id(&entry.path(), &|_| false)?)
+        } else {
+            let mode = if is_executable(&metadata) { "100755" } else { "100644" };
+            (mode, object_id("blob", &fs::read(entry.path())?))
+        };
+        // git sorts directories as if their name ended with a slash
+        let key = if mode == "40000" { format!("{}/", name) } else { name.clone() };
+        entries.push((key, mode, name, id));
+    }
+    entries.sort();
+    let mut tree = Vec::new();
+    for (_, mode, name, id) in entries {
+        tree.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+        tree.extend_from_slice(&id);
+    }
+    Ok(object_id("tree", &tree))
+}
+
+/// The `swh:1:dir:` identifier of the tree at `dir`. With
+/// `skip_artifacts`, the build outputs and files the tool adds next to the
+/// sources are left out, so that the identifier of a processed crate matches
+/// the one of its freshly extracted sources.
+pub fn source_tree(dir: &Path, skip_artifacts: bool) -> io::Result<String> {
+    let id = if skip_artifacts {
+        tree_id(dir, &is_artifact)?
+    } else {
+        tree_id(dir, &|_| false)?
+    };
+    let hex = id.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    Ok(format!("swh:1:dir:{}", hex))
+}
@@ -0,0 +1,79 @@
+// The targets of the crates that are built, for `--bins` and `--bin`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Cargo builds the library and the binaries of a crate unless told
+// otherwise. rustc names the bitcode of a target after the target, with `-`
+// replaced by `_`, followed by `-` and a hash, so the bitcode of a single
+// binary is told apart by its file name; a library of the same name shares
+// the prefix.
+use std::path::Path;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Targets {
+    /// What `cargo build` builds by default.
+    Default,
+    /// All binaries, `--bins`.
+    Bins,
+    /// The binary of that name, `--bin`.
+    Bin(String),
+}
+
+lazy_static! {
+    static ref SELECTED: RwLock<Targets> = RwLock::new(Targets::Default);
+}
+
+impl Targets {
+    pub fn name(&self) -> String {
+        match *self {
+            Targets::Default => "default".to_string(),
+            Targets::Bins => "bins".to_string(),
+            Targets::Bin(ref name) => format!("bin:{}", name),
+        }
+    }
+
+    /// Arguments of `cargo build` selecting the targets.
+    pub fn cargo_args(&self) -> Vec<String> {
+        match *self {
+            Targets::Default => Vec::new(),
+            Targets::Bins => vec!["--bins".to_string()],
+            Targets::Bin(ref name) => vec!["--bin".to_string(), name.clone()],
+        }
+    }
+
+    /// Whether the targets are only binaries.
+    pub fn only_bins(&self) -> bool {
+        *self != Targets::Default
+    }
+
+    /// Whether the bitcode file `path` may be of the targets.
+    pub fn has_bitcode(&self, path: &Path) -> bool {
+        match *self {
+            Targets::Bin(ref name) => path
+                .file_name()
+                .map_or(false, |file| file.to_string_lossy().starts_with(&format!("{}-", name.replace('-', "_")))),
+            _ => true,
+        }
+    }
+
+    /// The kinds of the `kinds` of targets of a crate these targets build.
+    pub fn built_kinds(&self, kinds: &[&'static str]) -> Vec<&'static str> {
+        kinds
+            .iter()
+            .cloned()
+            .filter(|&kind| !self.only_bins() || kind == "bin")
+            .collect()
+    }
+}
+
+/// Build and look for the bitcode of `targets` from now on.
+pub fn select(targets: Targets) {
+    *SELECTED.write().unwrap() = targets;
+}
+
+pub fn selected() -> Targets {
+    SELECTED.read().unwrap().clone()
+}
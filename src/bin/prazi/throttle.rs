@@ -0,0 +1,104 @@
+// Adaptive scheduling of crates by system load and available memory
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// With `--adaptive`, a worker about to start a crate first checks the load
+// average per CPU against `[throttle] max_load` and the available memory
+// against `min_available_memory`. Above a mark it waits, so every worker that
+// finishes a crate while the system is overloaded lowers the concurrency by
+// one; one worker always keeps going. Once the system is below the marks with
+// a margin, the waiting workers resume one per `RAMP_INTERVAL`. Load and memory
+// are read from `/proc`, elsewhere nothing is throttled.
+use config_value;
+use source_size;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use stop;
+
+/// How often waiting workers check the system again.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Time between two waiting workers resuming.
+const RAMP_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Share of the marks the system has to be below for workers to resume, so
+/// that they do not resume and stop again with every sample.
+const MARGIN: f64 = 0.9;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static WAITING: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// Load average per CPU above which no crates are started.
+    static ref MAX_LOAD: f64 = config_value("throttle", "max_load")
+        .map_or(1.0, |v| v.parse().expect("invalid [throttle] max_load"));
+    /// Available memory below which no crates are started.
+    static ref MIN_AVAILABLE_MEMORY: u64 = config_value("throttle", "min_available_memory")
+        .map_or(1 << 30, |v| source_size::parse_size(&v).expect("invalid [throttle] min_available_memory"));
+    static ref RESUMED_AT: Mutex<Instant> = Mutex::new(Instant::now());
+}
+
+/// Throttle the scheduling of crates from now on.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// The one-minute load average per CPU.
+fn load_per_cpu() -> Option<f64> {
+    let loadavg = fs::read_to_string("/proc/loadavg").ok()?;
+    let load: f64 = loadavg.split_whitespace().next()?.parse().ok()?;
+    Some(load / ::num_cpus::get() as f64)
+}
+
+/// Bytes of memory available without swapping.
+fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+/// Why the system is over the marks scaled by `margin`, if it is.
+fn pressure(margin: f64) -> Option<String> {
+    if let Some(load) = load_per_cpu() {
+        if load > *MAX_LOAD * margin {
+            return Some(format!("load {:.2} per CPU", load));
+        }
+    }
+    if let Some(available) = available_memory() {
+        if (available as f64) < *MIN_AVAILABLE_MEMORY as f64 / margin {
+            return Some(format!("{} MiB available", available / (1024 * 1024)));
+        }
+    }
+    None
+}
+
+/// Whether a worker is to start its next crate: waits while the system is
+/// overloaded with `--adaptive`, and is false once the run stops.
+pub fn admit() -> bool {
+    if ENABLED.load(Ordering::SeqCst) && !stop::should_stop() {
+        if let Some(reason) = pressure(1.0) {
+            let waiting = WAITING.fetch_add(1, Ordering::SeqCst) + 1;
+            if waiting < ::rayon::current_num_threads() {
+                progress!("{}, {} workers waiting", reason, waiting);
+                while !stop::should_stop() {
+                    thread::sleep(SAMPLE_INTERVAL);
+                    if pressure(MARGIN).is_none() {
+                        let mut resumed_at = RESUMED_AT.lock().unwrap();
+                        if resumed_at.elapsed() >= RAMP_INTERVAL {
+                            *resumed_at = Instant::now();
+                            break;
+                        }
+                    }
+                }
+            }
+            WAITING.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+    !stop::should_stop()
+}
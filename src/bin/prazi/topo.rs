@@ -0,0 +1,116 @@
+// Dependency-first build order, for `build-crates --topo-order`
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// The crates are put in waves: a crate comes after every crate of the list
+// it depends on by name, so all versions of a dependency are built before its
+// dependents. The crates of a wave do not depend on each other. When only
+// crates of a cycle are left, the one waiting for the fewest others is put in
+// a wave of its own as if it had no dependencies, which breaks the cycle.
+use std::collections::{BTreeSet, HashMap};
+use std::mem;
+
+#[derive(Debug, Default)]
+pub struct Order {
+    /// Indices into the list, wave by wave.
+    pub waves: Vec<Vec<usize>>,
+    /// The crates whose dependencies were ignored to break a cycle.
+    pub broken: Vec<usize>,
+}
+
+/// The order of the crates `names`, where `dependencies[i]` are the names the
+/// crate `names[i]` depends on.
+pub fn order(names: &[&str], dependencies: &[BTreeSet<String>]) -> Order {
+    let mut by_name: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, name) in names.iter().enumerate() {
+        by_name.entry(name).or_insert_with(Vec::new).push(i);
+    }
+    let mut pending = vec![0; names.len()];
+    let mut dependents = vec![Vec::new(); names.len()];
+    for (i, deps) in dependencies.iter().enumerate() {
+        // versions of the same crate do not wait for each other
+        for dep in deps.iter().filter(|dep| dep.as_str() != names[i]) {
+            for &j in by_name.get(dep.as_str()).into_iter().flatten() {
+                pending[i] += 1;
+                dependents[j].push(i);
+            }
+        }
+    }
+    let mut res = Order::default();
+    let mut done = vec![false; names.len()];
+    let mut ready = (0..names.len()).filter(|&i| pending[i] == 0).collect::<Vec<_>>();
+    let mut left = names.len();
+    while left > 0 {
+        if ready.is_empty() {
+            let cut = (0..names.len())
+                .filter(|&i| !done[i])
+                .min_by_key(|&i| pending[i])
+                .unwrap();
+            res.broken.push(cut);
+            ready.push(cut);
+        }
+        let mut wave = mem::replace(&mut ready, Vec::new());
+        wave.sort();
+        for &i in &wave {
+            done[i] = true;
+        }
+        left -= wave.len();
+        for &i in &wave {
+            for &dependent in &dependents[i] {
+                if !done[dependent] {
+                    pending[dependent] -= 1;
+                    if pending[dependent] == 0 {
+                        ready.push(dependent);
+                    }
+                }
+            }
+        }
+        res.waves.push(wave);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deps(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn a_chain_is_built_dependencies_first() {
+        // c depends on b, b on a, whatever the order of the list
+        let order = order(&["c", "a", "b"], &[deps(&["b"]), deps(&[]), deps(&["a", "libc"])]);
+        assert_eq!(order.waves, vec![vec![1], vec![2], vec![0]]);
+        assert!(order.broken.is_empty());
+    }
+
+    #[test]
+    fn all_versions_of_a_crate_come_before_its_dependents() {
+        // the second serde depends on serde, which it does not wait for
+        let order = order(
+            &["app", "serde", "serde", "log"],
+            &[deps(&["serde", "log"]), deps(&[]), deps(&["serde"]), deps(&[])],
+        );
+        assert_eq!(order.waves, vec![vec![1, 2, 3], vec![0]]);
+        assert!(order.broken.is_empty());
+    }
+
+    #[test]
+    fn a_cycle_is_broken_at_one_crate() {
+        // a and b depend on each other, c on both
+        let order = order(&["a", "b", "c"], &[deps(&["b"]), deps(&["a"]), deps(&["a", "b"])]);
+        assert_eq!(order.broken, vec![0]);
+        assert_eq!(order.waves, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn an_empty_list_has_no_waves() {
+        let order = order(&[], &[]);
+        assert!(order.waves.is_empty());
+        assert!(order.broken.is_empty());
+    }
+}
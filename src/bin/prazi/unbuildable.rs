@@ -0,0 +1,247 @@
+// Crates that build with none of the toolchains they were attempted with
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Every `build-crates` run also writes its report to
+// `reports/build-{toolchain}.json`, so runs with different toolchains (e.g.
+// stable, then nightly) leave one report each, which are merged here.
+// `reclassify` applies the categories to the failures of existing reports
+// again, after they changed.
+use disk;
+use report::{self, Entry, Envelope, Status};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use PraziCrate;
+
+/// Errors in the output of a failed `cargo build` identifying its cause, most
+/// specific first.
+const CATEGORIES: &[(&str, &[&str])] = &[
+    ("out-of-disk", disk::FULL_MESSAGES),
+    (
+        "lockfile-outdated",
+        &["needs to be updated but --locked was passed", "needs to be updated but --frozen was passed"],
+    ),
+    (
+        "dependency-resolution",
+        &["failed to select a version", "no matching package", "failed to load source"],
+    ),
+    (
+        "network",
+        &["failed to download", "failed to fetch", "Couldn't resolve host", "spurious network error"],
+    ),
+    (
+        "build-script",
+        &["failed to run custom build command"],
+    ),
+    (
+        "native-library",
+        &["linking with `cc` failed", "could not find native static library", "pkg-config"],
+    ),
+    (
+        "unstable-feature",
+        &[
+            "error[E0554]",
+            "error[E0658]",
+            "is unstable",
+            "may not be used on the stable release channel",
+            "use of unstable library feature",
+            "is only available on nightly",
+        ],
+    ),
+    (
+        "manifest",
+        &["failed to parse manifest", "could not find `Cargo.toml`"],
+    ),
+    ("compile-error", &["error[E", "could not compile"]),
+];
+
+/// The cause of a failed build from its `stderr`, `other` when unknown.
+pub fn categorize(stderr: &str) -> &'static str {
+    CATEGORIES
+        .iter()
+        .find(|&&(_, patterns)| patterns.iter().any(|pattern| stderr.contains(pattern)))
+        .map_or("other", |&(category, _)| category)
+}
+
+/// The features a failed build used from `#![feature(...)]` attributes
+/// quoted in its output and from uses of unstable library features.
+pub fn nightly_features(stderr: &str) -> BTreeSet<String> {
+    let mut features = BTreeSet::new();
+    for line in stderr.lines() {
+        if let Some(start) = line.find("#![feature(") {
+            let rest = &line[start + "#![feature(".len()..];
+            if let Some(end) = rest.find(')') {
+                features.extend(
+                    rest[..end]
+                        .split(',')
+                        .map(|feature| feature.trim())
+                        .filter(|feature| !feature.is_empty())
+                        .map(|feature| feature.to_string()),
+                );
+            }
+        }
+        // older compilers quote the feature with '', newer ones with ``
+        if let Some(start) = line.find("use of unstable library feature ") {
+            let rest = &line[start + "use of unstable library feature ".len()..];
+            let feature = rest
+                .trim_start_matches(|c| c == '\'' || c == '`')
+                .split(|c| c == '\'' || c == '`')
+                .next()
+                .unwrap_or("");
+            if !feature.is_empty() {
+                features.insert(feature.to_string());
+            }
+        }
+    }
+    features
+}
+
+/// Set the `error_category` of the failed build `entry` from its output, and
+/// tag it with `needs_nightly` and its `nightly_features` when an unstable
+/// feature made it fail.
+pub fn categorize_entry(entry: &mut Entry, stderr: &str) {
+    let category = categorize(stderr);
+    entry.details.insert("error_category".to_string(), category.into());
+    if category == "unstable-feature" {
+        entry.details.insert("needs_nightly".to_string(), true.into());
+        let features = nightly_features(stderr);
+        if !features.is_empty() {
+            entry
+                .details
+                .insert("nightly_features".to_string(), ::serde_json::to_value(&features).unwrap());
+        }
+    } else {
+        entry.details.remove("needs_nightly");
+        entry.details.remove("nightly_features");
+    }
+}
+
+/// A crate that failed to build with every toolchain it was attempted with.
+#[derive(Debug, Serialize)]
+pub struct Unbuildable {
+    pub name: String,
+    pub version: String,
+    /// Error category of the failed build by toolchain.
+    pub attempts: BTreeMap<String, String>,
+    /// The distinct categories of all attempts.
+    pub categories: BTreeSet<String>,
+}
+
+/// The `build-{toolchain}.json` reports of the reports folder; `build.json`
+/// is left out, it duplicates the report of the latest run.
+pub fn default_reports() -> io::Result<Vec<PathBuf>> {
+    let mut reports = fs::read_dir(report::reports_dir())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("build-") && name.ends_with(".json")
+        }).collect::<Vec<_>>();
+    reports.sort();
+    Ok(reports)
+}
+
+/// The toolchain a build entry of `report` was attempted with.
+fn toolchain(entry: &Entry, report: &Path) -> String {
+    entry
+        .details
+        .get("toolchain")
+        .and_then(|v| v.as_str())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| report.to_string_lossy().into_owned())
+}
+
+/// Merge build `reports` into the crates that failed in all attempts.
+pub fn collect(reports: &[PathBuf]) -> io::Result<Vec<Unbuildable>> {
+    let mut built = BTreeSet::new();
+    let mut failed: BTreeMap<(String, String), BTreeMap<String, String>> = BTreeMap::new();
+    for path in reports {
+        for entry in report::read(path)? {
+            let key = (entry.name.clone(), entry.version.clone());
+            match entry.status {
+                Status::Ok => {
+                    built.insert(key);
+                }
+                Status::Failed => {
+                    let category = entry
+                        .details
+                        .get("error_category")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("other")
+                        .to_string();
+                    failed
+                        .entry(key)
+                        .or_insert_with(BTreeMap::new)
+                        .insert(toolchain(&entry, path), category);
+                }
+                Status::Skipped => {}
+            }
+        }
+    }
+    Ok(failed
+        .into_iter()
+        .filter(|&(ref key, _)| !built.contains(key))
+        .map(|((name, version), attempts)| Unbuildable {
+            name,
+            version,
+            categories: attempts.values().cloned().collect(),
+            attempts,
+        }).collect())
+}
+
+/// The output the category of the failed build `entry` is taken from: the
+/// whole `build.log` of `--build-log` if there is one, else the tail kept in
+/// the report.
+fn failure_output(entry: &Entry) -> Option<String> {
+    let krate = PraziCrate {
+        name: entry.name.clone(),
+        version: entry.version.clone(),
+    };
+    if let Ok(log) = fs::read(krate.build_log()) {
+        return Some(String::from_utf8_lossy(&log).into_owned());
+    }
+    entry.details.get("stderr").and_then(|v| v.as_str()).map(|v| v.to_string())
+}
+
+/// Categorize the failed builds of the report `input` again with the current
+/// `CATEGORIES` and write it to `out`. Returns the number of entries
+/// categorized and of those whose category changed.
+pub fn reclassify(input: &Path, out: &Path) -> io::Result<(usize, usize)> {
+    let text = fs::read_to_string(input)?;
+    let mut envelope: Envelope<Vec<Entry>> = ::serde_json::from_str(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut categorized = 0;
+    let mut changed = 0;
+    for entry in envelope
+        .data
+        .iter_mut()
+        .filter(|entry| entry.phase == "build" && entry.status == Status::Failed)
+    {
+        let output = match failure_output(entry) {
+            Some(output) => output,
+            None => continue,
+        };
+        let before = entry.details.get("error_category").cloned();
+        categorize_entry(entry, &output);
+        categorized += 1;
+        if entry.details.get("error_category") != before.as_ref() {
+            changed += 1;
+        }
+    }
+    for entry in envelope.data.iter_mut() {
+        // the first failure of a flaky build only left its tail
+        let first = entry
+            .details
+            .get("first_stderr")
+            .and_then(|v| v.as_str())
+            .map(categorize);
+        if let Some(category) = first {
+            entry.details.insert("first_error_category".to_string(), category.into());
+        }
+    }
+    fs::write(out, Envelope::new(&envelope.kind, &envelope.data).to_json())?;
+    Ok((categorized, changed))
+}
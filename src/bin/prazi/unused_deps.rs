@@ -0,0 +1,149 @@
+// Declared dependencies without a call in the merged call graph
+//
+// (c) 2018 - onwards Joseph Hejderup <joseph.hejderup@gmail.com>
+//
+// MIT/APACHE licensed -- check LICENSE files in top dir
+//
+// Joins the dependencies an `export-index` file declares for every crate with
+// the inter-crate edges of a graph written by `merge-callgraphs`. Crates are
+// matched on the names and versions of the unique function identifiers, so
+// the graph has to be merged with `--node-id name-version`. Either input
+// may be `-`, read from stdin.
+use graph;
+use regex::Regex;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, BufRead};
+use std::path::Path;
+use stdio;
+
+/// A line of `export-index`, as far as needed here.
+#[derive(Debug, Deserialize)]
+struct ExportedVersion {
+    name: String,
+    version: String,
+    deps: Vec<ExportedDependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportedDependency {
+    name: String,
+    req: String,
+    optional: bool,
+    kind: Option<String>,
+}
+
+/// A declared dependency without calls.
+#[derive(Debug, Serialize)]
+pub struct Unused {
+    pub name: String,
+    pub req: String,
+    /// Optional dependencies are only compiled with the features enabling them.
+    pub optional: bool,
+}
+
+/// The declared dependencies of a crate of the graph and which of them it calls.
+#[derive(Debug, Serialize)]
+pub struct DeclaredDeps {
+    pub name: String,
+    pub version: String,
+    /// Declared dependencies the crate calls into.
+    pub called: BTreeSet<String>,
+    /// Declared dependencies the crate does not call into.
+    pub unused: Vec<Unused>,
+}
+
+fn edge_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r#"^\s*"(.*?)" -> "(.*?)"(?: \[caller="(.*?)", callee="(.*?)"\])?;$"#
+        ).unwrap();
+    }
+    &RE
+}
+
+fn node_regex() -> &'static Regex {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r#"^\s*"(.*?)"(?: \[.*\])?;$"#).unwrap();
+    }
+    &RE
+}
+
+/// `ufi` identifiers of name and version of the crate of `label`, or of the
+/// `name@version` of a `--boundary-only` edge attribute.
+fn crate_key(label: &str, attribute: Option<&str>) -> Option<(String, String)> {
+    match attribute {
+        Some(id) => {
+            let at = id.rfind('@')?;
+            Some((graph::ufi_ident(&id[..at]), graph::ufi_ident(&id[at + 1..])))
+        }
+        None => graph::ufi_crate(label).map(|(name, version)| (name.to_string(), version.to_string())),
+    }
+}
+
+/// The crates of the merged graph at `path`, with the names of the crates
+/// each of them calls into.
+fn calls(path: &Path) -> io::Result<HashMap<(String, String), HashSet<String>>> {
+    let mut calls: HashMap<(String, String), HashSet<String>> = HashMap::new();
+    for line in stdio::open(path)?.lines() {
+        let line = line?;
+        if let Some(caps) = edge_regex().captures(&line) {
+            let caller = crate_key(&caps[1], caps.get(3).map(|m| m.as_str()));
+            let callee = crate_key(&caps[2], caps.get(4).map(|m| m.as_str()));
+            if let (Some(caller), Some(callee)) = (caller, callee) {
+                calls.entry(callee.clone()).or_insert_with(HashSet::new);
+                if caller != callee {
+                    calls.entry(caller).or_insert_with(HashSet::new).insert(callee.0);
+                }
+            }
+        } else if let Some(caps) = node_regex().captures(&line) {
+            if let Some(key) = crate_key(&caps[1], None) {
+                calls.entry(key).or_insert_with(HashSet::new);
+            }
+        }
+    }
+    Ok(calls)
+}
+
+/// The declared normal dependencies of every crate version of `deps`, an
+/// `export-index` file, that is in the merged graph at `graph`. Crates absent
+/// from the graph are left out, with `--boundary-only` these include the
+/// crates without any inter-crate edge.
+pub fn compare(deps: &Path, graph: &Path) -> io::Result<Vec<DeclaredDeps>> {
+    let calls = calls(graph)?;
+    let mut res = Vec::new();
+    for line in stdio::open(deps)?.lines() {
+        let line = line?;
+        let version: ExportedVersion = ::serde_json::from_str(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let key = (graph::ufi_ident(&version.name), graph::ufi_ident(&version.version));
+        let callees = match calls.get(&key) {
+            Some(callees) => callees,
+            None => continue,
+        };
+        let mut called = BTreeSet::new();
+        let mut unused = Vec::new();
+        // dev and build dependencies do not end up in the crate's call graph
+        for dep in version.deps {
+            match dep.kind.as_ref().map(|k| k.as_str()) {
+                None | Some("normal") => {}
+                _ => continue,
+            }
+            if callees.contains(&graph::ufi_ident(&dep.name)) {
+                called.insert(dep.name);
+            } else {
+                unused.push(Unused {
+                    name: dep.name,
+                    req: dep.req,
+                    optional: dep.optional,
+                });
+            }
+        }
+        res.push(DeclaredDeps {
+            name: version.name,
+            version: version.version,
+            called,
+            unused,
+        });
+    }
+    Ok(res)
+}
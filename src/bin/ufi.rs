@@ -15,6 +15,12 @@ extern crate serde_json;
 #[macro_use]
 extern crate serde_derive;
 extern crate ini;
+extern crate toml;
+
+// the configuration of `prazi`, from `conf.toml`, `conf.json` or `conf.ini`
+#[allow(dead_code)]
+#[path = "prazi/config.rs"]
+mod config;
 
 use cargo::core::resolver::Resolve;
 use cargo::core::Package;
@@ -22,7 +28,6 @@ use cargo::core::Workspace;
 use cargo::ops::load_pkg_lockfile;
 use cargo::util::Config;
 use filebuffer::FileBuffer;
-use ini::Ini;
 use quote::ToTokens;
 use regex::Regex;
 use std::collections::HashMap;
@@ -35,19 +40,14 @@ use std::str;
 use syn::{Ident, PathSegment};
 
 lazy_static! {
-    static ref CONFIG: Ini = {
+    static ref CONFIG: config::Config = {
         let dir = env!("CARGO_MANIFEST_DIR");
-        let conf = Ini::load_from_file(format!("{0}/{1}", dir, "conf.ini")).unwrap();
-        conf
-    };
-    static ref PRAZI_DIR: String = {
-        CONFIG
-            .section(Some("storage"))
-            .unwrap()
-            .get("path")
-            .unwrap()
-            .to_string()
+        config::Config::load(Path::new(dir)).unwrap_or_else(|error| panic!("Error reading the configuration: {}", error))
     };
+    static ref PRAZI_DIR: String = CONFIG
+        .value("storage", "path")
+        .expect("no [storage] path configured")
+        .to_string();
 }
 
 fn is_a_node(text: &str) -> bool {
@@ -80,9 +80,9 @@ fn read_ws(path: &str) -> Result<(Package, Resolve), cargo::CargoError> {
     Ok((pkg.clone(), lock_file.unwrap()))
 }
 
-fn read_lib_name_from_path(path: &str) -> Result<String, cargo::CargoError> {
+fn read_lib_name_from_path(path: &Path) -> Result<String, cargo::CargoError> {
     let config = Config::default().expect("Should have config file");
-    let ws = Workspace::new(Path::new(path), &config)?;
+    let ws = Workspace::new(path, &config)?;
     let targets = ws
         .current()
         .unwrap()
@@ -145,13 +145,13 @@ fn fetch_deps(
         lock_file
             .iter()
             .map(|dep| {
-                let cargo_toml_path = format!(
-                    "{0}/crates/reg/{1}/{2}/Cargo.toml",
-                    &**PRAZI_DIR,
-                    dep.name(),
-                    dep.version()
-                );
-                let lib_name = read_lib_name_from_path(cargo_toml_path.as_str());
+                let cargo_toml_path = Path::new(&**PRAZI_DIR)
+                    .join("crates")
+                    .join("reg")
+                    .join(dep.name().to_string())
+                    .join(dep.version().to_string())
+                    .join("Cargo.toml");
+                let lib_name = read_lib_name_from_path(&cargo_toml_path);
                 if let Err(e) = lib_name {
                     Err(e)
                 } else {